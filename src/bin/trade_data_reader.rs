@@ -39,7 +39,12 @@ use feed_parser::nyse::base_funcs::{NYSEMsg, Stats};
 use thousands::Separable;
 use priority_queue::DoublePriorityQueue;
 use walkdir::WalkDir;
-use feed_parser::graphics::test_plot1::{test_plot_003, test_plot_004, test_power_spec_graph, test_spectral_density_graph};
+use feed_parser::graphics::test_plot1::{test_market_breadth_graph, test_plot_003, test_plot_004, test_power_spec_graph, test_spectral_density_graph};
+use feed_parser::math_funcs::blocks::{BlockCriteria, BlockReport};
+use feed_parser::math_funcs::cross_section::CrossSection;
+use feed_parser::math_funcs::liquidity::LiquidityStats;
+use feed_parser::math_funcs::diagnostics::{event_stats_diagnostics, get_most_mean_reverting, get_most_trending};
+use feed_parser::math_funcs::market::market_breadth;
 use feed_parser::math_funcs::pre_processing::{diff_series, freq_counter, gen_price_with_fft, huff_code};
 use feed_parser::general::parsing;
 use feed_parser::event_structs::EventList;
@@ -57,14 +62,16 @@ fn main() {
 
 
 fn proc_dta(input_file: String) {
+    // output files are tagged with the input file name, the data directory holds one file per day
+    let day = Path::new(&input_file).file_stem().map_or(String::from("unknown"), |s| s.to_string_lossy().to_string());
     if let Ok(mut dta) = parsing::proc_file(&input_file) {
-        evaluate_trades(&mut dta);
-        dump_stats(&mut dta);
+        evaluate_trades(&day, &dta);
+        dump_stats(&day, &mut dta);
 
     }
 }
 
-fn dump_stats(stats: &mut Stats) {
+fn dump_stats(day: &str, stats: &mut Stats) {
     println!("Symbol Index Mapping Messages {}", stats.msg_stats.msg_count[&NYSEMsg::T003].separate_with_commas());
     println!("Symbol Security Status Message {}", stats.msg_stats.msg_count[&NYSEMsg::T034].separate_with_commas());
     println!("Trade Messages {}", stats.msg_stats.msg_count[&NYSEMsg::T220].separate_with_commas());
@@ -73,6 +80,18 @@ fn dump_stats(stats: &mut Stats) {
     println!("Trade Message details: average_rate {}/second ", stats.trade_stats.get_average_rate().separate_with_commas());
     println!("50 Most Active Symbols: {:?} ", stats.symbol_stats.get_most_active(50)); //wish this was not mutable
     println!("50 Highest Volume Symbols: {:?} ", stats.symbol_stats.get_highest_volume(50));
+    let mut liquidity_stats = LiquidityStats::new();
+    liquidity_stats.update(&stats.event_stats, 60, 5, 20, 500);
+    println!("50 Highest Kyle Lambda Symbols: {:?} ", liquidity_stats.get_highest_lambda(50));
+    println!("50 Most Illiquid (Amihud) Symbols: {:?} ", liquidity_stats.get_most_illiquid(50));
+    println!("50 Highest Permanent Impact Symbols: {:?} ", liquidity_stats.get_highest_impact(50));
+    if let Ok(breadth) = market_breadth(&stats.event_stats, &stats.reference_stats, 60) {
+        _ = breadth.write_csv(&format!("plots/{}-market-breadth.csv", day));
+        _ = test_market_breadth_graph(day, &breadth);
+    }
+    if let Ok(blocks) = BlockReport::new(&stats.event_stats, &BlockCriteria::default(), &[10.0, 60.0, 300.0]) {
+        println!("Block trades {} , 50 Symbols with most blocks: {:?} ", blocks.total_blocks(), blocks.get_most_blocks(50));
+    }
 
     // println!("{} Activity: {:?}","TSLA",stats.event_stats.symbol_events.get("TSLA").unwrap().get_full_time_series());
 
@@ -88,7 +107,7 @@ fn dump_stats(stats: &mut Stats) {
     }
 }
 
-fn  evaluate_trades(stats: &Stats){
+fn  evaluate_trades(day: &str, stats: &Stats){
     let  mut greatest_p_variance:DoublePriorityQueue<String, i32> = DoublePriorityQueue::new();
     let  mut freq_map:HashMap<String,HashMap<i32,u32>>= HashMap::new();
 
@@ -115,8 +134,21 @@ fn  evaluate_trades(stats: &Stats){
         }
     }
 
+    let  diagnostics = event_stats_diagnostics(&stats.event_stats, 500, 10, &[2, 5, 10, 20]);
+    for (symbol, hurst) in get_most_mean_reverting(&diagnostics, 10) {
+        let  diag = &diagnostics[&symbol];
+        println!("mean reverting {} hurst {:.3} acf(1) {:.3} ljung-box p {:.4}", symbol, hurst, diag.return_acf[1], diag.return_ljung_box.p_value);
+    }
+    for (symbol, hurst) in get_most_trending(&diagnostics, 10) {
+        let  diag = &diagnostics[&symbol];
+        println!("trending {} hurst {:.3} acf(1) {:.3} ljung-box p {:.4}", symbol, hurst, diag.return_acf[1], diag.return_ljung_box.p_value);
+    }
 
-
+    if let Ok(cross_section) = CrossSection::new(&stats.event_stats, 50, 60, 500) {
+        println!("cross section of {} symbols, ledoit-wolf shrinkage {:.3}, explained by first 5 components {:?}",
+                 cross_section.panel.symbols.len(), cross_section.shrinkage, &cross_section.pca.explained[..5.min(cross_section.pca.explained.len())]);
+        _ = cross_section.write_loadings(&format!("plots/{}-loadings.csv", day), 5);
+    }
 }
 
 
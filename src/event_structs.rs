@@ -33,6 +33,7 @@
 
 pub const EXPECTED_TICS: usize = 16;
 
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 use rust_decimal::prelude::*;
 use rust_decimal_macros::dec;
@@ -48,6 +49,7 @@ use crate::time_funcs::s2hhmmss_32;
 /// - `seconds`: The time of the event in seconds.
 /// - `price`: The price at which the event occurred.
 /// - `volume`: The volume of the event.
+/// - `side`: Inferred aggressor side, `Unknown` until `EventList::classify_trades` is run.
 #[derive(Debug, Clone, PartialEq, Hash)]
 pub struct MuEvent {
     pub string_time: String,
@@ -55,6 +57,36 @@ pub struct MuEvent {
     pub seconds: Decimal,
     pub price: Decimal,
     pub volume: i32,
+    pub side: TradeSide,
+}
+
+/// Aggressor side of a trade, i.e. whether the buyer or the seller crossed the spread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TradeSide {
+    Buy,
+    Sell,
+    Unknown,
+}
+
+impl TradeSide {
+    /// +1 for buys, -1 for sells, 0 when unclassified.
+    pub fn sign(&self) -> i32 {
+        match self {
+            TradeSide::Buy => 1,
+            TradeSide::Sell => -1,
+            TradeSide::Unknown => 0,
+        }
+    }
+}
+
+/// Trade classification rules for data without quotes.
+///
+/// - `Tick`: Buy on an uptick from the previous different price, sell on a downtick.
+/// - `ReverseTick`: Buy when the next different price is lower, sell when it is higher.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TickRule {
+    Tick,
+    ReverseTick,
 }
 
 /// `Event` is a collection of `MuEvent`s and associated metadata.
@@ -76,6 +108,24 @@ pub struct Event {
     pub min_volume: i32,
 }
 
+/// `Bar` is an OHLC summary of the trades falling in one fixed interval.
+///
+/// It consists of the following fields:
+/// - `start`: Start of the interval in seconds after midnight.
+/// - `open`, `high`, `low`, `close`: First, highest, lowest and last trade price.
+/// - `volume`: Total volume traded in the interval.
+/// - `tic_count`: Number of trades in the interval.
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct Bar {
+    pub start: i32,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub volume: i32,
+    pub tic_count: i32,
+}
+
 /// `EventList` holds a map of `Event`s with the key being the time in seconds of the event.
 ///
 /// It consists of the following fields:
@@ -250,6 +300,109 @@ impl EventList {
         }
         time_series
     }
+
+    /// Set the `side` of every tic using `rule`.
+    /// Zero ticks inherit the side of the last price change; tics before the first change
+    /// (or after the last one for the reverse rule) stay `Unknown`.
+    pub fn classify_trades(&mut self, rule: TickRule) {
+        let mut tics: Vec<&mut MuEvent> = self.events.values_mut().flat_map(|e| e.tics.iter_mut()).collect();
+        if rule == TickRule::ReverseTick {
+            tics.reverse();
+        }
+        let mut last_price: Option<Decimal> = None;
+        let mut side = TradeSide::Unknown;
+        for tic in tics.iter_mut() {
+            if let Some(prev) = last_price {
+                // going backwards a higher price than the next trade is an uptick for the reverse rule
+                if tic.price > prev {
+                    side = TradeSide::Buy;
+                } else if tic.price < prev {
+                    side = TradeSide::Sell;
+                }
+            }
+            tic.side = side;
+            last_price = Some(tic.price);
+        }
+    }
+
+    /// True once any tic carries a `Buy` or `Sell` side.
+    pub fn has_trade_sides(&self) -> bool {
+        self.events.values().any(|e| e.tics.iter().any(|t| t.side != TradeSide::Unknown))
+    }
+
+    /// The list itself if its tics already carry sides, else a tick-rule classified copy.
+    pub fn with_trade_sides(&self) -> Cow<'_, EventList> {
+        if self.has_trade_sides() {
+            Cow::Borrowed(self)
+        } else {
+            let mut copy = self.clone();
+            copy.classify_trades(TickRule::Tick);
+            Cow::Owned(copy)
+        }
+    }
+
+    /// Generate a time series of tics as (seconds, signed volume), positive for buys.
+    pub fn get_signed_volume_series(&self) -> Vec<(Decimal, i32)> {
+        let mut time_series: Vec<(Decimal, i32)> = Vec::with_capacity(self.get_event_count());
+        for (_, event) in self.events.iter() {
+            for tic in event.tics.iter() {
+                time_series.push((tic.seconds, tic.side.sign() * tic.volume));
+            }
+        }
+        time_series
+    }
+
+    /// Generate a time series of `Event`s as a vector of tuples (idx, buy volume, sell volume).
+    pub fn get_sec_buy_sell_volume(&self) -> Vec<(Decimal, i32, i32)> {
+        let mut time_series: Vec<(Decimal, i32, i32)> = Vec::with_capacity(self.events.len());
+        for (idx, event) in self.events.iter() {
+            let (buy, sell) = event.get_buy_sell_volume();
+            time_series.push((Decimal::new(*idx as i64, 0), buy, sell));
+        }
+        time_series
+    }
+
+    pub fn get_sec_buy_sell_volume_s(&self) -> Vec<(String, i32, i32)> {
+        let mut time_series: Vec<(String, i32, i32)> = Vec::with_capacity(self.events.len());
+        for (idx, event) in self.events.iter() {
+            let (buy, sell) = event.get_buy_sell_volume();
+            time_series.push((s2hhmmss_32(*idx), buy, sell));
+        }
+        time_series
+    }
+
+    /// Aggregate the tics into OHLC bars of `interval` seconds, aligned to multiples of `interval`.
+    /// Intervals without trades produce no bar.
+    pub fn get_bars(&self, interval: i32) -> Vec<Bar> {
+        let mut bars: Vec<Bar> = Vec::new();
+        if interval <= 0 {
+            return bars;
+        }
+        for (idx, event) in self.events.iter() {
+            let start = idx.div_euclid(interval) * interval;
+            for tic in event.tics.iter() {
+                match bars.last_mut() {
+                    Some(bar) if bar.start == start => {
+                        bar.high = bar.high.max(tic.price);
+                        bar.low = bar.low.min(tic.price);
+                        bar.close = tic.price;
+                        bar.volume += tic.volume;
+                        bar.tic_count += 1;
+                    }
+                    _ => bars.push(Bar {
+                        start,
+                        open: tic.price,
+                        high: tic.price,
+                        low: tic.price,
+                        close: tic.price,
+                        volume: tic.volume,
+                        tic_count: 1,
+                    }),
+                }
+            }
+        }
+        bars
+    }
 }
 
 
@@ -257,7 +410,7 @@ impl Event {
     /// Create a new `Event` with an initial `MuEvent`.
     pub fn new(price: Decimal, volume: i32, seconds: Decimal, string_time: String) -> Event {
         let mut initial_tic = Vec::with_capacity(EXPECTED_TICS);
-        initial_tic.push(MuEvent { string_time, seconds: seconds, price, volume });
+        initial_tic.push(MuEvent { string_time, seconds, price, volume, side: TradeSide::Unknown });
         Event {
             tics: initial_tic,
             total_price: price,
@@ -277,7 +430,7 @@ impl Event {
         self.total_price += price;
         self.total_volume += volume;
         self.tic_count += 1;
-        self.tics.push(MuEvent { string_time, seconds: u_sec, price, volume });
+        self.tics.push(MuEvent { string_time, seconds: u_sec, price, volume, side: TradeSide::Unknown });
         if price < self.min_price {
             self.min_price = price;
         }
@@ -311,11 +464,39 @@ impl Event {
     pub fn get_tic_count(&self) -> i32 {
         self.tic_count
     }
+
+    /// Total (buy, sell) volume of the classified tics; unclassified volume is in neither.
+    pub fn get_buy_sell_volume(&self) -> (i32, i32) {
+        let mut buy = 0;
+        let mut sell = 0;
+        for tic in self.tics.iter() {
+            match tic.side {
+                TradeSide::Buy => buy += tic.volume,
+                TradeSide::Sell => sell += tic.volume,
+                TradeSide::Unknown => {}
+            }
+        }
+        (buy, sell)
+    }
+}
+
+/// Test fixture: an `EventList` with one tic per `(seconds after midnight, price, volume)`,
+/// fractional seconds are kept to the nanosecond.
+#[cfg(test)]
+pub(crate) fn test_event_list<T: Into<f64> + Copy, P: AsRef<str>>(tics: &[(T, P, i32)]) -> EventList {
+    let mut el = EventList::new();
+    for (secs, price, volume) in tics {
+        let nanos = ((*secs).into() * 1e9).round() as i64;
+        let time = format!("{}.{:09}", s2hhmmss_32((nanos / 1_000_000_000) as i32), nanos % 1_000_000_000);
+        el.update(&time, price.as_ref(), *volume).unwrap();
+    }
+    el
 }
 
 #[cfg(test)]
 mod test {
-    use crate::event_structs::EventList;
+    use crate::event_structs::{Bar, EventList, TickRule, TradeSide};
+    use std::borrow::Cow;
     use rust_decimal::prelude::*;
     use rust_decimal_macros::dec;
 
@@ -503,4 +684,55 @@ mod test {
         assert_eq!(min_vol, 10);
         assert_eq!(max_vol, 20);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_get_bars() {
+        let mut el = EventList::new();
+        let _ = el.update("09:30:00.100000000", "10.00", 100);
+        let _ = el.update("09:30:20.100000000", "10.05", 200);
+        let _ = el.update("09:30:59.900000000", "9.98", 100);
+        let _ = el.update("09:30:59.950000000", "10.01", 300);
+        let _ = el.update("09:32:10.000000000", "10.10", 50);
+        let bars = el.get_bars(60);
+        assert_eq!(bars, vec![
+            Bar { start: 34200, open: dec!(10.00), high: dec!(10.05), low: dec!(9.98), close: dec!(10.01), volume: 700, tic_count: 4 },
+            Bar { start: 34320, open: dec!(10.10), high: dec!(10.10), low: dec!(10.10), close: dec!(10.10), volume: 50, tic_count: 1 },
+        ]);
+        assert_eq!(el.get_bars(1).len(), 4);
+        assert!(el.get_bars(0).is_empty());
+    }
+
+    #[test]
+    fn test_classify_trades() {
+        let mut el = EventList::new();
+        let _ = el.update("09:30:00.100000000", "10.00", 100);
+        let _ = el.update("09:30:00.200000000", "10.01", 200);
+        let _ = el.update("09:30:00.300000000", "10.01", 300);
+        let _ = el.update("09:30:01.100000000", "10.00", 400);
+        let _ = el.update("09:30:02.100000000", "10.00", 500);
+        el.classify_trades(TickRule::Tick);
+        let sides: Vec<TradeSide> = el.events.values().flat_map(|e| e.tics.iter().map(|t| t.side)).collect();
+        assert_eq!(sides, vec![TradeSide::Unknown, TradeSide::Buy, TradeSide::Buy, TradeSide::Sell, TradeSide::Sell]);
+        let signed: Vec<i32> = el.get_signed_volume_series().iter().map(|(_, v)| *v).collect();
+        assert_eq!(signed, vec![0, 200, 300, -400, -500]);
+        assert_eq!(el.get_sec_buy_sell_volume(), vec![
+            (dec!(34200), 500, 0), (dec!(34201), 0, 400), (dec!(34202), 0, 500)]);
+        assert_eq!(el.get_sec_buy_sell_volume_s()[1], ("09:30:01".to_string(), 0, 400));
+
+        el.classify_trades(TickRule::ReverseTick);
+        let sides: Vec<TradeSide> = el.events.values().flat_map(|e| e.tics.iter().map(|t| t.side)).collect();
+        assert_eq!(sides, vec![TradeSide::Sell, TradeSide::Buy, TradeSide::Buy, TradeSide::Unknown, TradeSide::Unknown]);
+        assert!(matches!(el.with_trade_sides(), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_with_trade_sides() {
+        let mut el = EventList::new();
+        let _ = el.update("09:30:00.100000000", "10.00", 100);
+        let _ = el.update("09:30:00.200000000", "9.99", 200);
+        let classified = el.with_trade_sides();
+        assert!(matches!(classified, Cow::Owned(_)));
+        assert!(classified.has_trade_sides());
+        assert!(!el.has_trade_sides());
+    }
+}
@@ -32,6 +32,8 @@
 use indicatif::ProgressBar;
 use std::io::{self,BufRead};
 use crate::nyse::mt220::{T220, Tc2, Tc4};
+use crate::nyse::mt3::T3;
+use crate::nyse::mt34::T34;
 use crate::nyse::base_funcs::{NYSEMsg, Stats};
 use std::path::Path;
 use std::fs::File;
@@ -103,6 +105,16 @@ pub fn proc_file(data_file: &str) -> Result<Stats, Box<dyn std::error::Error>> {
     Result::Ok(stats)
 }
 
+/// Like `proc_file`, but for library callers: an unreadable file or a line that cannot be
+/// processed is returned as an error instead of ending the process.
+pub fn try_proc_file(data_file: &str) -> Result<Stats, Box<dyn Error>> {
+    let mut stats: Stats = Stats::new();
+    for (n, line) in read_lines(data_file)?.enumerate() {
+        process_line(line?, &mut stats).map_err(|e| format!("{} line {}: {}", data_file, n + 1, e))?;
+    }
+    Ok(stats)
+}
+
 
 fn process_line(line: String, stats: &mut Stats) -> Result<(), Box<dyn Error>> {
     let tokens: Vec<String> = line.split(',')
@@ -116,9 +128,22 @@ fn process_line(line: String, stats: &mut Stats) -> Result<(), Box<dyn Error>> {
         NYSEMsg::T003 => {
             let symbol: &String = &tokens[SYMBOL_IDX];
             stats.event_stats.init(&symbol.clone());
-            Ok(stats.symbol_stats.add(&symbol))
+            stats.symbol_stats.add(symbol);
+            match T3::new(tokens) {
+                Ok(mapping) => stats.reference_stats.add(&mapping),
+                Err(e) => {
+                    println!("Skipping symbol mapping line: {}", e);
+                    stats.reference_stats.malformed += 1;
+                }
+            }
+            Ok(())
         }
         NYSEMsg::T034 => {
+            // a bad status line must not abort the day, the halt periods are only used for annotation
+            if let Err(e) = T34::new(tokens).and_then(|status| stats.halt_stats.add(&status)) {
+                println!("Skipping security status line: {}", e);
+                stats.halt_stats.malformed += 1;
+            }
             Ok(())
         }
         NYSEMsg::T220 => {
@@ -34,14 +34,24 @@ use std::fmt;
 use plotly::{
     color::{Rgba},
     common::{
- Title,AxisSide
+ Title,AxisSide,Marker,Mode,Orientation,TextPosition
     },
-    layout::{Axis,  Layout, RangeSlider},
-    Plot,  Scatter};
+    layout::{Axis, AxisType, BarMode, Layout, RangeSlider, Shape, ShapeLayer, ShapeLine, ShapeType},
+    Bar, HeatMap, Plot,  Scatter};
 
 use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
 use welch_sde::{Build, SpectralDensity};
-use crate::math_funcs::pre_processing::{detrend, power_spectrum, sd_graph};
+use crate::event_structs::EventList;
+use crate::math_funcs::pre_processing::{detrend, power_spectrum, resample_event_list, sd_graph, SeriesKind};
+use crate::math_funcs::hayashi_yoshida::CovarianceSignature;
+use crate::math_funcs::hmm::Regimes;
+use crate::math_funcs::market::MarketBreadth;
+use crate::math_funcs::market_profile::{TpoProfile, VolumeProfile};
+use crate::math_funcs::spectral::{event_list_cross_spectrum, spectrogram};
+use crate::math_funcs::state_space::LocalLevelFit;
+use crate::math_funcs::vpin::VpinSeries;
+use crate::math_funcs::wavelets::cwt_morlet;
 use crate::time_funcs::s2hhmmss_32;
 
 
@@ -367,6 +377,497 @@ pub fn test_spectral_density_graph(ticker:&str, time_series: Vec<(String, f32, i
 }
 
 
+// spectrogram heatmap of a resampled series, time on x, frequency on y and log10 power as colour
+pub fn test_spectrogram_graph(ticker:&str, event_list:&EventList, kind:SeriesKind, interval:i32, window:usize, hop:usize) -> Result<(), Box<dyn Error>> {
+    let start = match event_list.events.keys().next() {
+        Some(sec) => *sec,
+        None => return Ok(()),
+    };
+    let series = resample_event_list(event_list, interval, kind);
+    if series.len() < window {
+        return Ok(());
+    }
+    let spec = spectrogram(&series, 1.0 / interval as f32, window, hop)?;
+
+    // returns are one grid point shorter and start at the second grid point
+    let offset = if kind == SeriesKind::Return { interval } else { 0 };
+    let time_line: Vec<String> = spec.times.iter().map(|t| s2hhmmss_32(start + offset + *t as i32)).collect();
+    let z: Vec<Vec<f32>> = (0..spec.frequencies.len())
+        .map(|f| spec.power.iter().map(|row| (row[f] + f32::EPSILON).log10()).collect())
+        .collect();
+
+    let trace1 = HeatMap::new(time_line, spec.frequencies.clone(), z).name("log10 power");
+    let mut plot = Plot::new();
+    plot.add_trace(trace1);
+
+    let mut title = String::new();
+    let mut file_name = String::new();
+
+    fmt::write(&mut title, format_args!("{} {:?} Spectrogram interval:{}s window:{} hop:{}",ticker,kind,interval,window,hop)).unwrap();
+    fmt::write(&mut file_name, format_args!("plots/{}-Spectrogram.html",ticker)).unwrap();
+    let layout = Layout::new()
+        .height(2200)
+        .width(4200)
+        .x_axis(
+            Axis::new()
+                .title("time".into())
+                .range_slider(RangeSlider::new().visible(true))
+
+        ).title(Title::new(&title))
+        .y_axis(Axis::new().title("frequency [Hz]".into())
+            .side(AxisSide::Left)
+        );
+    plot.set_layout(layout);
+    plot.use_local_plotly();
+    println!("writing file {}", &file_name);
+    plot.write_html(&file_name);
+    Ok(())
+}
+
+// coherence between two (ticker, events) pairs with its confidence band and the zero coherence threshold, phase lag on y2
+pub fn test_coherence_graph(symbol_a:(&str, &EventList), symbol_b:(&str, &EventList), kind:SeriesKind,
+                            interval:i32, window:usize, hop:usize) -> Result<(), Box<dyn Error>> {
+    let (ticker_a, event_list_a) = symbol_a;
+    let (ticker_b, event_list_b) = symbol_b;
+    let cs = event_list_cross_spectrum(event_list_a, event_list_b, kind, interval, window, hop, 0.95)?;
+    let freq_line: Vec<f32> = cs.frequencies.clone();
+    let threshold_line: Vec<f32> = vec![cs.coherence_threshold; freq_line.len()];
+
+    let trace1 = Scatter::new(freq_line.clone(), cs.coherence.clone()).name("coherence");
+    let trace2 = Scatter::new(freq_line.clone(), cs.coherence_lower.clone()).name("coherence lower 95%");
+    let trace3 = Scatter::new(freq_line.clone(), cs.coherence_upper.clone()).name("coherence upper 95%");
+    let trace4 = Scatter::new(freq_line.clone(), threshold_line).name("zero coherence threshold");
+    let trace5 = Scatter::new(freq_line.clone(), cs.phase_lag.clone()).name("phase lag [s]").y_axis("y2");
+    let mut plot = Plot::new();
+    plot.add_trace(trace1);
+    plot.add_trace(trace2);
+    plot.add_trace(trace3);
+    plot.add_trace(trace4);
+    plot.add_trace(trace5);
+
+    let mut title = String::new();
+    let mut file_name = String::new();
+
+    fmt::write(&mut title, format_args!("{} vs {} {:?} coherence interval:{}s segments:{}",ticker_a,ticker_b,kind,interval,cs.segments)).unwrap();
+    fmt::write(&mut file_name, format_args!("plots/{}-{}-Coherence.html",ticker_a,ticker_b)).unwrap();
+    let layout = Layout::new()
+        .height(2200)
+        .width(4200)
+        .x_axis(
+            Axis::new()
+                .title("frequency [Hz]".into())
+                .grid_color(Rgba::new(255, 255, 255, 1.0))
+        ).title(Title::new(&title))
+        .y_axis(Axis::new().title("coherence".into())
+            .grid_color(Rgba::new(255, 255, 255, 0.25))
+            .side(AxisSide::Left)
+        )
+        .y_axis2(Axis::new().title("phase lag [s]".into())
+            .grid_color(Rgba::new(255, 0, 0, 0.25))
+            .overlaying("y")
+            .side(AxisSide::Right)
+        );
+    plot.set_layout(layout);
+    plot.use_local_plotly();
+    println!("writing file {}", &file_name);
+    plot.write_html(&file_name);
+    Ok(())
+}
+
+// Morlet scalogram of a resampled series, time on x, Fourier period on y and log10 power as colour
+pub fn test_scalogram_graph(ticker:&str, event_list:&EventList, kind:SeriesKind, interval:i32, scales_per_octave:usize, octaves:usize) -> Result<(), Box<dyn Error>> {
+    let start = match event_list.events.keys().next() {
+        Some(sec) => *sec,
+        None => return Ok(()),
+    };
+    let series = resample_event_list(event_list, interval, kind);
+    if series.len() < 2 {
+        return Ok(());
+    }
+    let scalogram = cwt_morlet(&series, interval as f32, scales_per_octave, octaves)?;
+
+    let offset = if kind == SeriesKind::Return { interval } else { 0 };
+    let time_line: Vec<String> = (0..series.len()).map(|i| s2hhmmss_32(start + offset + i as i32 * interval)).collect();
+    let z: Vec<Vec<f32>> = scalogram.power.iter()
+        .map(|row| row.iter().map(|p| (p + f32::EPSILON).log10()).collect())
+        .collect();
+
+    let trace1 = HeatMap::new(time_line, scalogram.periods.clone(), z).name("log10 power");
+    let mut plot = Plot::new();
+    plot.add_trace(trace1);
+
+    let mut title = String::new();
+    let mut file_name = String::new();
+
+    fmt::write(&mut title, format_args!("{} {:?} Morlet scalogram interval:{}s",ticker,kind,interval)).unwrap();
+    fmt::write(&mut file_name, format_args!("plots/{}-Scalogram.html",ticker)).unwrap();
+    let layout = Layout::new()
+        .height(2200)
+        .width(4200)
+        .x_axis(
+            Axis::new()
+                .title("time".into())
+                .range_slider(RangeSlider::new().visible(true))
+
+        ).title(Title::new(&title))
+        .y_axis(Axis::new().title("period [s]".into())
+            .type_(AxisType::Log)
+            .side(AxisSide::Left)
+        );
+    plot.set_layout(layout);
+    plot.use_local_plotly();
+    println!("writing file {}", &file_name);
+    plot.write_html(&file_name);
+    Ok(())
+}
+
+// per second price as in test_plot_003 with rolling VPIN on the right axis and markers where its CDF crosses `threshold`
+pub fn test_vpin_graph(ticker:&str, event_list:&EventList, vpin:&VpinSeries, threshold:f32) -> Result<(), Box<dyn Error>> {
+    let mut time_line: Vec<String> = Vec::new();
+    let mut price_line: Vec<f32> = Vec::new();
+    for (a, b, _) in event_list.get_sec_avg_time_series_s() {
+        time_line.push(a);
+        price_line.push(b);
+    }
+    // buckets close on bar starts, snap them to the last traded second so both traces share the x categories
+    let snap = |t: &i32| -> String {
+        match event_list.events.range(..=*t).next_back() {
+            Some((sec, _)) => s2hhmmss_32(*sec),
+            None => s2hhmmss_32(*t),
+        }
+    };
+    let vpin_time: Vec<String> = vpin.times.iter().map(snap).collect();
+    let alerts = vpin.alerts(threshold);
+    let alert_time: Vec<String> = alerts.iter().map(|(t, _)| snap(t)).collect();
+    let alert_vpin: Vec<f32> = alerts.iter().map(|(_, v)| *v).collect();
+
+    let trace1 = Scatter::new(time_line.clone(), price_line).name("price");
+    let trace2 = Scatter::new(vpin_time, vpin.vpin.clone()).name("VPIN").y_axis("y2");
+    let trace3 = Scatter::new(alert_time, alert_vpin).name("alert").mode(Mode::Markers).y_axis("y2");
+    let mut plot = Plot::new();
+    plot.add_trace(trace1);
+    plot.add_trace(trace2);
+    plot.add_trace(trace3);
+
+    let mut title = String::new();
+    let mut file_name = String::new();
+
+    fmt::write(&mut title, format_args!("{} VPIN window:{} buckets of {:.0} shares, alert CDF >= {}",ticker,vpin.window,vpin.bucket_volume,threshold)).unwrap();
+    fmt::write(&mut file_name, format_args!("plots/{}-VPIN.html",ticker)).unwrap();
+    let layout = Layout::new()
+        .height(2200)
+        .width(4200)
+        .x_axis(
+            Axis::new()
+                .grid_color(Rgba::new(255, 255, 255, 1.0))
+                .range_slider(RangeSlider::new().visible(true))
+
+        ).title(Title::new(&title))
+        .y_axis(Axis::new().title("price".into())
+            .grid_color(Rgba::new(255, 255, 255, 0.25))
+            .side(AxisSide::Left)
+        )
+        .y_axis2(Axis::new().title("VPIN".into())
+            .grid_color(Rgba::new(255, 0, 0, 0.25))
+            .overlaying("y")
+            .range(vec![0.0, 1.0])
+            .side(AxisSide::Right)
+        );
+    plot.set_layout(layout);
+    plot.use_local_plotly();
+    println!("writing file {}", &file_name);
+    plot.write_html(&file_name);
+    Ok(())
+}
+
+/// Raw trades with the Kalman filtered and RTS smoothed efficient price overlaid, as in `test_plot_003`.
+/// `time_series` is `get_full_time_series_s` of the list `fit` was estimated on.
+pub fn test_efficient_price_graph(ticker:&str, time_series: Vec<(String, f32, i32)>, fit:&LocalLevelFit) -> Result<(), Box<dyn Error>> {
+    if time_series.len() != fit.smoothed.len() {
+        return Err(format!("{} trades but {} smoothed prices", time_series.len(), fit.smoothed.len()).into());
+    }
+    let mut time_line: Vec<String> = Vec::with_capacity(time_series.len());
+    let mut price_line: Vec<f32> = Vec::with_capacity(time_series.len());
+    let mut volume_line: Vec<i32> = Vec::with_capacity(time_series.len());
+    for (a, b, c) in time_series {
+        time_line.push(a);
+        price_line.push(b);
+        volume_line.push(c);
+    }
+    let trace1 = Scatter::new(time_line.clone(), price_line).name("price").mode(Mode::Markers);
+    let trace2 = Scatter::new(time_line.clone(), fit.filtered.clone()).name("filtered");
+    let trace3 = Scatter::new(time_line.clone(), fit.smoothed.clone()).name("smoothed");
+    let trace4 = Scatter::new(time_line.clone(), volume_line).name("volume").y_axis("y2");
+    let mut plot = Plot::new();
+    plot.add_trace(trace1);
+    plot.add_trace(trace2);
+    plot.add_trace(trace3);
+    plot.add_trace(trace4);
+
+    let mut title = String::new();
+    let mut file_name = String::new();
+
+    fmt::write(&mut title, format_args!("{} Efficient price {} ticks  state var {:.3e}  noise var {:.3e}",
+                                        ticker, time_line.len(), fit.state_var, fit.noise_var)).unwrap();
+    fmt::write(&mut file_name, format_args!("plots/{}-efficient_price.html",ticker)).unwrap();
+    let layout = Layout::new()
+        .height(2200)
+        .width(4200)
+        .x_axis(
+            Axis::new()
+                .grid_color(Rgba::new(255, 255, 255, 1.0))
+                .range_slider(RangeSlider::new().visible(true))
+        ).title(Title::new(&title))
+        .y_axis(Axis::new().title("price".into())
+            .grid_color(Rgba::new(255, 255, 255, 0.25))
+            .side(AxisSide::Left)
+        )
+        .y_axis2(Axis::new().title("volume".into())
+            .grid_color(Rgba::new(255, 0, 0, 0.25))
+            .overlaying("y")
+            .side(AxisSide::Right)
+        );
+    plot.set_layout(layout);
+    plot.use_local_plotly();
+    println!("writing file {}", &file_name);
+    plot.write_html(&file_name);
+    Ok(())
+}
+
+
+/// Bar closes with the background shaded by HMM regime, lighter for calmer regimes.
+pub fn test_regime_graph(ticker:&str, regimes:&Regimes) -> Result<(), Box<dyn Error>> {
+    let time_line: Vec<String> = regimes.starts.iter().map(|t| s2hhmmss_32(*t)).collect();
+    let trace1 = Scatter::new(time_line.clone(), regimes.closes.clone()).name("close");
+    let mut plot = Plot::new();
+    plot.add_trace(trace1);
+
+    let mut title = String::new();
+    let mut file_name = String::new();
+
+    let states = regimes.fit.model.states();
+    fmt::write(&mut title, format_args!("{} {} regimes over {} sec bars",ticker,states,regimes.interval)).unwrap();
+    fmt::write(&mut file_name, format_args!("plots/{}-regimes.html",ticker)).unwrap();
+    let mut layout = Layout::new()
+        .height(2200)
+        .width(4200)
+        .x_axis(
+            Axis::new()
+                .grid_color(Rgba::new(255, 255, 255, 1.0))
+                .range_slider(RangeSlider::new().visible(true))
+        ).title(Title::new(&title))
+        .y_axis(Axis::new().title("price".into())
+            .grid_color(Rgba::new(255, 255, 255, 0.25))
+            .side(AxisSide::Left)
+        );
+    // category axis, so shapes span from half a bar before the first to half a bar after the last
+    let mut first = 0usize;
+    for (_, _, state) in regimes.segments() {
+        let count = regimes.fit.states[first..].iter().take_while(|s| **s == state).count();
+        let alpha = 0.1 + 0.4 * state as f64 / (states.max(2) - 1) as f64;
+        layout.add_shape(Shape::new()
+            .shape_type(ShapeType::Rect)
+            .x_ref("x")
+            .y_ref("paper")
+            .x0(first as f64 - 0.5)
+            .x1((first + count) as f64 - 0.5)
+            .y0(0.0)
+            .y1(1.0)
+            .fill_color(Rgba::new(255, 0, 0, alpha))
+            .line(ShapeLine::new().width(0.0))
+            .layer(ShapeLayer::Below));
+        first += count;
+    }
+    plot.set_layout(layout);
+    plot.use_local_plotly();
+    println!("writing file {}", &file_name);
+    plot.write_html(&file_name);
+    Ok(())
+}
+
+
+/// Sampling frequency signature of two symbols, grid realized correlation and covariance against
+/// the sampling interval with the Hayashi-Yoshida values as flat references.
+pub fn test_signature_graph(pair:(&str, &str), signature:&CovarianceSignature) -> Result<(), Box<dyn Error>> {
+    let intervals = signature.intervals.clone();
+    let flat = |v: f64| vec![v; intervals.len()];
+    let grid_corr: Vec<f64> = signature.grid.iter().map(|g| g.correlation).collect();
+    let grid_cov: Vec<f64> = signature.grid.iter().map(|g| g.covariance).collect();
+    let trace1 = Scatter::new(intervals.clone(), grid_corr).name("grid correlation").mode(Mode::LinesMarkers);
+    let trace2 = Scatter::new(intervals.clone(), flat(signature.hayashi_yoshida.correlation)).name("Hayashi-Yoshida correlation");
+    let trace3 = Scatter::new(intervals.clone(), grid_cov).name("grid covariance").mode(Mode::LinesMarkers).y_axis("y2");
+    let trace4 = Scatter::new(intervals.clone(), flat(signature.hayashi_yoshida.covariance)).name("Hayashi-Yoshida covariance").y_axis("y2");
+    let mut plot = Plot::new();
+    plot.add_trace(trace1);
+    plot.add_trace(trace2);
+    plot.add_trace(trace3);
+    plot.add_trace(trace4);
+
+    let mut title = String::new();
+    let mut file_name = String::new();
+
+    fmt::write(&mut title, format_args!("{} / {} realized covariance signature",pair.0,pair.1)).unwrap();
+    fmt::write(&mut file_name, format_args!("plots/{}-{}-signature.html",pair.0,pair.1)).unwrap();
+    let layout = Layout::new()
+        .height(2200)
+        .width(4200)
+        .x_axis(
+            Axis::new()
+                .title("sampling interval [s]".into())
+                .type_(AxisType::Log)
+        ).title(Title::new(&title))
+        .y_axis(Axis::new().title("correlation".into())
+            .grid_color(Rgba::new(255, 255, 255, 0.25))
+            .side(AxisSide::Left)
+        )
+        .y_axis2(Axis::new().title("covariance".into())
+            .grid_color(Rgba::new(255, 0, 0, 0.25))
+            .overlaying("y")
+            .side(AxisSide::Right)
+        );
+    plot.set_layout(layout);
+    plot.use_local_plotly();
+    println!("writing file {}", &file_name);
+    plot.write_html(&file_name);
+    Ok(())
+}
+
+
+/// Equal and volume weighted market indices with advancers, decliners and new highs/lows per bar.
+pub fn test_market_breadth_graph(day:&str, breadth:&MarketBreadth) -> Result<(), Box<dyn Error>> {
+    let time_line: Vec<String> = breadth.times.iter().map(|t| s2hhmmss_32(*t)).collect();
+    let trace1 = Scatter::new(time_line.clone(), breadth.equal_weighted.clone()).name("equal weighted");
+    let trace2 = Scatter::new(time_line.clone(), breadth.volume_weighted.clone()).name("volume weighted");
+    let trace3 = Scatter::new(time_line.clone(), breadth.advancers.clone()).name("advancers").y_axis("y2");
+    let trace4 = Scatter::new(time_line.clone(), breadth.decliners.clone()).name("decliners").y_axis("y2");
+    let trace5 = Scatter::new(time_line.clone(), breadth.new_highs.clone()).name("new highs").mode(Mode::Markers).y_axis("y2");
+    let trace6 = Scatter::new(time_line.clone(), breadth.new_lows.clone()).name("new lows").mode(Mode::Markers).y_axis("y2");
+    let mut plot = Plot::new();
+    plot.add_trace(trace1);
+    plot.add_trace(trace2);
+    plot.add_trace(trace3);
+    plot.add_trace(trace4);
+    plot.add_trace(trace5);
+    plot.add_trace(trace6);
+
+    let mut title = String::new();
+    let mut file_name = String::new();
+
+    let symbols = breadth.symbols.last().cloned().unwrap_or(0);
+    fmt::write(&mut title, format_args!("{} market breadth {} symbols, {} sec bars",day,symbols,breadth.interval)).unwrap();
+    fmt::write(&mut file_name, format_args!("plots/{}-market-breadth.html",day)).unwrap();
+    let layout = Layout::new()
+        .height(2200)
+        .width(4200)
+        .x_axis(
+            Axis::new()
+                .grid_color(Rgba::new(255, 255, 255, 1.0))
+                .range_slider(RangeSlider::new().visible(true))
+        ).title(Title::new(&title))
+        .y_axis(Axis::new().title("index".into())
+            .grid_color(Rgba::new(255, 255, 255, 0.25))
+            .side(AxisSide::Left)
+        )
+        .y_axis2(Axis::new().title("symbols".into())
+            .grid_color(Rgba::new(255, 0, 0, 0.25))
+            .overlaying("y")
+            .side(AxisSide::Right)
+        );
+    plot.set_layout(layout);
+    plot.use_local_plotly();
+    println!("writing file {}", &file_name);
+    plot.write_html(&file_name);
+    Ok(())
+}
+
+
+/// Volume at price as horizontal bars, the point of control and value area drawn in their own colors.
+pub fn test_volume_profile_graph(ticker:&str, profile:&VolumeProfile) -> Result<(), Box<dyn Error>> {
+    let mut poc: (Vec<i64>, Vec<f64>) = (Vec::new(), Vec::new());
+    let mut area: (Vec<i64>, Vec<f64>) = (Vec::new(), Vec::new());
+    let mut outside: (Vec<i64>, Vec<f64>) = (Vec::new(), Vec::new());
+    for (price, volume) in profile.levels.iter() {
+        let target = if *price == profile.poc {
+            &mut poc
+        } else if *price >= profile.value_area_low && *price <= profile.value_area_high {
+            &mut area
+        } else {
+            &mut outside
+        };
+        target.0.push(*volume);
+        target.1.push(price.to_f64().unwrap_or(0.0));
+    }
+    let mut plot = Plot::new();
+    for ((volume, price), name, color) in [(outside, "volume", Rgba::new(120, 120, 120, 0.8)),
+                                           (area, "value area", Rgba::new(0, 90, 200, 0.8)),
+                                           (poc, "point of control", Rgba::new(220, 0, 0, 0.9))] {
+        plot.add_trace(Bar::new(volume, price).name(name).orientation(Orientation::Horizontal).marker(Marker::new().color(color)));
+    }
+
+    let mut title = String::new();
+    let mut file_name = String::new();
+
+    fmt::write(&mut title, format_args!("{} Volume profile POC {} value area {} - {} ({:.0}%)",
+                                        ticker, profile.poc, profile.value_area_low, profile.value_area_high, profile.value_area * 100.0)).unwrap();
+    fmt::write(&mut file_name, format_args!("plots/{}-volume_profile.html",ticker)).unwrap();
+    let layout = Layout::new()
+        .height(2200)
+        .width(4200)
+        .bar_mode(BarMode::Overlay)
+        .title(Title::new(&title))
+        .x_axis(Axis::new().title("volume".into()))
+        .y_axis(Axis::new().title("price".into()));
+    plot.set_layout(layout);
+    plot.use_local_plotly();
+    println!("writing file {}", &file_name);
+    plot.write_html(&file_name);
+    Ok(())
+}
+
+/// TPO letter chart, one horizontal bar of TPO count per price bucket labelled with its letters.
+pub fn test_tpo_graph(ticker:&str, tpo:&TpoProfile) -> Result<(), Box<dyn Error>> {
+    let counts: Vec<usize> = tpo.levels.iter().map(|(_, l)| l.len()).collect();
+    let prices: Vec<f64> = tpo.levels.iter().map(|(p, _)| p.to_f64().unwrap_or(0.0)).collect();
+    let letters: Vec<String> = tpo.levels.iter().map(|(_, l)| l.clone()).collect();
+    let colors: Vec<Rgba> = tpo.levels.iter().map(|(p, _)| {
+        if *p == tpo.poc {
+            Rgba::new(220, 0, 0, 0.9)
+        } else if *p >= tpo.value_area_low && *p <= tpo.value_area_high {
+            Rgba::new(0, 90, 200, 0.8)
+        } else {
+            Rgba::new(120, 120, 120, 0.8)
+        }
+    }).collect();
+    let trace = Bar::new(counts, prices)
+        .name("TPO")
+        .orientation(Orientation::Horizontal)
+        .text_array(letters)
+        .text_position(TextPosition::Inside)
+        .marker(Marker::new().color_array(colors));
+    let mut plot = Plot::new();
+    plot.add_trace(trace);
+
+    let mut title = String::new();
+    let mut file_name = String::new();
+
+    let legend: Vec<String> = tpo.legend().iter().map(|(c, t)| format!("{}={}", c, t)).collect();
+    fmt::write(&mut title, format_args!("{} TPO profile {} sec periods, POC {}, IB {} - {}<br>{}",
+                                        ticker, tpo.period, tpo.poc, tpo.initial_balance.0, tpo.initial_balance.1, legend.join(" "))).unwrap();
+    fmt::write(&mut file_name, format_args!("plots/{}-TPO.html",ticker)).unwrap();
+    let layout = Layout::new()
+        .height(2200)
+        .width(4200)
+        .title(Title::new(&title))
+        .x_axis(Axis::new().title("TPO count".into()))
+        .y_axis(Axis::new().title("price".into()));
+    plot.set_layout(layout);
+    plot.use_local_plotly();
+    println!("writing file {}", &file_name);
+    plot.write_html(&file_name);
+    Ok(())
+}
+
+
 #[cfg(test)]
 mod test {
     use super::*;
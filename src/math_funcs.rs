@@ -29,5 +29,30 @@
  *
  */
 
+pub  mod distributions;
 pub  mod pre_processing;
-pub  mod simulation;
\ No newline at end of file
+pub  mod simulation;
+pub  mod spectral;
+pub  mod filtering;
+pub  mod wavelets;
+pub  mod diagnostics;
+pub  mod spreads;
+pub  mod order_flow;
+pub  mod vpin;
+pub  mod liquidity;
+pub  mod optimize;
+pub  mod durations;
+pub  mod hawkes;
+pub  mod jumps;
+pub  mod garch;
+pub  mod state_space;
+pub  mod hmm;
+pub  mod arima;
+pub  mod cross_section;
+pub  mod hayashi_yoshida;
+pub  mod lead_lag;
+pub  mod market;
+pub  mod seasonality;
+pub  mod market_profile;
+pub  mod trade_size;
+pub  mod blocks;
\ No newline at end of file
@@ -0,0 +1,509 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// ARIMA(p,d,q) models for bar and per second price series
+//   w_t = diff^d(y)_t,  (w_t - mu) = sum phi_i (w_t-i - mu) + e_t + sum theta_j e_t-j
+// estimated by conditional least squares or by exact Gaussian likelihood through the Kalman
+// filter of `state_space`, with AIC/BIC order selection and multi-step forecast intervals.
+
+use std::error::Error;
+use std::f64::consts::PI;
+use nalgebra::{DMatrix, DVector};
+use rust_decimal::prelude::ToPrimitive;
+use crate::event_structs::EventList;
+use crate::math_funcs::distributions::norm_ppf;
+use crate::math_funcs::optimize::{hessian_std_errors, nelder_mead_restarts};
+use crate::math_funcs::pre_processing::diff_series;
+use crate::math_funcs::state_space::StateSpace;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ArimaOrder {
+    pub p: usize,
+    pub d: usize,
+    pub q: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArimaMethod {
+    /// Conditional least squares, pre-sample innovations set to zero.
+    Css,
+    /// Exact Gaussian maximum likelihood, started from the CSS estimate.
+    Mle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InformationCriterion {
+    Aic,
+    Bic,
+}
+
+/// Fitted ARIMA model, `std_err` being ordered as `[mean, ar.., ma..]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArimaFit {
+    pub order: ArimaOrder,
+    pub method: ArimaMethod,
+    pub mean: f64,
+    pub ar: Vec<f64>,
+    pub ma: Vec<f64>,
+    pub sigma2: f64,
+    pub std_err: Vec<f64>,
+    pub log_likelihood: f64,
+    pub aic: f64,
+    pub bic: f64,
+    pub converged: bool,
+    pub residuals: Vec<f32>,
+    differenced: Vec<f64>,
+    levels: Vec<f64>,
+}
+
+/// Point forecasts with a symmetric normal interval.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArimaForecast {
+    pub level: f64,
+    pub mean: Vec<f32>,
+    pub lower: Vec<f32>,
+    pub upper: Vec<f32>,
+}
+
+/// True when all roots of `1 - sum c_i z^i` lie outside the unit circle, i.e. the companion
+/// matrix of `c` has spectral radius below one.
+fn roots_outside_unit_circle(c: &[f64]) -> bool {
+    let k = c.len();
+    if k == 0 {
+        return true;
+    }
+    let mut companion = DMatrix::<f64>::zeros(k, k);
+    for (i, v) in c.iter().enumerate() {
+        companion[(0, i)] = *v;
+    }
+    for i in 1..k {
+        companion[(i, i - 1)] = 1.0;
+    }
+    companion.complex_eigenvalues().iter().all(|z| z.norm() < 1.0 - 1e-6)
+}
+
+/// Stationary and invertible.
+fn admissible(ar: &[f64], ma: &[f64]) -> bool {
+    let neg_ma: Vec<f64> = ma.iter().map(|v| -v).collect();
+    roots_outside_unit_circle(ar) && roots_outside_unit_circle(&neg_ma)
+}
+
+/// Conditional residuals of an ARMA(p,q) on `w` given its first `condition >= p` values, which
+/// are dropped.
+fn css_residuals(w: &[f64], mean: f64, ar: &[f64], ma: &[f64], condition: usize) -> Vec<f64> {
+    let mut e = vec![0.0; w.len()];
+    for t in condition..w.len() {
+        let mut v = w[t] - mean;
+        for (i, phi) in ar.iter().enumerate() {
+            v -= phi * (w[t - 1 - i] - mean);
+        }
+        for (j, theta) in ma.iter().enumerate() {
+            if t > j {
+                v -= theta * e[t - 1 - j];
+            }
+        }
+        e[t] = v;
+    }
+    e.split_off(condition)
+}
+
+/// MA(infinity) weights `psi_0 = 1, psi_1, ..`.
+fn psi_weights(ar: &[f64], ma: &[f64], n: usize) -> Vec<f64> {
+    let mut psi = vec![0.0; n];
+    for j in 0..n {
+        psi[j] = if j == 0 { 1.0 } else { ma.get(j - 1).cloned().unwrap_or(0.0) };
+        for (i, phi) in ar.iter().enumerate() {
+            if j > i {
+                psi[j] += phi * psi[j - 1 - i];
+            }
+        }
+    }
+    psi
+}
+
+/// ARMA(p,q) in Harvey's state space form with unit innovation variance.
+fn arma_state_space(ar: &[f64], ma: &[f64]) -> Result<(StateSpace, DMatrix<f64>), Box<dyn Error>> {
+    let r = ar.len().max(ma.len() + 1);
+    let mut transition = DMatrix::<f64>::zeros(r, r);
+    for (i, phi) in ar.iter().enumerate() {
+        transition[(i, 0)] = *phi;
+    }
+    for i in 0..r - 1 {
+        transition[(i, i + 1)] = 1.0;
+    }
+    let mut g = DVector::<f64>::zeros(r);
+    g[0] = 1.0;
+    for (j, theta) in ma.iter().enumerate() {
+        g[j + 1] = *theta;
+    }
+    let state_cov = &g * g.transpose();
+    // stationary covariance, vec(P) = (I - T kron T)^-1 vec(Q)
+    let lhs = DMatrix::<f64>::identity(r * r, r * r) - transition.kronecker(&transition);
+    let vec_p = lhs.lu().solve(&DVector::from_column_slice(state_cov.as_slice())).ok_or("no stationary covariance")?;
+    let p0 = DMatrix::from_column_slice(r, r, vec_p.as_slice());
+    let mut observation = DVector::<f64>::zeros(r);
+    observation[0] = 1.0;
+    Ok((StateSpace { transition, observation, state_cov, obs_var: 0.0 }, p0))
+}
+
+/// Exact log likelihood with the innovation variance concentrated out, and that variance.
+fn exact_log_likelihood(w: &[f64], mean: f64, ar: &[f64], ma: &[f64]) -> Option<(f64, f64)> {
+    if !admissible(ar, ma) {
+        return None;
+    }
+    let (model, p0) = arma_state_space(ar, ma).ok()?;
+    let y: Vec<f64> = w.iter().map(|v| v - mean).collect();
+    let x0 = DVector::zeros(model.transition.nrows());
+    let out = model.filter(&y, &x0, &p0);
+    let n = y.len() as f64;
+    let mut sum_sq = 0.0;
+    let mut sum_log_f = 0.0;
+    for (t, yt) in y.iter().enumerate() {
+        let v = yt - model.observation.dot(&out.predicted_mean[t]);
+        let f = out.predicted_cov[t][(0, 0)];
+        if f <= 0.0 || !f.is_finite() {
+            return None;
+        }
+        sum_sq += v * v / f;
+        sum_log_f += f.ln();
+    }
+    let sigma2 = sum_sq / n;
+    Some((-0.5 * n * ((2.0 * PI * sigma2).ln() + 1.0) - 0.5 * sum_log_f, sigma2))
+}
+
+fn split(x: &[f64], p: usize) -> (f64, &[f64], &[f64]) {
+    (x[0], &x[1..1 + p], &x[1 + p..])
+}
+
+/// Fits an ARIMA model to a series, differencing `order.d` times with `diff_series`.
+///
+/// # Arguments
+///
+/// * `series` - Levels, e.g. bar closes or `get_sec_avg_time_series` prices
+/// * `order` - AR order, integration order and MA order
+/// * `method` - `Css` or `Mle`
+///
+/// # Returns
+///
+/// The fit, or an error when the differenced series is too short or the start is not admissible
+///
+/// # Example
+///
+/// ```
+/// # use feed_parser::math_funcs::arima::{fit_arima, ArimaMethod, ArimaOrder};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let mut level = 100.0f32;
+/// # let closes: Vec<f32> = (0..300u32).map(|i| { level += ((i * 7_919 % 101) as f32 - 50.0) * 1e-3; level }).collect();
+/// let fit = fit_arima(&closes, ArimaOrder { p: 1, d: 1, q: 1 }, ArimaMethod::Mle)?;
+/// let next = fit.forecast(10, 0.95);
+/// # assert_eq!(next.mean.len(), 10);
+/// # Ok(())
+/// # }
+/// ```
+pub fn fit_arima(series: &[f32], order: ArimaOrder, method: ArimaMethod) -> Result<ArimaFit, Box<dyn Error>> {
+    fit_conditioned(series, order, method, order.p)
+}
+
+/// `fit_arima` with the conditional sum of squares taken given the first `condition` differenced
+/// values, so CSS fits of different AR orders are scored on the same sample.
+fn fit_conditioned(series: &[f32], order: ArimaOrder, method: ArimaMethod, condition: usize) -> Result<ArimaFit, Box<dyn Error>> {
+    let condition = condition.max(order.p);
+    let mut w: Vec<f64> = series.iter().map(|v| *v as f64).collect();
+    let mut levels = Vec::with_capacity(order.d);
+    for _ in 0..order.d {
+        levels.push(*w.last().ok_or("series is too short to difference")?);
+        w = diff_series(&w);
+    }
+    let k = 1 + order.p + order.q;
+    if w.len() < (condition + 3 * k).max(10) {
+        return Err(format!("{} differenced observations are too few for ARIMA({},{},{})", w.len(), order.p, order.d, order.q).into());
+    }
+    let n_mean = w.iter().sum::<f64>() / w.len() as f64;
+    let scale = (w.iter().map(|v| (v - n_mean).powi(2)).sum::<f64>() / w.len() as f64).sqrt();
+    if scale <= 0.0 {
+        return Err("differenced series is constant".into());
+    }
+    // work on a unit scale so the simplex step suits every price level
+    let z: Vec<f64> = w.iter().map(|v| (v - n_mean) / scale).collect();
+    let css = |x: &[f64]| -> f64 {
+        let (mean, ar, ma) = split(x, order.p);
+        if !admissible(ar, ma) {
+            return f64::INFINITY;
+        }
+        css_residuals(&z, mean, ar, ma, condition).iter().map(|e| e * e).sum()
+    };
+    let start = vec![0.0; k];
+    let css_min = nelder_mead_restarts(css, &start, 0.1, 1e-10, 2000 * k)?;
+    let mut x = css_min.x.clone();
+    let mut converged = css_min.converged;
+    let neg_log_likelihood = |x: &[f64]| -> f64 {
+        let (mean, ar, ma) = split(x, order.p);
+        match method {
+            ArimaMethod::Css => {
+                if !admissible(ar, ma) {
+                    return f64::INFINITY;
+                }
+                let e = css_residuals(&z, mean, ar, ma, condition);
+                let n = e.len() as f64;
+                0.5 * n * ((2.0 * PI * e.iter().map(|v| v * v).sum::<f64>() / n).ln() + 1.0)
+            },
+            ArimaMethod::Mle => exact_log_likelihood(&z, mean, ar, ma).map(|(ll, _)| -ll).unwrap_or(f64::INFINITY),
+        }
+    };
+    if method == ArimaMethod::Mle {
+        let mle = nelder_mead_restarts(neg_log_likelihood, &x, 0.05, 1e-10, 2000 * k)?;
+        x = mle.x;
+        converged = mle.converged;
+    }
+    let std_err: Vec<f64> = hessian_std_errors(neg_log_likelihood, &x, 1e-4)
+        .unwrap_or_else(|_| vec![f64::NAN; k]);
+
+    let (mean, ar, ma) = split(&x, order.p);
+    let e = css_residuals(&z, mean, ar, ma, condition);
+    let (log_likelihood, sigma2) = match method {
+        ArimaMethod::Css => {
+            let n = e.len() as f64;
+            let s2 = e.iter().map(|v| v * v).sum::<f64>() / n;
+            (-0.5 * n * ((2.0 * PI * s2).ln() + 1.0), s2)
+        },
+        ArimaMethod::Mle => exact_log_likelihood(&z, mean, ar, ma).ok_or("no admissible maximum")?,
+    };
+    // back to the original scale
+    let n_obs = match method {
+        ArimaMethod::Css => e.len(),
+        ArimaMethod::Mle => z.len(),
+    } as f64;
+    let log_likelihood = log_likelihood - n_obs * scale.ln();
+    let params = (k + 1) as f64;
+    let mut std_err = std_err;
+    std_err[0] *= scale;
+    Ok(ArimaFit {
+        order,
+        method,
+        mean: n_mean + mean * scale,
+        ar: ar.to_vec(),
+        ma: ma.to_vec(),
+        sigma2: sigma2 * scale * scale,
+        std_err,
+        log_likelihood,
+        aic: -2.0 * log_likelihood + 2.0 * params,
+        bic: -2.0 * log_likelihood + params * n_obs.ln(),
+        converged,
+        residuals: e.iter().map(|v| (v * scale) as f32).collect(),
+        differenced: w,
+        levels,
+    })
+}
+
+impl ArimaFit {
+    pub fn criterion(&self, criterion: InformationCriterion) -> f64 {
+        match criterion {
+            InformationCriterion::Aic => self.aic,
+            InformationCriterion::Bic => self.bic,
+        }
+    }
+
+    /// Forecasts the next `horizon` levels with a `level` (e.g. 0.95) normal interval.
+    pub fn forecast(&self, horizon: usize, level: f64) -> ArimaForecast {
+        let p = self.ar.len();
+        let q = self.ma.len();
+        let mut w = self.differenced.clone();
+        let mut e: Vec<f64> = vec![0.0; w.len() - self.residuals.len()];
+        e.extend(self.residuals.iter().map(|v| *v as f64));
+        for _ in 0..horizon {
+            let t = w.len();
+            let mut v = self.mean;
+            for i in 0..p {
+                v += self.ar[i] * (w[t - 1 - i] - self.mean);
+            }
+            for j in 0..q {
+                if t > j {
+                    v += self.ma[j] * e[t - 1 - j];
+                }
+            }
+            w.push(v);
+            e.push(0.0);
+        }
+        let mut path: Vec<f64> = w.split_off(self.differenced.len());
+        // integrate the forecasts and the MA(infinity) weights d times
+        let mut psi = psi_weights(&self.ar, &self.ma, horizon);
+        for last in self.levels.iter().rev() {
+            let mut acc = *last;
+            for v in path.iter_mut() {
+                acc += *v;
+                *v = acc;
+            }
+            let mut acc = 0.0;
+            for v in psi.iter_mut() {
+                acc += *v;
+                *v = acc;
+            }
+        }
+        let z = norm_ppf(0.5 + level / 2.0);
+        let mut var = 0.0;
+        let mut out = ArimaForecast { level, mean: Vec::with_capacity(horizon), lower: Vec::with_capacity(horizon), upper: Vec::with_capacity(horizon) };
+        for (h, m) in path.iter().enumerate() {
+            var += self.sigma2 * psi[h] * psi[h];
+            out.mean.push(*m as f32);
+            out.lower.push((m - z * var.sqrt()) as f32);
+            out.upper.push((m + z * var.sqrt()) as f32);
+        }
+        out
+    }
+}
+
+/// Order search result, `table` holding the criterion of every order that could be fit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArimaSelection {
+    pub best: ArimaFit,
+    pub table: Vec<(ArimaOrder, f64)>,
+}
+
+/// Fits every ARIMA(p,d,q) with `p <= max_p`, `q <= max_q` and keeps the best by `criterion`.
+/// CSS fits all condition on the first `max_p` differenced values so their criteria are comparable.
+pub fn select_arima(series: &[f32], max_p: usize, d: usize, max_q: usize, method: ArimaMethod,
+                    criterion: InformationCriterion) -> Result<ArimaSelection, Box<dyn Error>> {
+    let mut best: Option<ArimaFit> = None;
+    let mut table = Vec::new();
+    for p in 0..=max_p {
+        for q in 0..=max_q {
+            let order = ArimaOrder { p, d, q };
+            if let Ok(fit) = fit_conditioned(series, order, method, max_p) {
+                let value = fit.criterion(criterion);
+                table.push((order, value));
+                if best.as_ref().is_none_or(|b| value < b.criterion(criterion)) {
+                    best = Some(fit);
+                }
+            }
+        }
+    }
+    Ok(ArimaSelection { best: best.ok_or("no ARIMA order could be fit")?, table })
+}
+
+/// Price series for ARIMA work, `interval` second bar closes or with `None` the per second
+/// average prices of `get_sec_avg_time_series`.
+pub fn arima_series(event_list: &EventList, interval: Option<i32>) -> Vec<f32> {
+    match interval {
+        Some(secs) => event_list.get_bars(secs).iter().map(|b| b.close.to_f32().unwrap_or(0.0)).collect(),
+        None => event_list.get_sec_avg_time_series().iter().map(|(_, p, _)| p.to_f32().unwrap_or(0.0)).collect(),
+    }
+}
+
+/// Order search on an `EventList` price series.
+///
+/// # Example
+///
+/// ```ignore
+/// let fit = event_list_arima(&el, Some(60), 3, 1, 3, ArimaMethod::Mle, InformationCriterion::Bic)?.best;
+/// println!("ARIMA({},{},{}) sigma {:.4}", fit.order.p, fit.order.d, fit.order.q, fit.sigma2.sqrt());
+/// ```
+pub fn event_list_arima(event_list: &EventList, interval: Option<i32>, max_p: usize, d: usize, max_q: usize,
+                        method: ArimaMethod, criterion: InformationCriterion) -> Result<ArimaSelection, Box<dyn Error>> {
+    select_arima(&arima_series(event_list, interval), max_p, d, max_q, method, criterion)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use approx::assert_relative_eq;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use rand_distr::{Distribution, Normal};
+
+    fn arma(n: usize, ar: &[f64], ma: &[f64], seed: u64) -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let noise = Normal::new(0.0, 1.0).unwrap();
+        let burn = 200;
+        let mut w = vec![0.0; n + burn];
+        let e: Vec<f64> = (0..n + burn).map(|_| noise.sample(&mut rng)).collect();
+        for t in 0..n + burn {
+            w[t] = e[t];
+            for (i, phi) in ar.iter().enumerate() {
+                if t > i { w[t] += phi * w[t - 1 - i]; }
+            }
+            for (j, theta) in ma.iter().enumerate() {
+                if t > j { w[t] += theta * e[t - 1 - j]; }
+            }
+        }
+        w.split_off(burn)
+    }
+
+    #[test]
+    fn test_arma_state_space() {
+        // AR(1) stationary variance 1 / (1 - phi^2)
+        let (_, p0) = arma_state_space(&[0.5], &[]).unwrap();
+        assert_relative_eq!(p0[(0, 0)], 1.0 / 0.75, epsilon = 1e-10);
+        // MA(1) variance 1 + theta^2
+        let (_, p0) = arma_state_space(&[], &[0.4]).unwrap();
+        assert_relative_eq!(p0[(0, 0)], 1.16, epsilon = 1e-10);
+        assert_eq!(psi_weights(&[0.5], &[0.4], 3), vec![1.0, 0.9, 0.45]);
+        assert!(admissible(&[0.5, 0.3], &[0.4]));
+        assert!(!admissible(&[1.1], &[]));
+        assert!(!admissible(&[], &[-1.5]));
+    }
+
+    #[test]
+    fn test_fit_arma() {
+        let w: Vec<f32> = arma(2_000, &[0.6], &[0.3], 1).iter().map(|v| (v + 5.0) as f32).collect();
+        for method in [ArimaMethod::Css, ArimaMethod::Mle] {
+            let fit = fit_arima(&w, ArimaOrder { p: 1, d: 0, q: 1 }, method).unwrap();
+            assert_relative_eq!(fit.ar[0], 0.6, epsilon = 0.06);
+            assert_relative_eq!(fit.ma[0], 0.3, epsilon = 0.07);
+            assert_relative_eq!(fit.mean, 5.0, epsilon = 0.2);
+            assert_relative_eq!(fit.sigma2, 1.0, epsilon = 0.1);
+            assert!(fit.std_err.iter().all(|s| s.is_finite() && *s > 0.0));
+        }
+    }
+
+    #[test]
+    fn test_select_and_forecast() {
+        // random walk with AR(1) increments
+        let mut level = 100.0;
+        let y: Vec<f32> = arma(1_500, &[0.5], &[], 2).iter().map(|v| { level += 0.1 * v; level as f32 }).collect();
+        let selection = select_arima(&y, 2, 1, 1, ArimaMethod::Css, InformationCriterion::Bic).unwrap();
+        assert_eq!(selection.table.len(), 6);
+        let fit = selection.best;
+        // every candidate is scored on the same 1_499 - 2 residuals
+        assert_eq!(fit.residuals.len(), 1_497);
+        let white = fit_conditioned(&y, ArimaOrder { p: 0, d: 1, q: 0 }, ArimaMethod::Css, 2).unwrap();
+        assert_eq!(white.residuals.len(), 1_497);
+        assert_eq!(selection.table[0], (white.order, white.bic));
+        assert_eq!((fit.order.p, fit.order.q), (1, 0));
+        let fc = fit.forecast(20, 0.95);
+        assert_eq!(fc.mean.len(), 20);
+        // first step interval is +- 1.96 sigma around the level
+        let width = (fc.upper[0] - fc.lower[0]) as f64;
+        assert_relative_eq!(width, 2.0 * 1.959964 * fit.sigma2.sqrt(), max_relative = 1e-3);
+        assert!(fc.upper[19] - fc.lower[19] > fc.upper[0] - fc.lower[0]);
+        assert!((fc.mean[0] - y[y.len() - 1]).abs() < 0.5);
+    }
+}
@@ -0,0 +1,335 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// Block trade (large print) detection: trades above an absolute share or notional threshold or
+// above a per symbol size percentile, with the price move before and after each block over a
+// set of horizons, summarised per symbol and per day.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Write;
+use rust_decimal::prelude::ToPrimitive;
+use crate::event_structs::EventList;
+use crate::nyse::base_funcs::EventStats;
+
+/// A trade is a block when any enabled threshold is reached.
+///
+/// - `min_shares`: Absolute size in shares.
+/// - `min_notional`: Price times size in dollars.
+/// - `percentile`: Per symbol size percentile in (0, 1), e.g. 0.999. Only sizes strictly above
+///   it count, so a session of equal sized trades has no percentile blocks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockCriteria {
+    pub min_shares: Option<i32>,
+    pub min_notional: Option<f64>,
+    pub percentile: Option<f64>,
+}
+
+impl Default for BlockCriteria {
+    /// The customary 10,000 shares or $200,000.
+    fn default() -> Self {
+        BlockCriteria { min_shares: Some(10_000), min_notional: Some(200_000.0), percentile: None }
+    }
+}
+
+/// One block, `moves` holding `(horizon, before, after)` log price moves in basis points:
+/// block price against the last price `horizon` seconds before, and the last price `horizon`
+/// seconds after against the block price. `None` when the session does not reach that far.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockTrade {
+    pub time: f64,
+    pub string_time: String,
+    pub price: f32,
+    pub volume: i32,
+    pub notional: f64,
+    pub by_shares: bool,
+    pub by_notional: bool,
+    pub by_percentile: bool,
+    pub moves: Vec<(f64, Option<f64>, Option<f64>)>,
+}
+
+/// Size at the `q` quantile (nearest rank).
+fn size_percentile(sizes: &[i32], q: f64) -> Option<i32> {
+    if sizes.is_empty() {
+        return None;
+    }
+    let mut sorted = sizes.to_vec();
+    sorted.sort();
+    let rank = ((q * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len());
+    Some(sorted[rank - 1])
+}
+
+/// Finds the blocks of an `EventList`.
+///
+/// # Arguments
+///
+/// * `criteria` - Thresholds, see `BlockCriteria`
+/// * `horizons` - Seconds before and after each block to measure the price move over
+///
+/// # Example
+///
+/// ```ignore
+/// let blocks = detect_blocks(&el, &BlockCriteria::default(), &[1.0, 10.0, 60.0, 300.0]);
+/// for b in blocks.iter() {
+///     println!("{} {} @ {} moves {:?}", b.string_time, b.volume, b.price, b.moves);
+/// }
+/// ```
+pub fn detect_blocks(event_list: &EventList, criteria: &BlockCriteria, horizons: &[f64]) -> Vec<BlockTrade> {
+    let tics: Vec<_> = event_list.events.values().flat_map(|e| e.tics.iter()).collect();
+    let times: Vec<f64> = tics.iter().map(|t| t.seconds.to_f64().unwrap_or(0.0)).collect();
+    let prices: Vec<f64> = tics.iter().map(|t| t.price.to_f64().unwrap_or(0.0)).collect();
+    let sizes: Vec<i32> = tics.iter().map(|t| t.volume).collect();
+    let size_cut = criteria.percentile.and_then(|q| size_percentile(&sizes, q));
+    let (Some(first), Some(last)) = (times.first().cloned(), times.last().cloned()) else { return Vec::new() };
+    // last price at or before `t`
+    let price_at = |t: f64| -> f64 { prices[times.partition_point(|x| *x <= t).max(1) - 1] };
+
+    let mut out = Vec::new();
+    for (i, tic) in tics.iter().enumerate() {
+        let notional = prices[i] * tic.volume as f64;
+        let by_shares = criteria.min_shares.is_some_and(|m| tic.volume >= m);
+        let by_notional = criteria.min_notional.is_some_and(|m| notional >= m);
+        let by_percentile = size_cut.is_some_and(|m| tic.volume > m);
+        if !(by_shares || by_notional || by_percentile) || prices[i] <= 0.0 {
+            continue;
+        }
+        let moves = horizons.iter().map(|h| {
+            // before: the last trade prior to this one at least `h` earlier
+            let before = (times[i] - h >= first).then(|| {
+                let idx = times[..i].partition_point(|x| *x <= times[i] - h);
+                let p = if idx == 0 { prices[0] } else { prices[idx - 1] };
+                (prices[i] / p).ln() * 1e4
+            });
+            let after = (times[i] + h <= last).then(|| (price_at(times[i] + h) / prices[i]).ln() * 1e4);
+            (*h, before, after)
+        }).collect();
+        out.push(BlockTrade {
+            time: times[i],
+            string_time: tic.string_time.clone(),
+            price: prices[i] as f32,
+            volume: tic.volume,
+            notional,
+            by_shares,
+            by_notional,
+            by_percentile,
+            moves,
+        });
+    }
+    out
+}
+
+/// Blocks of one symbol with their share of the day and average moves per horizon.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolBlocks {
+    pub trades: usize,
+    pub volume: i64,
+    pub block_volume: i64,
+    pub blocks: Vec<BlockTrade>,
+    pub mean_before: Vec<f64>,
+    pub mean_after: Vec<f64>,
+}
+
+impl SymbolBlocks {
+    pub fn new(event_list: &EventList, criteria: &BlockCriteria, horizons: &[f64]) -> SymbolBlocks {
+        let blocks = detect_blocks(event_list, criteria, horizons);
+        let mean = |k: usize, after: bool| -> f64 {
+            let v: Vec<f64> = blocks.iter().filter_map(|b| if after { b.moves[k].2 } else { b.moves[k].1 }).collect();
+            if v.is_empty() { f64::NAN } else { v.iter().sum::<f64>() / v.len() as f64 }
+        };
+        SymbolBlocks {
+            trades: event_list.events.values().map(|e| e.tics.len()).sum(),
+            volume: event_list.events.values().map(|e| e.get_volume() as i64).sum(),
+            block_volume: blocks.iter().map(|b| b.volume as i64).sum(),
+            mean_before: (0..horizons.len()).map(|k| mean(k, false)).collect(),
+            mean_after: (0..horizons.len()).map(|k| mean(k, true)).collect(),
+            blocks,
+        }
+    }
+
+    pub fn block_volume_share(&self) -> f64 {
+        if self.volume > 0 { self.block_volume as f64 / self.volume as f64 } else { 0.0 }
+    }
+}
+
+/// Block trade report of one day (one parsed file), symbols without blocks left out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockReport {
+    pub criteria: BlockCriteria,
+    pub horizons: Vec<f64>,
+    pub symbols: HashMap<String, SymbolBlocks>,
+}
+
+impl BlockReport {
+    /// # Example
+    ///
+    /// ```ignore
+    /// let report = BlockReport::new(&stats.event_stats, &BlockCriteria::default(), &[10.0, 60.0, 300.0])?;
+    /// println!("{} blocks, most in {:?}", report.total_blocks(), report.get_most_blocks(10));
+    /// std::fs::write("plots/blocks.csv", report.blocks_csv("2023-01-03"))?;
+    /// ```
+    pub fn new(event_stats: &EventStats, criteria: &BlockCriteria, horizons: &[f64]) -> Result<BlockReport, Box<dyn Error>> {
+        if criteria.min_shares.is_none() && criteria.min_notional.is_none() && criteria.percentile.is_none() {
+            return Err("no block threshold set".into());
+        }
+        if criteria.percentile.is_some_and(|q| q <= 0.0 || q >= 1.0) {
+            return Err("percentile must be in (0, 1)".into());
+        }
+        let mut symbols = HashMap::new();
+        for (symbol, el) in event_stats.symbol_events.iter() {
+            let blocks = SymbolBlocks::new(el, criteria, horizons);
+            if !blocks.blocks.is_empty() {
+                symbols.insert(symbol.clone(), blocks);
+            }
+        }
+        Ok(BlockReport { criteria: *criteria, horizons: horizons.to_vec(), symbols })
+    }
+
+    pub fn total_blocks(&self) -> usize {
+        self.symbols.values().map(|s| s.blocks.len()).sum()
+    }
+
+    /// Symbols with the most blocks.
+    pub fn get_most_blocks(&self, max_items: usize) -> Vec<(String, usize)> {
+        let mut out: Vec<(String, usize)> = self.symbols.iter().map(|(s, b)| (s.clone(), b.blocks.len())).collect();
+        out.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        out.truncate(max_items);
+        out
+    }
+
+    /// One row per block, tagged with `day`.
+    pub fn blocks_csv(&self, day: &str) -> String {
+        let mut out = String::from("day,symbol,time,price,volume,notional,by_shares,by_notional,by_percentile");
+        for h in self.horizons.iter() {
+            write!(out, ",before_{}s_bps,after_{}s_bps", h, h).unwrap();
+        }
+        out.push('\n');
+        let mut symbols: Vec<&String> = self.symbols.keys().collect();
+        symbols.sort();
+        let opt = |v: Option<f64>| v.map_or(String::new(), |x| format!("{:.3}", x));
+        for symbol in symbols {
+            for b in self.symbols[symbol].blocks.iter() {
+                write!(out, "{},{},{},{},{},{:.2},{},{},{}", day, symbol, b.string_time, b.price, b.volume, b.notional,
+                       b.by_shares as i32, b.by_notional as i32, b.by_percentile as i32).unwrap();
+                for (_, before, after) in b.moves.iter() {
+                    write!(out, ",{},{}", opt(*before), opt(*after)).unwrap();
+                }
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    /// One row per symbol, tagged with `day`.
+    pub fn summary_csv(&self, day: &str) -> String {
+        let mut out = String::from("day,symbol,trades,blocks,volume,block_volume,block_volume_share");
+        for h in self.horizons.iter() {
+            write!(out, ",mean_before_{}s_bps,mean_after_{}s_bps", h, h).unwrap();
+        }
+        out.push('\n');
+        let mut symbols: Vec<&String> = self.symbols.keys().collect();
+        symbols.sort();
+        for symbol in symbols {
+            let s = &self.symbols[symbol];
+            write!(out, "{},{},{},{},{},{},{:.6}", day, symbol, s.trades, s.blocks.len(), s.volume, s.block_volume, s.block_volume_share()).unwrap();
+            for (before, after) in s.mean_before.iter().zip(s.mean_after.iter()) {
+                write!(out, ",{:.3},{:.3}", before, after).unwrap();
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_structs::test_event_list;
+    use approx::assert_relative_eq;
+
+    fn session() -> EventList {
+        let tics: Vec<(i32, &str, i32)> = (0..100).map(|i| {
+            // 10.00 until the block at 50, 10.10 after it
+            let (price, volume) = match i {
+                50 => ("10.05", 20_000),
+                70 => ("10.10", 600),
+                _ if i > 50 => ("10.10", 100),
+                _ => ("10.00", 100),
+            };
+            (34_200 + i, price, volume)
+        }).collect();
+        test_event_list(&tics)
+    }
+
+    #[test]
+    fn test_detect_blocks() {
+        let el = session();
+        let blocks = detect_blocks(&el, &BlockCriteria::default(), &[10.0, 60.0]);
+        assert_eq!(blocks.len(), 1);
+        let b = &blocks[0];
+        assert_eq!(b.volume, 20_000);
+        assert!(b.by_shares && b.by_notional && !b.by_percentile);
+        assert_relative_eq!(b.notional, 201_000.0);
+        assert_relative_eq!(b.moves[0].1.unwrap(), (10.05f64 / 10.0).ln() * 1e4, epsilon = 1e-9);
+        assert_relative_eq!(b.moves[0].2.unwrap(), (10.10f64 / 10.05).ln() * 1e4, epsilon = 1e-9);
+        // the session only runs 49 seconds past the block
+        assert_eq!(b.moves[1].2, None);
+
+        let by_size = BlockCriteria { min_shares: None, min_notional: Some(1e9), percentile: Some(0.98) };
+        let blocks = detect_blocks(&el, &by_size, &[1.0]);
+        assert_eq!(blocks.len(), 2);
+        assert!(blocks.iter().all(|b| b.by_percentile && !b.by_notional));
+    }
+
+    #[test]
+    fn test_uniform_sizes_have_no_percentile_blocks() {
+        let tics: Vec<(i32, &str, i32)> = (0..100).map(|i| (34_200 + i, "10.00", 100)).collect();
+        let by_size = BlockCriteria { min_shares: None, min_notional: None, percentile: Some(0.99) };
+        assert!(detect_blocks(&test_event_list(&tics), &by_size, &[1.0]).is_empty());
+    }
+
+    #[test]
+    fn test_block_report() {
+        let mut stats = EventStats::new();
+        stats.symbol_events.insert("BIG".to_string(), session());
+        stats.symbol_events.insert("SMALL".to_string(), EventList::new());
+        let report = BlockReport::new(&stats, &BlockCriteria::default(), &[10.0]).unwrap();
+        assert_eq!(report.total_blocks(), 1);
+        assert_eq!(report.get_most_blocks(5), vec![("BIG".to_string(), 1)]);
+        let big = &report.symbols["BIG"];
+        assert_eq!(big.trades, 100);
+        assert_relative_eq!(big.block_volume_share(), 20_000.0 / 30_400.0, epsilon = 1e-12);
+        assert_eq!(report.blocks_csv("d").lines().count(), 2);
+        assert!(report.summary_csv("d").lines().nth(1).unwrap().starts_with("d,BIG,100,1,30400,20000,"));
+        let none = BlockCriteria { min_shares: None, min_notional: None, percentile: None };
+        assert!(BlockReport::new(&stats, &none, &[10.0]).is_err());
+    }
+}
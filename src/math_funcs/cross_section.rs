@@ -0,0 +1,319 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// Cross sectional analysis of many symbols: previous tick returns on a common time grid,
+// sample and Ledoit-Wolf shrunk covariance, correlation, and PCA of the correlation matrix.
+
+use std::error::Error;
+use std::fmt::Write;
+use nalgebra::DMatrix;
+use rust_decimal::prelude::ToPrimitive;
+use crate::event_structs::EventList;
+use crate::nyse::base_funcs::EventStats;
+
+/// Log returns of several symbols on a shared grid, one row per interval and one column per symbol.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReturnPanel {
+    pub symbols: Vec<String>,
+    pub interval: i32,
+    pub grid: Vec<i32>,
+    pub returns: DMatrix<f64>,
+}
+
+fn trade_count(event_list: &EventList) -> usize {
+    event_list.events.values().map(|e| e.get_tic_count() as usize).sum()
+}
+
+/// Last traded price strictly before `time`.
+fn previous_tick(event_list: &EventList, time: i32) -> Option<f64> {
+    event_list.events.range(..time).next_back()
+        .and_then(|(_, e)| e.tics.last())
+        .and_then(|t| t.price.to_f64())
+}
+
+/// The `max_items` symbols with the most trades, at least `min_trades` each, most active first.
+pub fn most_active_symbols(event_stats: &EventStats, max_items: usize, min_trades: usize) -> Vec<String> {
+    let mut counts: Vec<(String, usize)> = event_stats.symbol_events.iter()
+        .map(|(s, el)| (s.clone(), trade_count(el)))
+        .filter(|(_, c)| *c >= min_trades)
+        .collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts.into_iter().take(max_items).map(|(s, _)| s).collect()
+}
+
+/// Aligns `symbols` on an `interval` second grid over the window where all of them trade,
+/// sampling the previous tick price at every grid point.
+pub fn aligned_returns(event_stats: &EventStats, symbols: &[String], interval: i32) -> Result<ReturnPanel, Box<dyn Error>> {
+    if interval <= 0 {
+        return Err("interval must be positive".into());
+    }
+    if symbols.len() < 2 {
+        return Err("need at least two symbols".into());
+    }
+    let mut lists = Vec::with_capacity(symbols.len());
+    for s in symbols {
+        let el = event_stats.symbol_events.get(s).ok_or(format!("symbol {} not found", s))?;
+        lists.push(el);
+    }
+    let first = lists.iter().filter_map(|el| el.events.keys().next()).max().ok_or("no trades")?;
+    let last = lists.iter().filter_map(|el| el.events.keys().next_back()).min().ok_or("no trades")?;
+    // grid points close intervals, the first needs a trade before it from every symbol
+    let start = (first / interval + 1) * interval;
+    let grid: Vec<i32> = (start..=last + 1).step_by(interval as usize).collect();
+    if grid.len() < 3 {
+        return Err("symbols do not overlap for three grid points".into());
+    }
+    let mut returns = DMatrix::<f64>::zeros(grid.len() - 1, symbols.len());
+    for (j, el) in lists.iter().enumerate() {
+        let prices: Vec<f64> = grid.iter().map(|g| previous_tick(el, *g).unwrap_or(0.0)).collect();
+        for (i, w) in prices.windows(2).enumerate() {
+            if w[0] > 0.0 && w[1] > 0.0 {
+                returns[(i, j)] = (w[1] / w[0]).ln();
+            }
+        }
+    }
+    Ok(ReturnPanel { symbols: symbols.to_vec(), interval, grid: grid[1..].to_vec(), returns })
+}
+
+fn centred(x: &DMatrix<f64>) -> DMatrix<f64> {
+    let mut out = x.clone();
+    for mut col in out.column_iter_mut() {
+        let mean = col.mean();
+        col.add_scalar_mut(-mean);
+    }
+    out
+}
+
+/// Sample covariance (divisor T) of the columns of `x`.
+pub fn covariance(x: &DMatrix<f64>) -> DMatrix<f64> {
+    let c = centred(x);
+    c.transpose() * &c / x.nrows().max(1) as f64
+}
+
+/// Correlation matrix from a covariance matrix, zero variance columns get zero correlation.
+pub fn correlation(cov: &DMatrix<f64>) -> DMatrix<f64> {
+    let n = cov.nrows();
+    DMatrix::from_fn(n, n, |i, j| {
+        let d = (cov[(i, i)] * cov[(j, j)]).sqrt();
+        if i == j { 1.0 } else if d > 0.0 { cov[(i, j)] / d } else { 0.0 }
+    })
+}
+
+/// Ledoit-Wolf (2004) shrinkage of the sample covariance towards a scaled identity.
+///
+/// # Returns
+///
+/// The shrunk covariance and the shrinkage intensity in [0, 1]
+pub fn ledoit_wolf(x: &DMatrix<f64>) -> (DMatrix<f64>, f64) {
+    let t = x.nrows();
+    let n = x.ncols();
+    let c = centred(x);
+    let s = c.transpose() * &c / t.max(1) as f64;
+    let mu = s.trace() / n as f64;
+    let target = DMatrix::<f64>::identity(n, n) * mu;
+    let d2 = (&s - &target).norm_squared();
+    if d2 <= 0.0 || t == 0 {
+        return (s, 0.0);
+    }
+    let mut b2 = 0.0;
+    for row in c.row_iter() {
+        let r = row.transpose();
+        b2 += (&r * r.transpose() - &s).norm_squared();
+    }
+    b2 /= (t * t) as f64;
+    let shrinkage = b2.min(d2) / d2;
+    (target * shrinkage + s * (1.0 - shrinkage), shrinkage)
+}
+
+/// Principal components, sorted by decreasing eigenvalue.
+///
+/// Column `k` of `loadings` is component `k`, signed so its loadings sum to a positive number.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pca {
+    pub eigenvalues: Vec<f64>,
+    pub explained: Vec<f64>,
+    pub loadings: DMatrix<f64>,
+}
+
+/// PCA of a symmetric covariance or correlation matrix.
+pub fn pca(matrix: &DMatrix<f64>) -> Pca {
+    let n = matrix.nrows();
+    let eigen = matrix.clone().symmetric_eigen();
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|a, b| eigen.eigenvalues[*b].total_cmp(&eigen.eigenvalues[*a]));
+    let eigenvalues: Vec<f64> = order.iter().map(|k| eigen.eigenvalues[*k].max(0.0)).collect();
+    let total: f64 = eigenvalues.iter().sum();
+    let explained = eigenvalues.iter().map(|v| if total > 0.0 { v / total } else { 0.0 }).collect();
+    let mut loadings = DMatrix::<f64>::zeros(n, n);
+    for (col, k) in order.iter().enumerate() {
+        let v = eigen.eigenvectors.column(*k);
+        let sign = if v.sum() < 0.0 { -1.0 } else { 1.0 };
+        loadings.set_column(col, &(v * sign));
+    }
+    Pca { eigenvalues, explained, loadings }
+}
+
+/// Correlation structure of the most active symbols.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrossSection {
+    pub panel: ReturnPanel,
+    pub covariance: DMatrix<f64>,
+    pub correlation: DMatrix<f64>,
+    pub shrunk_covariance: DMatrix<f64>,
+    pub shrinkage: f64,
+    pub pca: Pca,
+}
+
+impl CrossSection {
+    /// Builds the cross section of the `max_items` most active symbols with at least `min_trades`
+    /// trades over `interval` second returns. PCA is on the shrunk correlation matrix.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let cs = CrossSection::new(&stats.event_stats, 50, 60, 500)?;
+    /// println!("market factor explains {:.1}%", cs.pca.explained[0] * 100.0);
+    /// cs.write_loadings(&format!("plots/{}-loadings.csv", day), 5)?;
+    /// ```
+    pub fn new(event_stats: &EventStats, max_items: usize, interval: i32, min_trades: usize) -> Result<CrossSection, Box<dyn Error>> {
+        let symbols = most_active_symbols(event_stats, max_items, min_trades);
+        CrossSection::from_symbols(event_stats, &symbols, interval)
+    }
+
+    pub fn from_symbols(event_stats: &EventStats, symbols: &[String], interval: i32) -> Result<CrossSection, Box<dyn Error>> {
+        let panel = aligned_returns(event_stats, symbols, interval)?;
+        let covariance = covariance(&panel.returns);
+        let (shrunk_covariance, shrinkage) = ledoit_wolf(&panel.returns);
+        let pca = pca(&correlation(&shrunk_covariance));
+        Ok(CrossSection { correlation: correlation(&covariance), panel, covariance, shrunk_covariance, shrinkage, pca })
+    }
+
+    /// Loadings of the first `components` principal components as CSV, one row per symbol.
+    pub fn loadings_csv(&self, components: usize) -> String {
+        let k = components.min(self.pca.eigenvalues.len());
+        let mut out = String::from("symbol");
+        for c in 0..k {
+            write!(out, ",pc{}", c + 1).unwrap();
+        }
+        out.push('\n');
+        out.push_str("explained");
+        for c in 0..k {
+            write!(out, ",{:.6}", self.pca.explained[c]).unwrap();
+        }
+        out.push('\n');
+        for (i, symbol) in self.panel.symbols.iter().enumerate() {
+            out.push_str(symbol);
+            for c in 0..k {
+                write!(out, ",{:.6}", self.pca.loadings[(i, c)]).unwrap();
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn write_loadings(&self, file_name: &str, components: usize) -> Result<(), Box<dyn Error>> {
+        std::fs::write(file_name, self.loadings_csv(components))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_structs::test_event_list;
+    use approx::assert_relative_eq;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use rand_distr::{Distribution, Normal};
+
+    /// three symbols driven by one factor, the last one with a loading of zero
+    fn factor_stats(seed: u64) -> EventStats {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let step = Normal::new(0.0, 0.001).unwrap();
+        let betas = [("AAA", 1.0), ("BBB", 0.8), ("CCC", 0.0)];
+        let mut logp = [4.0f64, 3.0, 5.0];
+        let mut tics: Vec<Vec<(i32, String, i32)>> = vec![Vec::new(); betas.len()];
+        for secs in 34_200..34_200 + 2 * 3600 {
+            let f = step.sample(&mut rng);
+            for (j, (_, beta)) in betas.iter().enumerate() {
+                logp[j] += beta * f + 0.5 * step.sample(&mut rng);
+                if j == 1 && secs % 2 == 1 {
+                    continue;
+                }
+                tics[j].push((secs, format!("{:.4}", logp[j].exp()), 100));
+            }
+        }
+        let mut stats = EventStats::new();
+        for ((s, _), t) in betas.iter().zip(tics.iter()) {
+            stats.symbol_events.insert(s.to_string(), test_event_list(t));
+        }
+        stats
+    }
+
+    #[test]
+    fn test_cross_section() {
+        let stats = factor_stats(4);
+        assert_eq!(most_active_symbols(&stats, 2, 0), vec!["AAA".to_string(), "CCC".to_string()]);
+        let cs = CrossSection::new(&stats, 3, 60, 100).unwrap();
+        assert_eq!(cs.panel.grid.len(), cs.panel.returns.nrows());
+        assert_eq!(cs.panel.grid[0] % 60, 0);
+        let a = cs.panel.symbols.iter().position(|s| s == "AAA").unwrap();
+        let b = cs.panel.symbols.iter().position(|s| s == "BBB").unwrap();
+        let c = cs.panel.symbols.iter().position(|s| s == "CCC").unwrap();
+        assert!(cs.correlation[(a, b)] > 0.7);
+        assert!(cs.correlation[(a, c)].abs() < 0.3);
+        assert!(cs.shrinkage > 0.0 && cs.shrinkage < 1.0);
+        assert!(cs.pca.explained[0] > 0.5);
+        assert_relative_eq!(cs.pca.explained.iter().sum::<f64>(), 1.0, epsilon = 1e-10);
+        assert!(cs.pca.loadings[(a, 0)] > 0.5 && cs.pca.loadings[(c, 0)].abs() < 0.3);
+        let csv = cs.loadings_csv(2);
+        assert_eq!(csv.lines().count(), 5);
+        assert!(csv.starts_with("symbol,pc1,pc2\nexplained,"));
+    }
+
+    #[test]
+    fn test_ledoit_wolf() {
+        // identity covariance sample, shrinkage goes towards the target
+        let mut rng = StdRng::seed_from_u64(5);
+        let noise = Normal::new(0.0, 1.0).unwrap();
+        let x = DMatrix::from_fn(30, 10, |_, _| noise.sample(&mut rng));
+        let (shrunk, intensity) = ledoit_wolf(&x);
+        let s = covariance(&x);
+        assert!(intensity > 0.3);
+        assert_relative_eq!(shrunk.trace(), s.trace(), epsilon = 1e-10);
+        let off = |m: &DMatrix<f64>| m.iter().enumerate().filter(|(i, _)| i % 11 != 0).map(|(_, v)| v * v).sum::<f64>();
+        assert!(off(&shrunk) < off(&s));
+        let p = pca(&DMatrix::from_row_slice(2, 2, &[2.0, 0.0, 0.0, 1.0]));
+        assert_eq!(p.eigenvalues, vec![2.0, 1.0]);
+        assert_relative_eq!(p.explained[0], 2.0 / 3.0, epsilon = 1e-12);
+    }
+}
@@ -0,0 +1,476 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// Serial dependence diagnostics for trade-by-trade series: autocorrelation, Ljung-Box,
+// Hurst exponent (rescaled range and detrended fluctuation analysis) and Lo-MacKinlay
+// variance ratios. Hurst below 0.5 or variance ratios below 1 point to mean reversion,
+// above to trending behaviour.
+
+use std::collections::HashMap;
+use std::error::Error;
+use rust_decimal::prelude::ToPrimitive;
+use crate::event_structs::EventList;
+use crate::math_funcs::distributions::{chi2_sf, norm_cdf};
+use crate::math_funcs::pre_processing::diff_series;
+use crate::nyse::base_funcs::EventStats;
+
+/// Sample autocorrelation function for lags `0..=max_lag`, biased (divide by N) estimator.
+///
+/// # Arguments
+///
+/// * `inp` - Input series.
+/// * `max_lag` - Largest lag, must be smaller than the series length.
+///
+/// # Returns
+///
+/// `max_lag + 1` values with `acf[0] == 1`.
+pub fn acf(inp: &[f32], max_lag: usize) -> Result<Vec<f64>, Box<dyn Error>> {
+    let n = inp.len();
+    if max_lag >= n {
+        return Err(format!("lag {} needs more than {} points", max_lag, n).into());
+    }
+    let mean = inp.iter().map(|x| *x as f64).sum::<f64>() / n as f64;
+    let centred: Vec<f64> = inp.iter().map(|x| *x as f64 - mean).collect();
+    let c0 = centred.iter().map(|x| x * x).sum::<f64>();
+    if c0 <= 0.0 {
+        return Err("autocorrelation of a constant series is undefined".into());
+    }
+    Ok((0..=max_lag).map(|k| {
+        centred[k..].iter().zip(centred.iter()).map(|(a, b)| a * b).sum::<f64>() / c0
+    }).collect())
+}
+
+/// Partial autocorrelation function for lags `0..=max_lag` via the Durbin-Levinson recursion.
+///
+/// `pacf[0]` is 1 so the output lines up with `acf`.
+pub fn pacf(inp: &[f32], max_lag: usize) -> Result<Vec<f64>, Box<dyn Error>> {
+    let rho = acf(inp, max_lag)?;
+    let mut out = vec![1.0; max_lag + 1];
+    let mut phi: Vec<f64> = Vec::with_capacity(max_lag);
+    for k in 1..=max_lag {
+        let num = rho[k] - phi.iter().enumerate().map(|(j, p)| p * rho[k - 1 - j]).sum::<f64>();
+        let den = 1.0 - phi.iter().enumerate().map(|(j, p)| p * rho[j + 1]).sum::<f64>();
+        let phi_kk = if den.abs() > f64::EPSILON { num / den } else { 0.0 };
+        let prev = phi.clone();
+        for j in 0..phi.len() {
+            phi[j] = prev[j] - phi_kk * prev[prev.len() - 1 - j];
+        }
+        phi.push(phi_kk);
+        out[k] = phi_kk;
+    }
+    Ok(out)
+}
+
+/// Result of a Ljung-Box portmanteau test.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LjungBox {
+    pub statistic: f64,
+    pub lags: usize,
+    pub p_value: f64,
+}
+
+/// Ljung-Box test of the null that the first `lags` autocorrelations are jointly zero.
+///
+/// `Q = N (N + 2) sum_k rho_k^2 / (N - k)`, chi-squared with `lags` degrees of freedom under the null.
+pub fn ljung_box(inp: &[f32], lags: usize) -> Result<LjungBox, Box<dyn Error>> {
+    if lags == 0 {
+        return Err("Ljung-Box needs at least one lag".into());
+    }
+    let rho = acf(inp, lags)?;
+    let n = inp.len() as f64;
+    let statistic = n * (n + 2.0) * (1..=lags).map(|k| rho[k] * rho[k] / (n - k as f64)).sum::<f64>();
+    Ok(LjungBox { statistic, lags, p_value: chi2_sf(statistic, lags as f64) })
+}
+
+/// Least squares slope of `y` on `x`.
+fn ols_slope(x: &[f64], y: &[f64]) -> f64 {
+    let n = x.len() as f64;
+    let mx = x.iter().sum::<f64>() / n;
+    let my = y.iter().sum::<f64>() / n;
+    let sxy = x.iter().zip(y.iter()).map(|(a, b)| (a - mx) * (b - my)).sum::<f64>();
+    let sxx = x.iter().map(|a| (a - mx) * (a - mx)).sum::<f64>();
+    sxy / sxx
+}
+
+/// Geometrically spaced window sizes from `min` up to `max`, roughly four per octave.
+fn window_sizes(min: usize, max: usize) -> Vec<usize> {
+    let mut sizes: Vec<usize> = Vec::new();
+    let mut w = min as f64;
+    while w <= max as f64 {
+        let s = w.round() as usize;
+        if sizes.last() != Some(&s) {
+            sizes.push(s);
+        }
+        w *= 2f64.powf(0.25);
+    }
+    sizes
+}
+
+/// Hurst exponent by classical rescaled range analysis.
+///
+/// The series is split into non-overlapping blocks for window sizes between 8 and `N / 2`,
+/// the mean `R/S` is taken per size and `H` is the log-log slope. The estimator is biased
+/// upwards in small samples, so compare symbols against each other rather than against 0.5.
+pub fn hurst_rs(inp: &[f32]) -> Result<f64, Box<dyn Error>> {
+    let n = inp.len();
+    if n < 32 {
+        return Err(format!("rescaled range needs at least 32 points, got {}", n).into());
+    }
+    let mut log_n: Vec<f64> = Vec::new();
+    let mut log_rs: Vec<f64> = Vec::new();
+    for size in window_sizes(8, n / 2) {
+        let mut total = 0.0;
+        let mut count = 0;
+        for block in inp.chunks_exact(size) {
+            let mean = block.iter().map(|x| *x as f64).sum::<f64>() / size as f64;
+            let mut cum = 0.0;
+            let mut lo = 0.0f64;
+            let mut hi = 0.0f64;
+            let mut ss = 0.0;
+            for x in block {
+                let d = *x as f64 - mean;
+                cum += d;
+                lo = lo.min(cum);
+                hi = hi.max(cum);
+                ss += d * d;
+            }
+            let s = (ss / size as f64).sqrt();
+            if s > 0.0 {
+                total += (hi - lo) / s;
+                count += 1;
+            }
+        }
+        if count > 0 {
+            log_n.push((size as f64).ln());
+            log_rs.push((total / count as f64).ln());
+        }
+    }
+    if log_n.len() < 2 {
+        return Err("not enough non-constant blocks for rescaled range".into());
+    }
+    Ok(ols_slope(&log_n, &log_rs))
+}
+
+/// Hurst exponent by first order detrended fluctuation analysis.
+///
+/// The input is treated as increments (e.g. returns): the mean-removed cumulative sum is
+/// split into boxes for sizes between 4 and `N / 4`, a line is fitted in each box and the
+/// RMS residual `F(n)` is regressed on `n` in logs. White noise gives 0.5.
+pub fn hurst_dfa(inp: &[f32]) -> Result<f64, Box<dyn Error>> {
+    let n = inp.len();
+    if n < 32 {
+        return Err(format!("DFA needs at least 32 points, got {}", n).into());
+    }
+    let mean = inp.iter().map(|x| *x as f64).sum::<f64>() / n as f64;
+    let profile: Vec<f64> = inp.iter().scan(0.0, |acc, x| {
+        *acc += *x as f64 - mean;
+        Some(*acc)
+    }).collect();
+    let mut log_n: Vec<f64> = Vec::new();
+    let mut log_f: Vec<f64> = Vec::new();
+    for size in window_sizes(4, n / 4) {
+        // x = 0..size centred, so the fitted line has a closed form
+        let xm = (size as f64 - 1.0) / 2.0;
+        let sxx = (0..size).map(|i| (i as f64 - xm).powi(2)).sum::<f64>();
+        let mut ss = 0.0;
+        let mut boxes = 0;
+        for block in profile.chunks_exact(size) {
+            let ym = block.iter().sum::<f64>() / size as f64;
+            let slope = block.iter().enumerate().map(|(i, y)| (i as f64 - xm) * (y - ym)).sum::<f64>() / sxx;
+            ss += block.iter().enumerate().map(|(i, y)| (y - ym - slope * (i as f64 - xm)).powi(2)).sum::<f64>();
+            boxes += 1;
+        }
+        let f = (ss / (boxes * size) as f64).sqrt();
+        if f > 0.0 {
+            log_n.push((size as f64).ln());
+            log_f.push(f.ln());
+        }
+    }
+    if log_n.len() < 2 {
+        return Err("not enough fluctuating boxes for DFA".into());
+    }
+    Ok(ols_slope(&log_n, &log_f))
+}
+
+/// Lo-MacKinlay variance ratio at horizon `q`.
+///
+/// - `ratio`: Variance of overlapping `q`-period returns over `q` times the one-period variance.
+/// - `z`: Test statistic under homoskedastic increments.
+/// - `z_robust`: Heteroskedasticity-consistent statistic `z*`.
+/// - `p_value`: Two-sided p-value of `z_robust`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VarianceRatio {
+    pub q: usize,
+    pub ratio: f64,
+    pub z: f64,
+    pub z_robust: f64,
+    pub p_value: f64,
+}
+
+/// Lo-MacKinlay (1988) variance ratio test on one-period returns.
+///
+/// # Arguments
+///
+/// * `returns` - One-period returns (log price increments).
+/// * `q` - Aggregation horizon, at least 2.
+///
+/// # Example
+///
+/// ```
+/// # use feed_parser::math_funcs::diagnostics::variance_ratio;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let returns: Vec<f32> = (0..1000).map(|i| if i % 2 == 0 { 0.001 } else { -0.001 }).collect();
+/// let vr = variance_ratio(&returns, 10)?;
+/// if vr.ratio < 1.0 && vr.p_value < 0.05 { println!("mean reverting"); }
+/// # Ok(())
+/// # }
+/// ```
+pub fn variance_ratio(returns: &[f32], q: usize) -> Result<VarianceRatio, Box<dyn Error>> {
+    let t = returns.len();
+    if q < 2 || t < 2 * q {
+        return Err(format!("variance ratio at horizon {} needs at least {} returns, got {}", q, 2 * q, t).into());
+    }
+    let tf = t as f64;
+    let qf = q as f64;
+    let mu = returns.iter().map(|x| *x as f64).sum::<f64>() / tf;
+    let dev: Vec<f64> = returns.iter().map(|x| *x as f64 - mu).collect();
+    let ss = dev.iter().map(|d| d * d).sum::<f64>();
+    if ss <= 0.0 {
+        return Err("variance ratio of a constant series is undefined".into());
+    }
+    let var_a = ss / (tf - 1.0);
+    let m = qf * (tf - qf + 1.0) * (1.0 - qf / tf);
+    let mut window: f64 = dev[..q].iter().sum();
+    let mut sum_c = window * window;
+    for i in q..t {
+        window += dev[i] - dev[i - q];
+        sum_c += window * window;
+    }
+    let ratio = (sum_c / m) / var_a;
+
+    let z = (ratio - 1.0) / (2.0 * (2.0 * qf - 1.0) * (qf - 1.0) / (3.0 * qf * tf)).sqrt();
+    let theta = (1..q).map(|j| {
+        let delta = tf * (j..t).map(|i| dev[i] * dev[i] * dev[i - j] * dev[i - j]).sum::<f64>() / (ss * ss);
+        (2.0 * (qf - j as f64) / qf).powi(2) * delta
+    }).sum::<f64>();
+    let z_robust = (ratio - 1.0) / (theta / tf).sqrt();
+    Ok(VarianceRatio { q, ratio, z, z_robust, p_value: 2.0 * (1.0 - norm_cdf(z_robust.abs())) })
+}
+
+/// Diagnostics for one symbol, computed on trade-by-trade log returns and price diffs in cents.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolDiagnostics {
+    pub trades: usize,
+    pub return_acf: Vec<f64>,
+    pub return_pacf: Vec<f64>,
+    pub diff_acf: Vec<f64>,
+    pub return_ljung_box: LjungBox,
+    pub diff_ljung_box: LjungBox,
+    pub hurst_rs: f64,
+    pub hurst_dfa: f64,
+    pub variance_ratios: Vec<VarianceRatio>,
+}
+
+/// Runs every diagnostic on the tics of an `EventList`.
+///
+/// # Arguments
+///
+/// * `event_list` - Trades for one symbol.
+/// * `max_lag` - Lags used for ACF, PACF and Ljung-Box.
+/// * `horizons` - Variance ratio horizons in trades; horizons too long for the series are skipped.
+pub fn event_list_diagnostics(event_list: &EventList, max_lag: usize, horizons: &[usize]) -> Result<SymbolDiagnostics, Box<dyn Error>> {
+    let prices: Vec<f64> = event_list.get_full_time_series().iter()
+        .map(|(_, p, _)| p.to_f64().unwrap_or(0.0)).collect();
+    if prices.iter().any(|p| *p <= 0.0) {
+        return Err("non-positive trade price".into());
+    }
+    let returns: Vec<f32> = prices.windows(2).map(|w| (w[1] / w[0]).ln() as f32).collect();
+    let cents: Vec<i32> = prices.iter().map(|p| (p * 100.0).round() as i32).collect();
+    let diffs: Vec<f32> = diff_series(&cents).iter().map(|d| *d as f32).collect();
+
+    Ok(SymbolDiagnostics {
+        trades: prices.len(),
+        return_acf: acf(&returns, max_lag)?,
+        return_pacf: pacf(&returns, max_lag)?,
+        diff_acf: acf(&diffs, max_lag)?,
+        return_ljung_box: ljung_box(&returns, max_lag)?,
+        diff_ljung_box: ljung_box(&diffs, max_lag)?,
+        hurst_rs: hurst_rs(&returns)?,
+        hurst_dfa: hurst_dfa(&returns)?,
+        variance_ratios: horizons.iter().filter_map(|q| variance_ratio(&returns, *q).ok()).collect(),
+    })
+}
+
+/// Diagnostics for every symbol in `EventStats` with at least `min_trades` trades.
+///
+/// Symbols whose diagnostics cannot be computed (e.g. the price never moved) are left out.
+pub fn event_stats_diagnostics(event_stats: &EventStats, min_trades: usize, max_lag: usize,
+                               horizons: &[usize]) -> HashMap<String, SymbolDiagnostics> {
+    let mut out: HashMap<String, SymbolDiagnostics> = HashMap::new();
+    for (symbol, event_list) in event_stats.symbol_events.iter() {
+        // screen on the tic count first, most of the universe trades too thinly to be worth the work
+        if event_list.get_event_count() < min_trades.max(1) {
+            continue;
+        }
+        if let Ok(diag) = event_list_diagnostics(event_list, max_lag, horizons) {
+            out.insert(symbol.clone(), diag);
+        }
+    }
+    out
+}
+
+/// Symbols ranked by DFA Hurst exponent, lowest (most mean reverting) first.
+pub fn get_most_mean_reverting(diagnostics: &HashMap<String, SymbolDiagnostics>, n: usize) -> Vec<(String, f64)> {
+    let mut ranked: Vec<(String, f64)> = diagnostics.iter()
+        .filter(|(_, d)| d.hurst_dfa.is_finite())
+        .map(|(s, d)| (s.clone(), d.hurst_dfa)).collect();
+    ranked.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+    ranked.truncate(n);
+    ranked
+}
+
+/// Symbols ranked by DFA Hurst exponent, highest (most trending) first.
+pub fn get_most_trending(diagnostics: &HashMap<String, SymbolDiagnostics>, n: usize) -> Vec<(String, f64)> {
+    let mut ranked: Vec<(String, f64)> = diagnostics.iter()
+        .filter(|(_, d)| d.hurst_dfa.is_finite())
+        .map(|(s, d)| (s.clone(), d.hurst_dfa)).collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked.truncate(n);
+    ranked
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use approx::assert_relative_eq;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use rand_distr::{Distribution, StandardNormal};
+
+    fn noise(n: usize, seed: u64) -> Vec<f32> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..n).map(|_| StandardNormal.sample(&mut rng)).collect()
+    }
+
+    fn ar1(n: usize, phi: f32, seed: u64) -> Vec<f32> {
+        let e = noise(n, seed);
+        let mut out = vec![0.0f32; n];
+        for i in 1..n {
+            out[i] = phi * out[i - 1] + e[i];
+        }
+        out
+    }
+
+    #[test]
+    fn test_acf_pacf() {
+        let x = ar1(20_000, 0.5, 1);
+        let r = acf(&x, 3).unwrap();
+        assert_relative_eq!(r[0], 1.0, epsilon = 1e-12);
+        assert_relative_eq!(r[1], 0.5, epsilon = 0.03);
+        assert_relative_eq!(r[2], 0.25, epsilon = 0.03);
+        let p = pacf(&x, 3).unwrap();
+        assert_relative_eq!(p[1], r[1], epsilon = 1e-12);
+        assert!(p[2].abs() < 0.03);
+        assert!(p[3].abs() < 0.03);
+        assert!(acf(&[1.0, 1.0, 1.0], 1).is_err());
+        assert!(acf(&[1.0, 2.0], 2).is_err());
+    }
+
+    #[test]
+    fn test_ljung_box() {
+        let white = ljung_box(&noise(2_000, 2), 10).unwrap();
+        assert!(white.p_value > 0.01);
+        let dependent = ljung_box(&ar1(2_000, 0.3, 3), 10).unwrap();
+        assert!(dependent.p_value < 1e-6);
+        assert_eq!(dependent.lags, 10);
+    }
+
+    #[test]
+    fn test_hurst() {
+        let white = noise(8_192, 4);
+        assert_relative_eq!(hurst_dfa(&white).unwrap(), 0.5, epsilon = 0.08);
+        assert_relative_eq!(hurst_rs(&white).unwrap(), 0.55, epsilon = 0.1);
+        // first difference of noise is strongly anti-persistent
+        let e = noise(8_193, 5);
+        let reverting: Vec<f32> = e.windows(2).map(|w| w[1] - w[0]).collect();
+        assert!(hurst_dfa(&reverting).unwrap() < 0.3);
+        assert!(hurst_rs(&reverting).unwrap() < hurst_rs(&white).unwrap());
+        assert!(hurst_dfa(&ar1(8_192, 0.8, 6)).unwrap() > 0.6);
+        assert!(hurst_dfa(&white[..10]).is_err());
+    }
+
+    #[test]
+    fn test_variance_ratio() {
+        let white = variance_ratio(&noise(5_000, 7), 5).unwrap();
+        assert_relative_eq!(white.ratio, 1.0, epsilon = 0.1);
+        assert!(white.p_value > 0.01);
+        let e = noise(5_001, 8);
+        let reverting: Vec<f32> = e.windows(2).map(|w| w[1] - w[0]).collect();
+        let vr = variance_ratio(&reverting, 5).unwrap();
+        assert!(vr.ratio < 0.5);
+        assert!(vr.z < 0.0 && vr.z_robust < 0.0 && vr.p_value < 1e-6);
+        let trending = variance_ratio(&ar1(5_000, 0.5, 9), 5).unwrap();
+        assert!(trending.ratio > 1.5);
+        assert!(variance_ratio(&[0.1, 0.2, 0.3], 2).is_err());
+    }
+
+    #[test]
+    fn test_event_stats_ranking() {
+        let mut stats = EventStats::new();
+        let mut rng = StdRng::seed_from_u64(10);
+        // BOUNCE alternates around 10.00, DRIFT only moves up or holds
+        stats.init("BOUNCE");
+        stats.init("DRIFT");
+        stats.init("EMPTY");
+        stats.init("THIN");
+        let mut drift = 20.0f64;
+        for i in 0..600 {
+            let seconds = format!("10:{:02}:{:02}.000000000", i / 60, i % 60);
+            let z: f64 = StandardNormal.sample(&mut rng);
+            let bounce = 10.0 + if i % 2 == 0 { 0.05 } else { -0.05 } + 0.01 * z.round();
+            drift += 0.01 * (z.abs().round());
+            stats.update("BOUNCE", &seconds, &format!("{:.2}", bounce), 100).unwrap();
+            stats.update("DRIFT", &seconds, &format!("{:.2}", drift), 100).unwrap();
+            if i < 50 {
+                stats.update("THIN", &seconds, &format!("{:.2}", bounce), 100).unwrap();
+            }
+        }
+        let diags = event_stats_diagnostics(&stats, 100, 5, &[2, 5, 10_000]);
+        assert_eq!(diags.len(), 2);
+        assert!(!diags.contains_key("THIN"));
+        assert_eq!(diags["BOUNCE"].trades, 600);
+        assert_eq!(diags["BOUNCE"].variance_ratios.len(), 2);
+        assert!(diags["BOUNCE"].return_acf[1] < -0.5);
+        assert_eq!(get_most_mean_reverting(&diags, 1)[0].0, "BOUNCE");
+        assert_eq!(get_most_trending(&diags, 2)[0].0, "DRIFT");
+    }
+}
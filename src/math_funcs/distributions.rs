@@ -0,0 +1,284 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+// Distribution functions needed for confidence bounds and test statistics.
+// rand_distr only samples, it does not give us CDFs or quantiles.
+
+/// Complementary error function.
+///
+/// Chebyshev fit from Numerical Recipes, fractional error below 1.2e-7 everywhere.
+pub fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let ans = t * (-z * z - 1.26551223 + t * (1.00002368 + t * (0.37409196 + t * (0.09678418
+        + t * (-0.18628806 + t * (0.27886807 + t * (-1.13520398 + t * (1.48851587
+        + t * (-0.82215223 + t * 0.17087277))))))))).exp();
+    if x >= 0.0 { ans } else { 2.0 - ans }
+}
+
+/// Cumulative distribution function of the standard normal distribution.
+///
+/// # Example
+///
+/// ```
+/// # use feed_parser::math_funcs::distributions::norm_cdf;
+/// assert!((norm_cdf(0.0) - 0.5).abs() < 1e-7);
+/// ```
+pub fn norm_cdf(x: f64) -> f64 {
+    0.5 * erfc(-x / std::f64::consts::SQRT_2)
+}
+
+/// Quantile function (inverse CDF) of the standard normal distribution.
+///
+/// Uses Acklam's rational approximation, relative error below 1.2e-9.
+/// Returns `-inf` for `p <= 0` and `+inf` for `p >= 1`.
+///
+/// # Example
+///
+/// ```
+/// # use feed_parser::math_funcs::distributions::norm_ppf;
+/// assert!((norm_ppf(0.975) - 1.959964).abs() < 1e-6);
+/// ```
+pub fn norm_ppf(p: f64) -> f64 {
+    if p <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if p >= 1.0 {
+        return f64::INFINITY;
+    }
+    const A: [f64; 6] = [-3.969683028665376e+01, 2.209460984245205e+02, -2.759285104469687e+02,
+        1.38357751867269e+02, -3.066479806614716e+01, 2.506628277459239e+00];
+    const B: [f64; 5] = [-5.447609879822406e+01, 1.615858368580409e+02, -1.556989798598866e+02,
+        6.680131188771972e+01, -1.328068155288572e+01];
+    const C: [f64; 6] = [-7.784894002430293e-03, -3.223964580411365e-01, -2.400758277161838e+00,
+        -2.549732539343734e+00, 4.374664141464968e+00, 2.938163982698783e+00];
+    const D: [f64; 4] = [7.784695709041462e-03, 3.224671290700398e-01, 2.445134137142996e+00,
+        3.754408661907416e+00];
+    const P_LOW: f64 = 0.02425;
+
+    if p < P_LOW {
+        let q = (-2.0 * p.ln()).sqrt();
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    } else if p <= 1.0 - P_LOW {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    } else {
+        -norm_ppf(1.0 - p)
+    }
+}
+
+
+/// Natural log of the gamma function, Lanczos approximation (g = 7, n = 9).
+pub fn ln_gamma(x: f64) -> f64 {
+    const COEFFS: [f64; 9] = [0.999_999_999_999_809_9, 676.5203681218851, -1259.1392167224028,
+        771.323_428_777_653_1, -176.615_029_162_140_6, 12.507343278686905, -0.13857109526572012,
+        9.984_369_578_019_572e-6, 1.5056327351493116e-7];
+    if x < 0.5 {
+        // reflection formula
+        return (std::f64::consts::PI / (std::f64::consts::PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let mut sum = COEFFS[0];
+    for (i, c) in COEFFS.iter().enumerate().skip(1) {
+        sum += c / (x + i as f64);
+    }
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+/// Regularized lower incomplete gamma function `P(a, x)`.
+///
+/// Series expansion below `a + 1`, continued fraction above (Numerical Recipes `gammp`).
+pub fn gamma_p(a: f64, x: f64) -> f64 {
+    if x <= 0.0 || a <= 0.0 {
+        return 0.0;
+    }
+    if x < a + 1.0 {
+        let mut ap = a;
+        let mut del = 1.0 / a;
+        let mut sum = del;
+        for _ in 0..500 {
+            ap += 1.0;
+            del *= x / ap;
+            sum += del;
+            if del.abs() < sum.abs() * 1e-15 {
+                break;
+            }
+        }
+        (sum.ln() - x + a * x.ln() - ln_gamma(a)).exp()
+    } else {
+        1.0 - gamma_q(a, x)
+    }
+}
+
+/// Regularized upper incomplete gamma function `Q(a, x) = 1 - P(a, x)`.
+pub fn gamma_q(a: f64, x: f64) -> f64 {
+    if x <= 0.0 || a <= 0.0 {
+        return 1.0;
+    }
+    if x < a + 1.0 {
+        return 1.0 - gamma_p(a, x);
+    }
+    // modified Lentz continued fraction
+    let tiny = 1e-300;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for i in 1..500 {
+        let an = -(i as f64) * (i as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        if d.abs() < tiny {
+            d = tiny;
+        }
+        c = b + an / c;
+        if c.abs() < tiny {
+            c = tiny;
+        }
+        d = 1.0 / d;
+        let del = d * c;
+        h *= del;
+        if (del - 1.0).abs() < 1e-15 {
+            break;
+        }
+    }
+    (-x + a * x.ln() - ln_gamma(a)).exp() * h
+}
+
+/// Cumulative distribution function of the chi-squared distribution with `dof` degrees of freedom.
+pub fn chi2_cdf(x: f64, dof: f64) -> f64 {
+    gamma_p(dof / 2.0, x / 2.0)
+}
+
+/// Survival function (upper tail probability) of the chi-squared distribution, i.e. the p-value of a statistic `x`.
+///
+/// # Example
+///
+/// ```
+/// # use feed_parser::math_funcs::distributions::chi2_sf;
+/// assert!((chi2_sf(3.841459, 1.0) - 0.05).abs() < 1e-6);
+/// ```
+pub fn chi2_sf(x: f64, dof: f64) -> f64 {
+    gamma_q(dof / 2.0, x / 2.0)
+}
+
+/// Asymptotic Kolmogorov distribution tail, the p-value of a one-sample KS statistic `d` with `n` points.
+///
+/// Uses the small sample correction of Stephens (1970), `lambda = (sqrt(n) + 0.12 + 0.11 / sqrt(n)) d`.
+pub fn kolmogorov_sf(d: f64, n: usize) -> f64 {
+    let sn = (n as f64).sqrt();
+    let lambda = (sn + 0.12 + 0.11 / sn) * d;
+    if lambda < 0.2 {
+        return 1.0;
+    }
+    let mut sum = 0.0;
+    for k in 1..=100 {
+        let term = (-2.0 * (k * k) as f64 * lambda * lambda).exp();
+        sum += if k % 2 == 1 { term } else { -term };
+        if term < 1e-16 {
+            break;
+        }
+    }
+    (2.0 * sum).clamp(0.0, 1.0)
+}
+
+/// One-sample Kolmogorov-Smirnov test of `sample` against the unit exponential distribution.
+///
+/// # Returns
+///
+/// `(D statistic, p-value)`.
+pub fn ks_exponential(sample: &[f64]) -> (f64, f64) {
+    if sample.is_empty() {
+        return (f64::NAN, f64::NAN);
+    }
+    let mut sorted = sample.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let n = sorted.len() as f64;
+    let d = sorted.iter().enumerate().map(|(i, x)| {
+        let cdf = 1.0 - (-x.max(0.0)).exp();
+        ((i + 1) as f64 / n - cdf).max(cdf - i as f64 / n)
+    }).fold(0.0, f64::max);
+    (d, kolmogorov_sf(d, sorted.len()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_norm_cdf() {
+        assert_relative_eq!(norm_cdf(0.0), 0.5, epsilon = 1e-7);
+        assert_relative_eq!(norm_cdf(1.959964), 0.975, epsilon = 1e-6);
+        assert_relative_eq!(norm_cdf(-1.0), 0.158655254, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_norm_ppf() {
+        assert_relative_eq!(norm_ppf(0.5), 0.0, epsilon = 1e-9);
+        assert_relative_eq!(norm_ppf(0.975), 1.959963985, epsilon = 1e-6);
+        assert_relative_eq!(norm_ppf(0.001), -3.090232306, epsilon = 1e-6);
+        for p in [0.01, 0.2, 0.7, 0.99] {
+            assert_relative_eq!(norm_cdf(norm_ppf(p)), p, epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_ln_gamma() {
+        assert_relative_eq!(ln_gamma(1.0), 0.0, epsilon = 1e-12);
+        assert_relative_eq!(ln_gamma(5.0), 24f64.ln(), epsilon = 1e-12);
+        assert_relative_eq!(ln_gamma(0.5), std::f64::consts::PI.sqrt().ln(), epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_chi2() {
+        assert_relative_eq!(chi2_sf(3.841459, 1.0), 0.05, epsilon = 1e-6);
+        assert_relative_eq!(chi2_sf(18.307038, 10.0), 0.05, epsilon = 1e-6);
+        assert_relative_eq!(chi2_cdf(2.0, 2.0), 1.0 - (-1.0_f64).exp(), epsilon = 1e-12);
+        assert_relative_eq!(chi2_cdf(0.5, 4.0) + chi2_sf(0.5, 4.0), 1.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_kolmogorov() {
+        // 5% critical value of the limiting distribution
+        assert_relative_eq!(kolmogorov_sf(1.358 / (10_000f64).sqrt(), 10_000), 0.05, epsilon = 2e-3);
+        assert_eq!(kolmogorov_sf(0.0, 100), 1.0);
+        let quantiles: Vec<f64> = (0..1000).map(|i| -(1.0 - (i as f64 + 0.5) / 1000.0).ln()).collect();
+        let (d, p) = ks_exponential(&quantiles);
+        assert!(d < 1e-3 && p > 0.99);
+        let (_, p) = ks_exponential(&quantiles.iter().map(|x| 2.0 * x).collect::<Vec<f64>>());
+        assert!(p < 1e-6);
+    }
+}
@@ -0,0 +1,291 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// Inter-trade durations and the autoregressive conditional duration model of
+// Engle & Russell (1998). Durations are divided by a time-of-day profile before fitting
+// so the ACD only has to explain clustering, not the open/close activity pattern.
+
+use std::error::Error;
+use rust_decimal::prelude::ToPrimitive;
+use crate::event_structs::EventList;
+use crate::math_funcs::diagnostics::{ljung_box, LjungBox};
+use crate::math_funcs::optimize::{hessian_std_errors, nelder_mead_restarts};
+
+/// Durations between consecutive trades as `(time of the later trade, duration)` in seconds.
+///
+/// Trades printed with the same timestamp are treated as one arrival, so zero durations are dropped.
+pub fn trade_durations(event_list: &EventList) -> Vec<(f64, f64)> {
+    let times: Vec<f64> = event_list.get_full_time_series().iter()
+        .map(|(t, _, _)| t.to_f64().unwrap_or(0.0)).collect();
+    times.windows(2)
+        .filter(|w| w[1] > w[0])
+        .map(|w| (w[1], w[1] - w[0]))
+        .collect()
+}
+
+/// Mean duration by time of day, piecewise linear between bin centres and flat beyond the ends.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiurnalProfile {
+    pub bin_secs: f64,
+    pub centres: Vec<f64>,
+    pub means: Vec<f64>,
+}
+
+impl DiurnalProfile {
+    /// Expected duration at `time` seconds after midnight.
+    pub fn factor(&self, time: f64) -> f64 {
+        let k = self.centres.partition_point(|c| *c <= time);
+        if k == 0 {
+            return self.means[0];
+        }
+        if k == self.centres.len() {
+            return self.means[k - 1];
+        }
+        let w = (time - self.centres[k - 1]) / (self.centres[k] - self.centres[k - 1]);
+        self.means[k - 1] + w * (self.means[k] - self.means[k - 1])
+    }
+}
+
+/// Estimates the time-of-day profile from `(time, duration)` pairs using bins of `bin_secs`.
+pub fn diurnal_profile(durations: &[(f64, f64)], bin_secs: f64) -> Result<DiurnalProfile, Box<dyn Error>> {
+    if bin_secs <= 0.0 {
+        return Err("bin width must be positive".into());
+    }
+    let mut bins: std::collections::BTreeMap<i64, (f64, usize)> = std::collections::BTreeMap::new();
+    for (t, d) in durations {
+        let entry = bins.entry((t / bin_secs).floor() as i64).or_insert((0.0, 0));
+        entry.0 += d;
+        entry.1 += 1;
+    }
+    if bins.is_empty() {
+        return Err("no durations to build a profile from".into());
+    }
+    Ok(DiurnalProfile {
+        bin_secs,
+        centres: bins.keys().map(|b| (*b as f64 + 0.5) * bin_secs).collect(),
+        means: bins.values().map(|(sum, n)| sum / *n as f64).collect(),
+    })
+}
+
+/// Durations divided by the diurnal factor at their time, mean close to 1.
+pub fn deseasonalize_durations(durations: &[(f64, f64)], profile: &DiurnalProfile) -> Vec<f64> {
+    durations.iter().map(|(t, d)| d / profile.factor(*t)).collect()
+}
+
+/// ACD(1,1) fit, `x_i = psi_i e_i` with `psi_i = omega + alpha x_i-1 + beta psi_i-1`.
+///
+/// - `std_err`: Standard errors of (omega, alpha, beta) from the inverse Hessian.
+/// - `conditional_durations`: Fitted `psi_i`.
+/// - `residuals`: `e_i = x_i / psi_i`, i.i.d. unit exponential if the model is right.
+/// - `ljung_box_durations`, `ljung_box_residuals`: Serial correlation before and after the fit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AcdFit {
+    pub omega: f64,
+    pub alpha: f64,
+    pub beta: f64,
+    pub std_err: Vec<f64>,
+    pub log_likelihood: f64,
+    pub converged: bool,
+    pub conditional_durations: Vec<f64>,
+    pub residuals: Vec<f64>,
+    pub residual_mean: f64,
+    pub residual_std: f64,
+    pub ljung_box_durations: LjungBox,
+    pub ljung_box_residuals: LjungBox,
+}
+
+/// Conditional durations for given parameters, started at the sample mean.
+fn acd_filter(x: &[f64], omega: f64, alpha: f64, beta: f64) -> Vec<f64> {
+    let mut psi = Vec::with_capacity(x.len());
+    let mut prev_psi = x.iter().sum::<f64>() / x.len() as f64;
+    let mut prev_x = prev_psi;
+    for xi in x {
+        let p = omega + alpha * prev_x + beta * prev_psi;
+        psi.push(p);
+        prev_psi = p;
+        prev_x = *xi;
+    }
+    psi
+}
+
+/// Exponential negative log likelihood, NaN outside the stationary region.
+fn acd_nll(x: &[f64], omega: f64, alpha: f64, beta: f64) -> f64 {
+    if omega <= 0.0 || alpha < 0.0 || beta < 0.0 || alpha + beta >= 1.0 {
+        return f64::NAN;
+    }
+    acd_filter(x, omega, alpha, beta).iter().zip(x.iter()).map(|(p, xi)| p.ln() + xi / p).sum()
+}
+
+/// Fits an ACD(1,1) with exponential errors by maximum likelihood.
+///
+/// With non-exponential durations this is quasi maximum likelihood: the estimates stay
+/// consistent but the Hessian standard errors are only approximate.
+///
+/// # Arguments
+///
+/// * `x` - Positive (ideally deseasonalized) durations.
+/// * `lags` - Lags for the Ljung-Box diagnostics.
+pub fn fit_acd(x: &[f64], lags: usize) -> Result<AcdFit, Box<dyn Error>> {
+    if x.len() < 50 {
+        return Err(format!("ACD needs at least 50 durations, got {}", x.len()).into());
+    }
+    if x.iter().any(|v| *v <= 0.0 || !v.is_finite()) {
+        return Err("durations must be positive".into());
+    }
+    let mean = x.iter().sum::<f64>() / x.len() as f64;
+    // omega = exp(t0), alpha and beta through a softmax so that alpha + beta < 1
+    let unpack = |t: &[f64]| {
+        let den = 1.0 + t[1].exp() + t[2].exp();
+        (t[0].exp(), t[1].exp() / den, t[2].exp() / den)
+    };
+    let start = [(0.1 * mean).ln(), 0.0, 8f64.ln()];
+    let min = nelder_mead_restarts(|t| {
+        let (omega, alpha, beta) = unpack(t);
+        acd_nll(x, omega, alpha, beta)
+    }, &start, 0.5, 1e-12, 5_000)?;
+    let (omega, alpha, beta) = unpack(&min.x);
+    let std_err = hessian_std_errors(|p| acd_nll(x, p[0], p[1], p[2]), &[omega, alpha, beta], 1e-4)
+        .unwrap_or_else(|_| vec![f64::NAN; 3]);
+
+    let conditional_durations = acd_filter(x, omega, alpha, beta);
+    let residuals: Vec<f64> = x.iter().zip(conditional_durations.iter()).map(|(xi, p)| xi / p).collect();
+    let n = residuals.len() as f64;
+    let residual_mean = residuals.iter().sum::<f64>() / n;
+    let residual_std = (residuals.iter().map(|e| (e - residual_mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt();
+    let as_f32 = |v: &[f64]| -> Vec<f32> { v.iter().map(|e| *e as f32).collect() };
+    Ok(AcdFit {
+        omega,
+        alpha,
+        beta,
+        std_err,
+        log_likelihood: -min.value,
+        converged: min.converged,
+        ljung_box_durations: ljung_box(&as_f32(x), lags)?,
+        ljung_box_residuals: ljung_box(&as_f32(&residuals), lags)?,
+        conditional_durations,
+        residuals,
+        residual_mean,
+        residual_std,
+    })
+}
+
+/// Durations, diurnal profile and ACD fit for one symbol.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DurationAnalysis {
+    pub durations: Vec<(f64, f64)>,
+    pub profile: DiurnalProfile,
+    pub adjusted: Vec<f64>,
+    pub acd: AcdFit,
+}
+
+/// Runs the full duration analysis on an `EventList`.
+///
+/// # Example
+///
+/// ```ignore
+/// // 30 minute diurnal bins, 15 lags for Ljung-Box
+/// let analysis = event_list_durations(&event_list, 1800.0, 15)?;
+/// println!("alpha {:.3} beta {:.3}", analysis.acd.alpha, analysis.acd.beta);
+/// ```
+pub fn event_list_durations(event_list: &EventList, bin_secs: f64, lags: usize) -> Result<DurationAnalysis, Box<dyn Error>> {
+    let durations = trade_durations(event_list);
+    let profile = diurnal_profile(&durations, bin_secs)?;
+    let adjusted = deseasonalize_durations(&durations, &profile);
+    let acd = fit_acd(&adjusted, lags)?;
+    Ok(DurationAnalysis { durations, profile, adjusted, acd })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use approx::assert_relative_eq;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    fn simulate_acd(n: usize, omega: f64, alpha: f64, beta: f64, seed: u64) -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut psi = omega / (1.0 - alpha - beta);
+        let mut prev_x = psi;
+        (0..n).map(|_| {
+            psi = omega + alpha * prev_x + beta * psi;
+            let e: f64 = -(1.0 - rng.gen::<f64>()).ln();
+            prev_x = psi * e;
+            prev_x
+        }).collect()
+    }
+
+    #[test]
+    fn test_trade_durations() {
+        let mut el = EventList::new();
+        let _ = el.update("09:30:00.000000000", "10.0", 100);
+        let _ = el.update("09:30:00.250000000", "10.0", 100);
+        let _ = el.update("09:30:00.250000000", "10.0", 100);
+        let _ = el.update("09:30:01.750000000", "10.0", 100);
+        let durations = trade_durations(&el);
+        assert_eq!(durations.len(), 2);
+        assert_relative_eq!(durations[0].1, 0.25, epsilon = 1e-9);
+        assert_relative_eq!(durations[1].0, 34201.75, epsilon = 1e-9);
+        assert_relative_eq!(durations[1].1, 1.5, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_diurnal_profile() {
+        let durations: Vec<(f64, f64)> = (0..100).map(|i| {
+            let t = 34_200.0 + 36.0 * i as f64;
+            (t, if t < 36_000.0 { 1.0 } else { 3.0 })
+        }).collect();
+        let profile = diurnal_profile(&durations, 1800.0).unwrap();
+        assert_eq!(profile.means, vec![1.0, 3.0]);
+        assert_relative_eq!(profile.factor(0.0), 1.0);
+        assert_relative_eq!(profile.factor(35_100.0), 1.0);
+        assert_relative_eq!(profile.factor(36_000.0), 2.0);
+        assert_relative_eq!(profile.factor(50_000.0), 3.0);
+        let adjusted = deseasonalize_durations(&durations[..3], &profile);
+        assert_eq!(adjusted, vec![1.0, 1.0, 1.0]);
+        assert!(diurnal_profile(&[], 1800.0).is_err());
+    }
+
+    #[test]
+    fn test_fit_acd() {
+        let x = simulate_acd(20_000, 0.1, 0.1, 0.8, 1);
+        let fit = fit_acd(&x, 10).unwrap();
+        assert_relative_eq!(fit.alpha, 0.1, epsilon = 0.02);
+        assert_relative_eq!(fit.beta, 0.8, epsilon = 0.05);
+        assert_relative_eq!(fit.omega, 0.1, epsilon = 0.04);
+        assert!(fit.std_err.iter().all(|s| s.is_finite() && *s > 0.0 && *s < 0.1));
+        assert_relative_eq!(fit.residual_mean, 1.0, epsilon = 0.03);
+        assert!(fit.ljung_box_durations.p_value < 1e-6);
+        assert!(fit.ljung_box_residuals.p_value > 0.01);
+        assert_eq!(fit.residuals.len(), x.len());
+        assert!(fit_acd(&x[..10], 5).is_err());
+    }
+}
@@ -0,0 +1,469 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+// Smoothing and IIR filters for regularly sampled series.
+// Run these on resampled EventList data before handing it to the spectral graphs.
+
+use std::error::Error;
+use std::f64::consts::PI;
+use nalgebra::DMatrix;
+use rustfft::num_complex::Complex;
+use crate::event_structs::EventList;
+use crate::math_funcs::pre_processing::{resample_event_list, SeriesKind};
+
+/// Trailing simple moving average.
+///
+/// The first `window - 1` outputs average over the points available so far, so the
+/// output has the same length as the input.
+///
+/// # Example
+///
+/// ```
+/// # use feed_parser::math_funcs::filtering::moving_average;
+/// let out = moving_average(&[1.0, 2.0, 3.0, 4.0], 2);
+/// assert_eq!(out, vec![1.0, 1.5, 2.5, 3.5]);
+/// ```
+pub fn moving_average(inp: &[f32], window: usize) -> Vec<f32> {
+    let window = window.max(1);
+    let mut out = Vec::with_capacity(inp.len());
+    let mut sum = 0.0_f64;
+    for (idx, x) in inp.iter().enumerate() {
+        sum += *x as f64;
+        if idx >= window {
+            sum -= inp[idx - window] as f64;
+        }
+        out.push((sum / (idx + 1).min(window) as f64) as f32);
+    }
+    out
+}
+
+/// Exponential moving average with smoothing factor `2 / (span + 1)`, seeded with the first value.
+pub fn ema(inp: &[f32], span: usize) -> Vec<f32> {
+    let alpha = 2.0 / (span.max(1) as f64 + 1.0);
+    let mut out = Vec::with_capacity(inp.len());
+    let mut level = match inp.first() {
+        Some(x) => *x as f64,
+        None => return out,
+    };
+    for x in inp {
+        level += alpha * (*x as f64 - level);
+        out.push(level as f32);
+    }
+    out
+}
+
+/// Builds the Savitzky-Golay hat matrix for a window of `window` points and a polynomial of `order`.
+///
+/// Row `j` holds the weights that give the least squares polynomial's value at window position `j`.
+fn savitzky_golay_hat(window: usize, order: usize) -> Result<DMatrix<f64>, Box<dyn Error>> {
+    let half = (window / 2) as f64;
+    let a = DMatrix::from_fn(window, order + 1, |r, c| ((r as f64 - half) / half).powi(c as i32));
+    let ata_inv = (a.transpose() * &a).try_inverse().ok_or("Savitzky-Golay normal equations are singular")?;
+    Ok(&a * ata_inv * a.transpose())
+}
+
+/// Savitzky-Golay smoothing filter.
+///
+/// Fits a polynomial of degree `order` by least squares to each window of `window` points and
+/// takes its value at the centre. The first and last half windows use the polynomial fitted to
+/// the first and last full window, so the output has the same length as the input.
+///
+/// # Arguments
+///
+/// * `inp` - The series to smooth.
+/// * `window` - Odd window length, larger than `order`.
+/// * `order` - Degree of the fitted polynomial.
+///
+/// # Returns
+///
+/// * `Ok(Vec<f32>)` with the smoothed series.
+/// * `Err` if the window is even, not larger than `order`, or longer than the series.
+pub fn savitzky_golay(inp: &[f32], window: usize, order: usize) -> Result<Vec<f32>, Box<dyn Error>> {
+    if window % 2 != 1 || window <= order {
+        return Err("Savitzky-Golay window must be odd and larger than the polynomial order".into());
+    }
+    if inp.len() < window {
+        return Err(format!("series of {} points is shorter than the window of {}", inp.len(), window).into());
+    }
+    let hat = savitzky_golay_hat(window, order)?;
+    let half = window / 2;
+    let n = inp.len();
+    let weighted = |row: usize, start: usize| -> f32 {
+        (0..window).map(|k| hat[(row, k)] * inp[start + k] as f64).sum::<f64>() as f32
+    };
+
+    let mut out = Vec::with_capacity(n);
+    for row in 0..half {
+        out.push(weighted(row, 0));
+    }
+    for centre in half..n - half {
+        out.push(weighted(half, centre - half));
+    }
+    for row in half + 1..window {
+        out.push(weighted(row, n - window));
+    }
+    Ok(out)
+}
+
+/// One second order section of an IIR filter, `a[0]` is always 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Biquad {
+    pub b: [f64; 3],
+    pub a: [f64; 3],
+}
+
+impl Biquad {
+    fn response(&self, z: Complex<f64>) -> Complex<f64> {
+        let zi = z.inv();
+        (self.b[0] + zi * (self.b[1] + zi * self.b[2])) / (self.a[0] + zi * (self.a[1] + zi * self.a[2]))
+    }
+
+    // direct form II transposed state that gives a constant output for a constant input x0
+    fn steady_state(&self, x0: f64) -> [f64; 2] {
+        let gain = (self.b[0] + self.b[1] + self.b[2]) / (self.a[0] + self.a[1] + self.a[2]);
+        let y0 = gain * x0;
+        let z2 = self.b[2] * x0 - self.a[2] * y0;
+        let z1 = self.b[1] * x0 - self.a[1] * y0 + z2;
+        [z1, z2]
+    }
+}
+
+/// Pass band of a Butterworth design, cutoff frequencies in Hz.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterBand {
+    LowPass(f32),
+    HighPass(f32),
+    BandPass(f32, f32),
+}
+
+/// An IIR filter held as a cascade of second order sections.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Iir {
+    pub sections: Vec<Biquad>,
+}
+
+impl Iir {
+    /// Returns the complex frequency response at `freq` Hz for sampling frequency `fs`.
+    pub fn response(&self, freq: f32, fs: f32) -> Complex<f64> {
+        let z = Complex::from_polar(1.0, 2.0 * PI * freq as f64 / fs as f64);
+        self.sections.iter().fold(Complex::new(1.0, 0.0), |acc, s| acc * s.response(z))
+    }
+
+    fn run(&self, inp: &[f64], steady: bool) -> Vec<f64> {
+        let mut data = inp.to_vec();
+        for section in &self.sections {
+            let mut state = match (steady, data.first()) {
+                (true, Some(x0)) => section.steady_state(*x0),
+                _ => [0.0, 0.0],
+            };
+            for x in data.iter_mut() {
+                let y = section.b[0] * *x + state[0];
+                state[0] = section.b[1] * *x - section.a[1] * y + state[1];
+                state[1] = section.b[2] * *x - section.a[2] * y;
+                *x = y;
+            }
+        }
+        data
+    }
+
+    /// Filters a series forward in time, starting from rest.
+    pub fn filter(&self, inp: &[f32]) -> Vec<f32> {
+        let data: Vec<f64> = inp.iter().map(|x| *x as f64).collect();
+        self.run(&data, false).iter().map(|x| *x as f32).collect()
+    }
+
+    /// Zero-phase forward-backward filtering.
+    ///
+    /// The series is extended at both ends by an odd reflection and each pass starts from the
+    /// steady state of its first sample, which keeps start-up transients out of the result.
+    /// The magnitude response is squared and the phase response cancels.
+    pub fn filtfilt(&self, inp: &[f32]) -> Vec<f32> {
+        let n = inp.len();
+        if n < 2 {
+            return inp.to_vec();
+        }
+        let pad = (6 * self.sections.len()).min(n - 1);
+        let first = inp[0] as f64;
+        let last = inp[n - 1] as f64;
+        let mut data: Vec<f64> = Vec::with_capacity(n + 2 * pad);
+        data.extend((1..=pad).rev().map(|i| 2.0 * first - inp[i] as f64));
+        data.extend(inp.iter().map(|x| *x as f64));
+        data.extend((1..=pad).map(|i| 2.0 * last - inp[n - 1 - i] as f64));
+
+        let mut forward = self.run(&data, true);
+        forward.reverse();
+        let mut backward = self.run(&forward, true);
+        backward.reverse();
+        backward[pad..pad + n].iter().map(|x| *x as f32).collect()
+    }
+}
+
+// bilinear transform of an analog pole or zero, with k = 2 fs
+fn bilinear(s: Complex<f64>, k: f64) -> Complex<f64> {
+    (k + s) / (k - s)
+}
+
+/// Designs a digital Butterworth filter by bilinear transform of the analog prototype.
+///
+/// Cutoffs are pre-warped so the -3 dB points land on the requested frequencies. A band-pass
+/// design of `order` has `2 * order` poles. The gain is normalised to one at DC for low-pass,
+/// at Nyquist for high-pass and at the geometric centre of the band for band-pass.
+///
+/// # Arguments
+///
+/// * `order` - Order of the analog prototype, 1 or more.
+/// * `band` - The pass band, cutoffs in Hz.
+/// * `fs` - Sampling frequency in Hz, cutoffs must lie strictly between 0 and `fs / 2`.
+///
+/// # Example
+///
+/// ```
+/// # use feed_parser::math_funcs::filtering::{butterworth, FilterBand};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let prices: Vec<f32> = (0..600).map(|i| 100.0 + (i as f32 / 10.0).sin()).collect();
+/// // 1 second bars, keep periods longer than a minute
+/// let lp = butterworth(4, FilterBand::LowPass(1.0 / 60.0), 1.0)?;
+/// let smooth = lp.filtfilt(&prices);
+/// # assert_eq!(smooth.len(), prices.len());
+/// # Ok(())
+/// # }
+/// ```
+pub fn butterworth(order: usize, band: FilterBand, fs: f32) -> Result<Iir, Box<dyn Error>> {
+    if order == 0 {
+        return Err("filter order must be at least 1".into());
+    }
+    let fs = fs as f64;
+    let nyquist = fs / 2.0;
+    let check = |f: f32| -> Result<f64, Box<dyn Error>> {
+        let f = f as f64;
+        if f <= 0.0 || f >= nyquist {
+            return Err(format!("cutoff {} Hz must be between 0 and the Nyquist frequency {} Hz", f, nyquist).into());
+        }
+        Ok(f)
+    };
+    let k = 2.0 * fs;
+    let warp = |f: f64| k * (PI * f / fs).tan();
+
+    // normalised prototype poles in the left half plane
+    let prototype: Vec<Complex<f64>> = (0..order)
+        .map(|i| Complex::from_polar(1.0, PI * (2 * i + order + 1) as f64 / (2 * order) as f64))
+        .collect();
+
+    // digital zeros: analog zeros at infinity map to -1 and zeros at the origin map to +1,
+    // band-pass sections each get one of each
+    let (analog_poles, zeros, reference): (Vec<Complex<f64>>, Vec<f64>, f64) = match band {
+        FilterBand::LowPass(fc) => {
+            let wc = warp(check(fc)?);
+            (prototype.iter().map(|p| p * wc).collect(), vec![-1.0; order], 0.0)
+        }
+        FilterBand::HighPass(fc) => {
+            let wc = warp(check(fc)?);
+            (prototype.iter().map(|p| wc / p).collect(), vec![1.0; order], nyquist)
+        }
+        FilterBand::BandPass(f1, f2) => {
+            let (f1, f2) = (check(f1)?, check(f2)?);
+            if f1 >= f2 {
+                return Err("band-pass lower cutoff must be below the upper cutoff".into());
+            }
+            let (w1, w2) = (warp(f1), warp(f2));
+            let (w0, bw) = ((w1 * w2).sqrt(), w2 - w1);
+            let mut poles = Vec::with_capacity(2 * order);
+            for p in &prototype {
+                let root = (p * p * bw * bw - 4.0 * w0 * w0).sqrt();
+                poles.push((p * bw + root) / 2.0);
+                poles.push((p * bw - root) / 2.0);
+            }
+            let zeros = (0..order).flat_map(|_| [1.0, -1.0]).collect();
+            (poles, zeros, 2.0 * (w0 / k).atan() * fs / (2.0 * PI))
+        }
+    };
+
+    let mut digital: Vec<Complex<f64>> = analog_poles.iter().map(|p| bilinear(*p, k)).collect();
+
+    // pair complex poles with their conjugates, then the real poles with each other
+    digital.sort_by(|a, b| b.im.partial_cmp(&a.im).unwrap());
+    let mut pole_pairs: Vec<(Complex<f64>, Option<Complex<f64>>)> = Vec::new();
+    let mut real_poles: Vec<f64> = Vec::new();
+    for p in &digital {
+        if p.im > 1e-12 {
+            pole_pairs.push((*p, Some(p.conj())));
+        } else if p.im.abs() <= 1e-12 {
+            real_poles.push(p.re);
+        }
+    }
+    for pair in real_poles.chunks(2) {
+        match pair {
+            [a, b] => pole_pairs.push((Complex::new(*a, 0.0), Some(Complex::new(*b, 0.0)))),
+            [a] => pole_pairs.push((Complex::new(*a, 0.0), None)),
+            _ => {}
+        }
+    }
+
+    let mut zero_iter = zeros.into_iter();
+    let mut sections: Vec<Biquad> = Vec::with_capacity(pole_pairs.len());
+    for (p1, p2) in pole_pairs {
+        let section = match p2 {
+            Some(p2) => {
+                let z1 = zero_iter.next().unwrap_or(-1.0);
+                let z2 = zero_iter.next().unwrap_or(-1.0);
+                Biquad {
+                    b: [1.0, -(z1 + z2), z1 * z2],
+                    a: [1.0, -(p1 + p2).re, (p1 * p2).re],
+                }
+            }
+            None => {
+                let z1 = zero_iter.next().unwrap_or(-1.0);
+                Biquad { b: [1.0, -z1, 0.0], a: [1.0, -p1.re, 0.0] }
+            }
+        };
+        sections.push(section);
+    }
+
+    let mut iir = Iir { sections };
+    let gain = iir.response(reference as f32, fs as f32).norm();
+    if gain > 0.0 {
+        for b in iir.sections[0].b.iter_mut() {
+            *b /= gain;
+        }
+    }
+    Ok(iir)
+}
+
+/// A filter that can be applied to an `EventList` derived series.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    MovingAverage(usize),
+    Ema(usize),
+    SavitzkyGolay { window: usize, order: usize },
+    /// Causal IIR filter, applied forward only.
+    Iir(Iir),
+    /// IIR filter applied forward and backward for zero phase distortion.
+    ZeroPhase(Iir),
+}
+
+impl Filter {
+    /// Applies the filter to a regularly sampled series.
+    pub fn apply(&self, inp: &[f32]) -> Result<Vec<f32>, Box<dyn Error>> {
+        match self {
+            Filter::MovingAverage(window) => Ok(moving_average(inp, *window)),
+            Filter::Ema(span) => Ok(ema(inp, *span)),
+            Filter::SavitzkyGolay { window, order } => savitzky_golay(inp, *window, *order),
+            Filter::Iir(iir) => Ok(iir.filter(inp)),
+            Filter::ZeroPhase(iir) => Ok(iir.filtfilt(inp)),
+        }
+    }
+}
+
+/// Resamples an `EventList` and applies a filter to the result.
+///
+/// Butterworth filters should be designed with `fs = 1.0 / interval`.
+///
+/// # Example
+///
+/// ```ignore
+/// let lp = butterworth(4, FilterBand::LowPass(1.0 / 300.0), 1.0)?;
+/// let smooth = filter_event_list(&event_list, 1, SeriesKind::Price, &Filter::ZeroPhase(lp))?;
+/// ```
+pub fn filter_event_list(event_list: &EventList, interval: i32, kind: SeriesKind, filter: &Filter) -> Result<Vec<f32>, Box<dyn Error>> {
+    filter.apply(&resample_event_list(event_list, interval, kind))
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    fn sine(samples: usize, freq: f32, fs: f32) -> Vec<f32> {
+        (0..samples).map(|i| (2.0 * std::f32::consts::PI * freq * i as f32 / fs).sin()).collect()
+    }
+
+    #[test]
+    fn test_moving_average_and_ema() {
+        assert_eq!(moving_average(&[1.0, 2.0, 3.0, 4.0], 2), vec![1.0, 1.5, 2.5, 3.5]);
+        let out = ema(&[2.0, 2.0, 5.0], 3);
+        assert_eq!(out[..2], [2.0, 2.0]);
+        assert_relative_eq!(out[2], 3.5);
+        assert!(ema(&[], 3).is_empty());
+    }
+
+    #[test]
+    fn test_savitzky_golay_preserves_polynomial() {
+        let inp: Vec<f32> = (0..20).map(|i| 0.5 * (i * i) as f32 - 3.0 * i as f32 + 1.0).collect();
+        let out = savitzky_golay(&inp, 7, 2).unwrap();
+        assert_eq!(out.len(), inp.len());
+        for (a, b) in out.iter().zip(inp.iter()) {
+            assert_relative_eq!(*a, *b, epsilon = 1e-3);
+        }
+        assert!(savitzky_golay(&inp, 6, 2).is_err());
+        assert!(savitzky_golay(&inp[..5], 7, 2).is_err());
+    }
+
+    #[test]
+    fn test_butterworth_responses() {
+        let lp = butterworth(4, FilterBand::LowPass(0.1), 1.0).unwrap();
+        assert_eq!(lp.sections.len(), 2);
+        assert_relative_eq!(lp.response(0.0, 1.0).norm(), 1.0, epsilon = 1e-9);
+        assert_relative_eq!(lp.response(0.1, 1.0).norm(), 0.5_f64.sqrt(), epsilon = 1e-6);
+        assert!(lp.response(0.4, 1.0).norm() < 1e-3);
+
+        let hp = butterworth(3, FilterBand::HighPass(0.1), 1.0).unwrap();
+        assert_eq!(hp.sections.len(), 2);
+        assert!(hp.response(0.0, 1.0).norm() < 1e-9);
+        assert_relative_eq!(hp.response(0.1, 1.0).norm(), 0.5_f64.sqrt(), epsilon = 1e-6);
+
+        let bp = butterworth(2, FilterBand::BandPass(0.05, 0.2), 1.0).unwrap();
+        assert_eq!(bp.sections.len(), 2);
+        assert_relative_eq!(bp.response(0.05, 1.0).norm(), 0.5_f64.sqrt(), epsilon = 1e-6);
+        assert_relative_eq!(bp.response(0.2, 1.0).norm(), 0.5_f64.sqrt(), epsilon = 1e-6);
+        assert!(bp.response(0.0, 1.0).norm() < 1e-9);
+
+        assert!(butterworth(2, FilterBand::LowPass(0.6), 1.0).is_err());
+        assert!(butterworth(2, FilterBand::BandPass(0.2, 0.1), 1.0).is_err());
+        assert!(butterworth(0, FilterBand::LowPass(0.1), 1.0).is_err());
+    }
+
+    #[test]
+    fn test_filtfilt_zero_phase() {
+        let slow = sine(400, 0.01, 1.0);
+        let fast = sine(400, 0.3, 1.0);
+        let noisy: Vec<f32> = slow.iter().zip(fast.iter()).map(|(a, b)| 10.0 + a + 0.5 * b).collect();
+        let lp = butterworth(4, FilterBand::LowPass(0.05), 1.0).unwrap();
+        let smooth = Filter::ZeroPhase(lp.clone()).apply(&noisy).unwrap();
+        assert_eq!(smooth.len(), noisy.len());
+        for i in 50..350 {
+            assert_relative_eq!(smooth[i], 10.0 + slow[i], epsilon = 0.02);
+        }
+        // the causal filter lags, the zero phase one does not
+        let causal = lp.filter(&noisy);
+        assert!((causal[200] - 10.0 - slow[200]).abs() > 0.05);
+    }
+}
@@ -0,0 +1,375 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// GARCH(1,1), GJR-GARCH(1,1) and EGARCH(1,1) on demeaned returns, estimated by Gaussian
+// quasi maximum likelihood. omega is searched on a log scale so the fit does not depend on
+// whether returns are in fractions or percent, and standard errors use the
+// Bollerslev-Wooldridge sandwich so they stay valid for fat tailed intraday returns.
+
+use std::error::Error;
+use std::f64::consts::PI;
+use nalgebra::DMatrix;
+use rust_decimal::prelude::ToPrimitive;
+use crate::event_structs::EventList;
+use crate::math_funcs::diagnostics::{ljung_box, LjungBox};
+use crate::math_funcs::optimize::{nelder_mead_restarts, numerical_hessian};
+
+/// Conditional variance specifications.
+///
+/// - `Garch`: `s2_t = omega + alpha e_t-1^2 + beta s2_t-1`.
+/// - `GjrGarch`: adds `gamma e_t-1^2` when `e_t-1 < 0`.
+/// - `Egarch`: `ln s2_t = omega + alpha (|z_t-1| - E|z|) + gamma z_t-1 + beta ln s2_t-1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GarchModel {
+    Garch,
+    GjrGarch,
+    Egarch,
+}
+
+impl GarchModel {
+    fn parameter_count(&self) -> usize {
+        match self {
+            GarchModel::Garch => 3,
+            GarchModel::GjrGarch | GarchModel::Egarch => 4,
+        }
+    }
+}
+
+const ABS_Z_MEAN: f64 = 0.797_884_560_802_865_4; // sqrt(2 / pi)
+
+/// Parameters as (omega, alpha, beta, gamma) from the search vector, whose first entry is ln(omega)
+/// for the GARCH and GJR models and omega itself for EGARCH.
+fn unpack(model: GarchModel, theta: &[f64]) -> (f64, f64, f64, f64) {
+    let gamma = if model == GarchModel::Garch { 0.0 } else { theta[3] };
+    match model {
+        GarchModel::Egarch => (theta[0], theta[1], theta[2], gamma),
+        _ => (theta[0].exp(), theta[1], theta[2], gamma),
+    }
+}
+
+fn feasible(model: GarchModel, omega: f64, alpha: f64, beta: f64, gamma: f64) -> bool {
+    match model {
+        GarchModel::Garch => omega > 0.0 && alpha >= 0.0 && beta >= 0.0 && alpha + beta < 1.0,
+        GarchModel::GjrGarch => omega > 0.0 && alpha >= 0.0 && alpha + gamma >= 0.0 && beta >= 0.0
+            && alpha + gamma / 2.0 + beta < 1.0,
+        GarchModel::Egarch => beta.abs() < 1.0,
+    }
+}
+
+/// Conditional variances for demeaned returns `e`, started at the sample variance.
+fn variance_path(model: GarchModel, e: &[f64], omega: f64, alpha: f64, beta: f64, gamma: f64) -> Vec<f64> {
+    let mut s2 = e.iter().map(|x| x * x).sum::<f64>() / e.len() as f64;
+    let mut out = Vec::with_capacity(e.len());
+    for t in 0..e.len() {
+        if t > 0 {
+            s2 = next_variance(model, e[t - 1], s2, omega, alpha, beta, gamma);
+        }
+        out.push(s2);
+    }
+    out
+}
+
+fn next_variance(model: GarchModel, e: f64, s2: f64, omega: f64, alpha: f64, beta: f64, gamma: f64) -> f64 {
+    match model {
+        GarchModel::Garch => omega + alpha * e * e + beta * s2,
+        GarchModel::GjrGarch => omega + (alpha + if e < 0.0 { gamma } else { 0.0 }) * e * e + beta * s2,
+        GarchModel::Egarch => {
+            let z = e / s2.sqrt();
+            (omega + alpha * (z.abs() - ABS_Z_MEAN) + gamma * z + beta * s2.ln()).exp()
+        }
+    }
+}
+
+/// Per observation Gaussian log likelihood contributions, NaN when infeasible.
+fn contributions(model: GarchModel, e: &[f64], theta: &[f64]) -> Vec<f64> {
+    let (omega, alpha, beta, gamma) = unpack(model, theta);
+    if !feasible(model, omega, alpha, beta, gamma) {
+        return vec![f64::NAN; e.len()];
+    }
+    variance_path(model, e, omega, alpha, beta, gamma).iter().zip(e.iter())
+        .map(|(s2, x)| -0.5 * ((2.0 * PI).ln() + s2.ln() + x * x / s2))
+        .collect()
+}
+
+/// Quasi maximum likelihood GARCH family fit.
+///
+/// - `mu`: Sample mean removed before fitting.
+/// - `std_err`: Robust standard errors of (omega, alpha, beta[, gamma]).
+/// - `persistence`: `alpha + beta`, `alpha + gamma / 2 + beta` or `beta` (EGARCH).
+/// - `conditional_variance`, `standardized_residuals`: In sample fit.
+/// - `ljung_box_residuals`, `ljung_box_squared`: Serial correlation left in `z` and `z^2`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GarchFit {
+    pub model: GarchModel,
+    pub mu: f64,
+    pub omega: f64,
+    pub alpha: f64,
+    pub beta: f64,
+    pub gamma: f64,
+    pub std_err: Vec<f64>,
+    pub log_likelihood: f64,
+    pub aic: f64,
+    pub bic: f64,
+    pub persistence: f64,
+    pub converged: bool,
+    pub conditional_variance: Vec<f32>,
+    pub standardized_residuals: Vec<f32>,
+    pub residual_skewness: f64,
+    pub residual_kurtosis: f64,
+    pub ljung_box_residuals: LjungBox,
+    pub ljung_box_squared: LjungBox,
+    last_residual: f64,
+    last_variance: f64,
+}
+
+impl GarchFit {
+    /// Conditional variance forecasts for steps `1..=horizon` after the sample.
+    ///
+    /// GARCH and GJR use the exact recursion towards the unconditional variance; for EGARCH
+    /// the log variance is projected, which understates the mean variance slightly at long horizons.
+    pub fn forecast(&self, horizon: usize) -> Vec<f64> {
+        let mut out = Vec::with_capacity(horizon);
+        if horizon == 0 {
+            return out;
+        }
+        let first = next_variance(self.model, self.last_residual, self.last_variance, self.omega, self.alpha, self.beta, self.gamma);
+        out.push(first);
+        for _ in 1..horizon {
+            let prev = out[out.len() - 1];
+            out.push(match self.model {
+                GarchModel::Garch | GarchModel::GjrGarch => self.omega + self.persistence * prev,
+                GarchModel::Egarch => (self.omega + self.beta * prev.ln()).exp(),
+            });
+        }
+        out
+    }
+}
+
+/// Fits a GARCH family model to `returns` by Gaussian quasi maximum likelihood.
+///
+/// # Arguments
+///
+/// * `returns` - Log returns, demeaned internally.
+/// * `model` - Variance specification.
+/// * `lags` - Lags for the Ljung-Box tests on the standardized residuals.
+pub fn fit_garch(returns: &[f32], model: GarchModel, lags: usize) -> Result<GarchFit, Box<dyn Error>> {
+    let n = returns.len();
+    if n < 100 {
+        return Err(format!("GARCH needs at least 100 returns, got {}", n).into());
+    }
+    let mu = returns.iter().map(|r| *r as f64).sum::<f64>() / n as f64;
+    let e: Vec<f64> = returns.iter().map(|r| *r as f64 - mu).collect();
+    let var = e.iter().map(|x| x * x).sum::<f64>() / n as f64;
+    if var <= 0.0 {
+        return Err("returns have no variance".into());
+    }
+    let start: Vec<f64> = match model {
+        GarchModel::Garch => vec![(0.05 * var).ln(), 0.05, 0.9],
+        GarchModel::GjrGarch => vec![(0.05 * var).ln(), 0.03, 0.9, 0.04],
+        GarchModel::Egarch => vec![0.05 * var.ln(), 0.1, 0.95, 0.0],
+    };
+    let nll = |theta: &[f64]| -contributions(model, &e, theta).iter().sum::<f64>();
+    let min = nelder_mead_restarts(nll, &start, 0.2, 1e-12, 10_000)?;
+    let theta = min.x.clone();
+    let (omega, alpha, beta, gamma) = unpack(model, &theta);
+
+    // sandwich H^-1 J H^-1 with J the outer product of per observation scores
+    let k = theta.len();
+    let hessian = numerical_hessian(nll, &theta, 1e-4);
+    let mut scores = DMatrix::zeros(n, k);
+    for i in 0..k {
+        let h = 1e-5 * theta[i].abs().max(1.0);
+        let mut up = theta.clone();
+        let mut down = theta.clone();
+        up[i] += h;
+        down[i] -= h;
+        let cu = contributions(model, &e, &up);
+        let cd = contributions(model, &e, &down);
+        for t in 0..n {
+            scores[(t, i)] = (cu[t] - cd[t]) / (2.0 * h);
+        }
+    }
+    let opg = scores.transpose() * &scores;
+    let mut std_err: Vec<f64> = match hessian.try_inverse() {
+        Some(h_inv) => {
+            let cov = &h_inv * opg * &h_inv;
+            (0..k).map(|i| if cov[(i, i)] > 0.0 { cov[(i, i)].sqrt() } else { f64::NAN }).collect()
+        }
+        None => vec![f64::NAN; k],
+    };
+    if model != GarchModel::Egarch {
+        // delta method from ln(omega) to omega
+        std_err[0] *= omega;
+    }
+
+    let variance = variance_path(model, &e, omega, alpha, beta, gamma);
+    let z: Vec<f64> = e.iter().zip(variance.iter()).map(|(x, s2)| x / s2.sqrt()).collect();
+    let nf = n as f64;
+    let z_mean = z.iter().sum::<f64>() / nf;
+    let z_var = z.iter().map(|v| (v - z_mean).powi(2)).sum::<f64>() / nf;
+    let residual_skewness = z.iter().map(|v| (v - z_mean).powi(3)).sum::<f64>() / nf / z_var.powf(1.5);
+    let residual_kurtosis = z.iter().map(|v| (v - z_mean).powi(4)).sum::<f64>() / nf / (z_var * z_var);
+    let standardized_residuals: Vec<f32> = z.iter().map(|v| *v as f32).collect();
+    let squared: Vec<f32> = z.iter().map(|v| (v * v) as f32).collect();
+    let log_likelihood = -min.value;
+    let persistence = match model {
+        GarchModel::Garch => alpha + beta,
+        GarchModel::GjrGarch => alpha + gamma / 2.0 + beta,
+        GarchModel::Egarch => beta,
+    };
+    let p = model.parameter_count() as f64;
+    Ok(GarchFit {
+        model,
+        mu,
+        omega,
+        alpha,
+        beta,
+        gamma,
+        std_err,
+        log_likelihood,
+        aic: 2.0 * p - 2.0 * log_likelihood,
+        bic: p * nf.ln() - 2.0 * log_likelihood,
+        persistence,
+        converged: min.converged,
+        conditional_variance: variance.iter().map(|v| *v as f32).collect(),
+        ljung_box_residuals: ljung_box(&standardized_residuals, lags)?,
+        ljung_box_squared: ljung_box(&squared, lags)?,
+        standardized_residuals,
+        residual_skewness,
+        residual_kurtosis,
+        last_residual: e[n - 1],
+        last_variance: variance[n - 1],
+    })
+}
+
+/// Log returns between consecutive `interval` second bar closes.
+pub fn bar_returns(event_list: &EventList, interval: i32) -> Vec<f32> {
+    let closes: Vec<f64> = event_list.get_bars(interval).iter().map(|b| b.close.to_f64().unwrap_or(0.0)).collect();
+    closes.windows(2)
+        .filter(|w| w[0] > 0.0 && w[1] > 0.0)
+        .map(|w| (w[1] / w[0]).ln() as f32)
+        .collect()
+}
+
+/// Fits `model` to the `interval` second bar returns of an `EventList`.
+///
+/// # Example
+///
+/// ```ignore
+/// let fit = event_list_garch(&event_list, 60, GarchModel::GjrGarch, 10)?;
+/// let next_hour = fit.forecast(60);
+/// ```
+pub fn event_list_garch(event_list: &EventList, interval: i32, model: GarchModel, lags: usize) -> Result<GarchFit, Box<dyn Error>> {
+    fit_garch(&bar_returns(event_list, interval), model, lags)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_structs::test_event_list;
+    use approx::assert_relative_eq;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use rand_distr::{Distribution, StandardNormal};
+
+    fn simulate(model: GarchModel, n: usize, params: (f64, f64, f64, f64), scale: f64, seed: u64) -> Vec<f32> {
+        let (omega, alpha, beta, gamma) = params;
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut s2 = match model {
+            GarchModel::Egarch => (omega / (1.0 - beta)).exp(),
+            _ => omega / (1.0 - alpha - gamma / 2.0 - beta),
+        };
+        let mut e = 0.0;
+        let mut out = Vec::with_capacity(n);
+        for t in 0..n + 500 {
+            if t > 0 {
+                s2 = next_variance(model, e, s2, omega, alpha, beta, gamma);
+            }
+            let z: f64 = StandardNormal.sample(&mut rng);
+            e = s2.sqrt() * z;
+            if t >= 500 {
+                out.push((scale * e) as f32);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_garch() {
+        let returns = simulate(GarchModel::Garch, 8_000, (0.05, 0.1, 0.85, 0.0), 0.001, 1);
+        let fit = fit_garch(&returns, GarchModel::Garch, 10).unwrap();
+        assert_relative_eq!(fit.alpha, 0.1, epsilon = 0.03);
+        assert_relative_eq!(fit.beta, 0.85, epsilon = 0.04);
+        assert_relative_eq!(fit.omega, 0.05e-6, max_relative = 0.4);
+        assert!(fit.std_err.iter().all(|s| s.is_finite() && *s > 0.0));
+        assert!(fit.std_err[1] < 0.03);
+        assert!(fit.ljung_box_squared.p_value > 0.01);
+        assert_relative_eq!(fit.residual_kurtosis, 3.0, epsilon = 0.3);
+        let forecast = fit.forecast(2_000);
+        assert_eq!(forecast.len(), 2_000);
+        assert_relative_eq!(forecast[1_999], fit.omega / (1.0 - fit.persistence), max_relative = 1e-3);
+        assert!(fit_garch(&returns[..50], GarchModel::Garch, 10).is_err());
+    }
+
+    #[test]
+    fn test_gjr_and_egarch() {
+        let returns = simulate(GarchModel::GjrGarch, 8_000, (0.05, 0.03, 0.85, 0.15), 1.0, 2);
+        let gjr = fit_garch(&returns, GarchModel::GjrGarch, 10).unwrap();
+        assert_relative_eq!(gjr.gamma, 0.15, epsilon = 0.05);
+        assert_relative_eq!(gjr.beta, 0.85, epsilon = 0.05);
+        let plain = fit_garch(&returns, GarchModel::Garch, 10).unwrap();
+        assert!(gjr.aic < plain.aic);
+
+        let returns = simulate(GarchModel::Egarch, 8_000, (-0.05, 0.2, 0.95, -0.1), 1.0, 3);
+        let egarch = fit_garch(&returns, GarchModel::Egarch, 10).unwrap();
+        assert_relative_eq!(egarch.alpha, 0.2, epsilon = 0.05);
+        assert_relative_eq!(egarch.gamma, -0.1, epsilon = 0.04);
+        assert_relative_eq!(egarch.beta, 0.95, epsilon = 0.03);
+        assert!(egarch.forecast(5).iter().all(|v| *v > 0.0));
+    }
+
+    #[test]
+    fn test_event_list_garch() {
+        let returns = simulate(GarchModel::Garch, 600, (0.05, 0.1, 0.85, 0.0), 0.001, 4);
+        let mut log_p = 50f64.ln();
+        let mut tics = Vec::with_capacity(returns.len());
+        for (i, r) in returns.iter().enumerate() {
+            log_p += *r as f64;
+            tics.push((34_200 + 10 * i as i32, format!("{:.6}", log_p.exp()), 100));
+        }
+        let el = test_event_list(&tics);
+        assert_eq!(bar_returns(&el, 10).len(), 599);
+        let fit = event_list_garch(&el, 10, GarchModel::Garch, 5).unwrap();
+        assert_eq!(fit.conditional_variance.len(), 599);
+        assert!(fit.persistence < 1.0);
+    }
+}
@@ -0,0 +1,390 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// Multivariate Hawkes process with exponential kernels and a common decay,
+//   lambda_m(t) = mu_m + sum_n alpha_mn sum_{t_j^n < t} exp(-beta (t - t_j^n)).
+// One dimension models all trade arrivals, two dimensions buyer and seller initiated trades.
+// Events are (time, dimension) pairs with times measured from the start of the window.
+
+use std::error::Error;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rust_decimal::prelude::ToPrimitive;
+use crate::event_structs::{EventList, TradeSide};
+use crate::math_funcs::distributions::ks_exponential;
+use crate::math_funcs::optimize::{hessian_std_errors, nelder_mead_restarts};
+
+/// Hawkes parameters, `alpha[m][n]` being the jump in the intensity of `m` caused by an event of `n`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HawkesParams {
+    pub mu: Vec<f64>,
+    pub alpha: Vec<Vec<f64>>,
+    pub beta: f64,
+}
+
+impl HawkesParams {
+    pub fn dims(&self) -> usize {
+        self.mu.len()
+    }
+
+    /// Spectral radius of `alpha / beta`, the expected number of children per event.
+    /// The process is stationary when this is below 1.
+    pub fn branching_ratio(&self) -> f64 {
+        let d = self.dims();
+        let mut v = vec![1.0; d];
+        let mut radius = 0.0;
+        for _ in 0..200 {
+            let next: Vec<f64> = (0..d).map(|m| (0..d).map(|n| self.alpha[m][n] * v[n]).sum::<f64>() / self.beta).collect();
+            radius = next.iter().cloned().fold(0.0, f64::max);
+            if radius <= 0.0 {
+                return 0.0;
+            }
+            v = next.iter().map(|x| x / radius).collect();
+        }
+        radius
+    }
+
+    /// Log likelihood of time ordered `events` observed over `[0, horizon]`.
+    pub fn log_likelihood(&self, events: &[(f64, usize)], horizon: f64) -> f64 {
+        let d = self.dims();
+        let mut excitation = vec![0.0; d];
+        let mut kernel_mass = vec![0.0; d];
+        let mut last = 0.0;
+        let mut ll = 0.0;
+        for (t, m) in events {
+            let decay = (-self.beta * (t - last)).exp();
+            excitation.iter_mut().for_each(|s| *s *= decay);
+            let lambda = self.mu[*m] + (0..d).map(|n| self.alpha[*m][n] * excitation[n]).sum::<f64>();
+            if lambda <= 0.0 {
+                return f64::NEG_INFINITY;
+            }
+            ll += lambda.ln();
+            excitation[*m] += 1.0;
+            kernel_mass[*m] += 1.0 - (-self.beta * (horizon - t)).exp();
+            last = *t;
+        }
+        for m in 0..d {
+            ll -= self.mu[m] * horizon + (0..d).map(|n| self.alpha[m][n] * kernel_mass[n]).sum::<f64>() / self.beta;
+        }
+        ll
+    }
+
+    /// Time rescaling residuals, the compensator between consecutive events of each dimension.
+    /// Under a correct model they are i.i.d. unit exponential.
+    pub fn residuals(&self, events: &[(f64, usize)]) -> Vec<Vec<f64>> {
+        let d = self.dims();
+        let mut excitation = vec![0.0; d];
+        let mut compensator = vec![0.0; d];
+        let mut out: Vec<Vec<f64>> = vec![Vec::new(); d];
+        let mut last = 0.0;
+        for (t, m) in events {
+            let dt = t - last;
+            let decay = (-self.beta * dt).exp();
+            for (k, c) in compensator.iter_mut().enumerate() {
+                *c += self.mu[k] * dt
+                    + (0..d).map(|n| self.alpha[k][n] * excitation[n]).sum::<f64>() * (1.0 - decay) / self.beta;
+            }
+            excitation.iter_mut().for_each(|s| *s *= decay);
+            out[*m].push(compensator[*m]);
+            compensator[*m] = 0.0;
+            excitation[*m] += 1.0;
+            last = *t;
+        }
+        out
+    }
+
+    /// Simulates the process on `[0, horizon]` by Ogata thinning.
+    ///
+    /// The intensity only decays between events, so its value just after the last event
+    /// bounds it until the next one.
+    pub fn simulate(&self, horizon: f64, seed: u64) -> Vec<(f64, usize)> {
+        let d = self.dims();
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut excitation = vec![0.0; d];
+        let mut events: Vec<(f64, usize)> = Vec::new();
+        let mut t = 0.0;
+        loop {
+            let bound: f64 = (0..d).map(|m| self.mu[m] + (0..d).map(|n| self.alpha[m][n] * excitation[n]).sum::<f64>()).sum();
+            if bound <= 0.0 {
+                break;
+            }
+            let wait = -(1.0 - rng.gen::<f64>()).ln() / bound;
+            t += wait;
+            if t > horizon {
+                break;
+            }
+            let decay = (-self.beta * wait).exp();
+            excitation.iter_mut().for_each(|s| *s *= decay);
+            let u = rng.gen::<f64>() * bound;
+            let mut cumulative = 0.0;
+            for m in 0..d {
+                cumulative += self.mu[m] + (0..d).map(|n| self.alpha[m][n] * excitation[n]).sum::<f64>();
+                if u <= cumulative {
+                    events.push((t, m));
+                    excitation[m] += 1.0;
+                    break;
+                }
+            }
+        }
+        events
+    }
+}
+
+/// Maximum likelihood Hawkes fit.
+///
+/// - `start`, `horizon`: Observation window in seconds after midnight and its length.
+/// - `std_err`: Standard errors in the order mu, alpha (row major), beta.
+/// - `residuals`: Time rescaling residuals per dimension.
+/// - `ks_statistic`, `ks_p_value`: Kolmogorov-Smirnov test of the residuals against Exp(1), per dimension.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HawkesFit {
+    pub params: HawkesParams,
+    pub start: f64,
+    pub horizon: f64,
+    pub counts: Vec<usize>,
+    pub std_err: Vec<f64>,
+    pub log_likelihood: f64,
+    pub converged: bool,
+    pub branching_ratio: f64,
+    pub residuals: Vec<Vec<f64>>,
+    pub ks_statistic: Vec<f64>,
+    pub ks_p_value: Vec<f64>,
+}
+
+impl HawkesFit {
+    /// Synthetic arrivals from the fitted process over the same window, as (seconds after midnight, dimension).
+    pub fn simulate(&self, seed: u64) -> Vec<(f64, usize)> {
+        self.params.simulate(self.horizon, seed).into_iter().map(|(t, m)| (self.start + t, m)).collect()
+    }
+}
+
+fn unpack(x: &[f64], dims: usize) -> HawkesParams {
+    HawkesParams {
+        mu: x[..dims].to_vec(),
+        alpha: (0..dims).map(|m| x[dims + m * dims..dims + (m + 1) * dims].to_vec()).collect(),
+        beta: x[dims + dims * dims],
+    }
+}
+
+/// Negative log likelihood over the natural parameters, NaN outside the stationary region.
+fn hawkes_nll(x: &[f64], dims: usize, events: &[(f64, usize)], horizon: f64) -> f64 {
+    if x.iter().any(|v| *v < 0.0) || x[..dims].iter().any(|v| *v <= 0.0) || x[x.len() - 1] <= 0.0 {
+        return f64::NAN;
+    }
+    let params = unpack(x, dims);
+    if params.branching_ratio() >= 1.0 {
+        return f64::NAN;
+    }
+    -params.log_likelihood(events, horizon)
+}
+
+/// Fits a `dims` dimensional Hawkes process to time ordered `events` on `[0, horizon]`.
+///
+/// The search runs on log parameters; standard errors come from the Hessian of the
+/// likelihood in the natural parameters and are NaN for parameters estimated at zero.
+pub fn fit_hawkes(events: &[(f64, usize)], dims: usize, horizon: f64) -> Result<HawkesFit, Box<dyn Error>> {
+    if dims == 0 || horizon <= 0.0 {
+        return Err("need at least one dimension and a positive horizon".into());
+    }
+    let mut counts = vec![0usize; dims];
+    for (t, m) in events {
+        if *m >= dims || *t < 0.0 || *t > horizon {
+            return Err(format!("event ({}, {}) is outside {} dimensions on [0, {}]", t, m, dims, horizon).into());
+        }
+        counts[*m] += 1;
+    }
+    if counts.iter().any(|c| *c < 10) {
+        return Err(format!("need at least 10 events per dimension, got {:?}", counts).into());
+    }
+    if events.windows(2).any(|w| w[1].0 < w[0].0) {
+        return Err("events must be in time order".into());
+    }
+
+    let rate = events.len() as f64 / horizon;
+    let beta0 = 2.0 * rate / dims as f64;
+    let mut start: Vec<f64> = counts.iter().map(|c| (0.5 * *c as f64 / horizon).ln()).collect();
+    for m in 0..dims {
+        for n in 0..dims {
+            start.push((if m == n { 0.3 } else { 0.1 } * beta0).ln());
+        }
+    }
+    start.push(beta0.ln());
+    let min = nelder_mead_restarts(|t| {
+        let x: Vec<f64> = t.iter().map(|v| v.exp()).collect();
+        hawkes_nll(&x, dims, events, horizon)
+    }, &start, 0.5, 1e-10, 20_000)?;
+    let natural: Vec<f64> = min.x.iter().map(|v| v.exp()).collect();
+    let std_err = hessian_std_errors(|x| hawkes_nll(x, dims, events, horizon), &natural, 1e-4)
+        .unwrap_or_else(|_| vec![f64::NAN; natural.len()]);
+
+    let params = unpack(&natural, dims);
+    let residuals = params.residuals(events);
+    let (ks_statistic, ks_p_value): (Vec<f64>, Vec<f64>) = residuals.iter().map(|r| ks_exponential(r)).unzip();
+    Ok(HawkesFit {
+        branching_ratio: params.branching_ratio(),
+        params,
+        start: 0.0,
+        horizon,
+        counts,
+        std_err,
+        log_likelihood: -min.value,
+        converged: min.converged,
+        residuals,
+        ks_statistic,
+        ks_p_value,
+    })
+}
+
+/// Distinct trade timestamps in seconds; trades printed at the same time count as one arrival.
+pub fn arrival_times(event_list: &EventList) -> Vec<f64> {
+    let mut times: Vec<f64> = event_list.get_full_time_series().iter().map(|(t, _, _)| t.to_f64().unwrap_or(0.0)).collect();
+    times.dedup();
+    times
+}
+
+/// Distinct buyer (0) and seller (1) initiated arrivals, tick-rule classified if needed.
+/// Trades the tick rule cannot sign are dropped.
+pub fn buy_sell_arrivals(event_list: &EventList) -> Vec<(f64, usize)> {
+    let el = event_list.with_trade_sides();
+    let mut out: Vec<(f64, usize)> = el.events.values()
+        .flat_map(|e| e.tics.iter())
+        .filter_map(|t| match t.side {
+            TradeSide::Buy => Some((t.seconds.to_f64().unwrap_or(0.0), 0)),
+            TradeSide::Sell => Some((t.seconds.to_f64().unwrap_or(0.0), 1)),
+            TradeSide::Unknown => None,
+        })
+        .collect();
+    out.dedup();
+    out
+}
+
+/// Fits over the window from the first to the last event and shifts the window back to clock time.
+fn fit_window(events: Vec<(f64, usize)>, dims: usize) -> Result<HawkesFit, Box<dyn Error>> {
+    let (first, last) = match (events.first(), events.last()) {
+        (Some(f), Some(l)) => (f.0, l.0),
+        _ => return Err("no arrivals".into()),
+    };
+    let relative: Vec<(f64, usize)> = events.iter().map(|(t, m)| (t - first, *m)).collect();
+    let mut fit = fit_hawkes(&relative, dims, last - first)?;
+    fit.start = first;
+    Ok(fit)
+}
+
+/// Univariate Hawkes fit to the trade arrivals of an `EventList`.
+///
+/// # Example
+///
+/// ```ignore
+/// let fit = event_list_hawkes(&event_list)?;
+/// println!("branching ratio {:.2}, KS p {:.3}", fit.branching_ratio, fit.ks_p_value[0]);
+/// let synthetic = fit.simulate(42);
+/// ```
+pub fn event_list_hawkes(event_list: &EventList) -> Result<HawkesFit, Box<dyn Error>> {
+    fit_window(arrival_times(event_list).into_iter().map(|t| (t, 0)).collect(), 1)
+}
+
+/// Bivariate buy (dimension 0) / sell (dimension 1) Hawkes fit to an `EventList`.
+pub fn event_list_hawkes_buy_sell(event_list: &EventList) -> Result<HawkesFit, Box<dyn Error>> {
+    fit_window(buy_sell_arrivals(event_list), 2)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_structs::test_event_list;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_simulate_and_likelihood() {
+        let params = HawkesParams { mu: vec![0.5], alpha: vec![vec![0.8]], beta: 1.6 };
+        assert_relative_eq!(params.branching_ratio(), 0.5, epsilon = 1e-9);
+        let events = params.simulate(10_000.0, 1);
+        // stationary rate mu / (1 - n)
+        assert_relative_eq!(events.len() as f64 / 10_000.0, 1.0, epsilon = 0.1);
+        assert!(events.windows(2).all(|w| w[1].0 >= w[0].0));
+        // a Poisson process has likelihood N ln(mu) - mu T
+        let poisson = HawkesParams { mu: vec![2.0], alpha: vec![vec![0.0]], beta: 1.0 };
+        let ll = poisson.log_likelihood(&[(1.0, 0), (2.0, 0)], 3.0);
+        assert_relative_eq!(ll, 2.0 * 2f64.ln() - 6.0, epsilon = 1e-12);
+        assert_eq!(poisson.residuals(&[(1.0, 0), (2.5, 0)]), vec![vec![2.0, 3.0]]);
+    }
+
+    #[test]
+    fn test_fit_univariate() {
+        let params = HawkesParams { mu: vec![0.5], alpha: vec![vec![0.8]], beta: 1.6 };
+        let events = params.simulate(8_000.0, 2);
+        let fit = fit_hawkes(&events, 1, 8_000.0).unwrap();
+        assert_relative_eq!(fit.params.mu[0], 0.5, epsilon = 0.08);
+        assert_relative_eq!(fit.params.alpha[0][0], 0.8, epsilon = 0.15);
+        assert_relative_eq!(fit.params.beta, 1.6, epsilon = 0.3);
+        assert_relative_eq!(fit.branching_ratio, 0.5, epsilon = 0.05);
+        assert!(fit.std_err.iter().all(|s| s.is_finite() && *s > 0.0));
+        assert!(fit.ks_p_value[0] > 0.01);
+        // a Poisson model of the same data fails time rescaling
+        let poisson = HawkesParams { mu: vec![events.len() as f64 / 8_000.0], alpha: vec![vec![0.0]], beta: 1.0 };
+        let (_, p) = ks_exponential(&poisson.residuals(&events)[0]);
+        assert!(p < 1e-3);
+    }
+
+    #[test]
+    fn test_fit_bivariate() {
+        let params = HawkesParams { mu: vec![0.3, 0.3], alpha: vec![vec![0.8, 0.2], vec![0.1, 0.6]], beta: 2.0 };
+        let events = params.simulate(8_000.0, 3);
+        let fit = fit_hawkes(&events, 2, 8_000.0).unwrap();
+        assert_relative_eq!(fit.params.alpha[0][0], 0.8, epsilon = 0.2);
+        assert_relative_eq!(fit.params.alpha[1][1], 0.6, epsilon = 0.2);
+        assert!(fit.params.alpha[0][1] < fit.params.alpha[0][0]);
+        assert_relative_eq!(fit.branching_ratio, params.branching_ratio(), epsilon = 0.08);
+        assert!(fit.ks_p_value.iter().all(|p| *p > 0.01));
+        assert_eq!(fit.std_err.len(), 7);
+        assert!(fit_hawkes(&events, 1, 8_000.0).is_err());
+    }
+
+    #[test]
+    fn test_event_list_hawkes() {
+        let params = HawkesParams { mu: vec![0.5], alpha: vec![vec![0.8]], beta: 1.6 };
+        let tics: Vec<(f64, String, i32)> = params.simulate(3_000.0, 4).iter().enumerate()
+            .map(|(i, (t, _))| {
+                let price = 1000 + (i * 7) % 11;
+                (34_200.0 + t, format!("{}.{:02}", price / 100, price % 100), 100)
+            })
+            .collect();
+        let el = test_event_list(&tics);
+        let fit = event_list_hawkes(&el).unwrap();
+        assert!(fit.start >= 34_200.0 && fit.start < 34_220.0);
+        assert_relative_eq!(fit.branching_ratio, 0.5, epsilon = 0.15);
+        let synthetic = fit.simulate(5);
+        assert!(synthetic.iter().all(|(t, m)| *t >= fit.start && *t <= fit.start + fit.horizon && *m == 0));
+        let bivariate = event_list_hawkes_buy_sell(&el).unwrap();
+        assert_eq!(bivariate.counts.iter().sum::<usize>(), buy_sell_arrivals(&el).len());
+    }
+}
@@ -0,0 +1,238 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// Hayashi-Yoshida realized covariance of two asynchronously traded symbols
+//   HY = sum_i sum_j r_a,i r_b,j 1{(t_a,i-1, t_a,i] overlaps (t_b,j-1, t_b,j]}
+// computed straight from the tick streams, and the grid sampled realized covariance it is
+// compared with in a sampling frequency signature.
+
+use std::error::Error;
+use rust_decimal::prelude::ToPrimitive;
+use crate::event_structs::EventList;
+
+/// `(time in seconds, log price)` of every distinct trade timestamp, the last trade at a
+/// timestamp standing for it.
+pub fn tick_log_prices(event_list: &EventList) -> Vec<(f64, f64)> {
+    let mut out: Vec<(f64, f64)> = Vec::new();
+    for event in event_list.events.values() {
+        for tic in event.tics.iter() {
+            let time = tic.seconds.to_f64().unwrap_or(0.0);
+            let price = tic.price.to_f64().unwrap_or(0.0);
+            if price <= 0.0 {
+                continue;
+            }
+            match out.last_mut() {
+                Some(last) if last.0 == time => last.1 = price.ln(),
+                _ => out.push((time, price.ln())),
+            }
+        }
+    }
+    out
+}
+
+/// Ticks inside `[start, end]`.
+fn window(ticks: &[(f64, f64)], start: f64, end: f64) -> &[(f64, f64)] {
+    let lo = ticks.partition_point(|t| t.0 < start);
+    let hi = ticks.partition_point(|t| t.0 <= end);
+    &ticks[lo..hi.max(lo)]
+}
+
+/// Hayashi-Yoshida covariance of two time sorted `(time, log price)` tick series.
+pub fn hayashi_yoshida(a: &[(f64, f64)], b: &[(f64, f64)]) -> f64 {
+    if a.len() < 2 || b.len() < 2 {
+        return 0.0;
+    }
+    let mut sum = 0.0;
+    let mut first = 1;
+    for i in 1..a.len() {
+        let (a0, a1) = (a[i - 1].0, a[i].0);
+        let ra = a[i].1 - a[i - 1].1;
+        // b intervals ending at or before a0 can not overlap this or any later a interval
+        while first < b.len() && b[first].0 <= a0 {
+            first += 1;
+        }
+        let mut j = first;
+        while j < b.len() && b[j - 1].0 < a1 {
+            sum += ra * (b[j].1 - b[j - 1].1);
+            j += 1;
+        }
+    }
+    sum
+}
+
+fn realized_variance(ticks: &[(f64, f64)]) -> f64 {
+    ticks.windows(2).map(|w| (w[1].1 - w[0].1).powi(2)).sum()
+}
+
+/// Realized covariance with both variances, over the window where both symbols trade.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RealizedCovariance {
+    pub covariance: f64,
+    pub variance_a: f64,
+    pub variance_b: f64,
+    pub correlation: f64,
+}
+
+impl RealizedCovariance {
+    fn new(covariance: f64, variance_a: f64, variance_b: f64) -> RealizedCovariance {
+        let d = (variance_a * variance_b).sqrt();
+        RealizedCovariance { covariance, variance_a, variance_b, correlation: if d > 0.0 { covariance / d } else { 0.0 } }
+    }
+}
+
+fn common_window(a: &[(f64, f64)], b: &[(f64, f64)]) -> Result<(f64, f64), Box<dyn Error>> {
+    let start = a.first().ok_or("no ticks")?.0.max(b.first().ok_or("no ticks")?.0);
+    let end = a.last().ok_or("no ticks")?.0.min(b.last().ok_or("no ticks")?.0);
+    if end <= start {
+        return Err("symbols do not trade at the same time".into());
+    }
+    Ok((start, end))
+}
+
+/// Hayashi-Yoshida covariance with tick realized variances, the correlation being
+/// `HY / sqrt(RV_a RV_b)`.
+pub fn hy_estimate(a: &[(f64, f64)], b: &[(f64, f64)]) -> Result<RealizedCovariance, Box<dyn Error>> {
+    let (start, end) = common_window(a, b)?;
+    let (a, b) = (window(a, start, end), window(b, start, end));
+    Ok(RealizedCovariance::new(hayashi_yoshida(a, b), realized_variance(a), realized_variance(b)))
+}
+
+/// Realized covariance of previous tick prices sampled every `interval` seconds.
+pub fn grid_estimate(a: &[(f64, f64)], b: &[(f64, f64)], interval: f64) -> Result<RealizedCovariance, Box<dyn Error>> {
+    if interval <= 0.0 {
+        return Err("interval must be positive".into());
+    }
+    let (start, end) = common_window(a, b)?;
+    let sample = |ticks: &[(f64, f64)]| -> Vec<f64> {
+        let mut out = Vec::new();
+        let mut t = start;
+        while t <= end {
+            let idx = ticks.partition_point(|p| p.0 <= t);
+            out.push(ticks[idx.max(1) - 1].1);
+            t += interval;
+        }
+        out
+    };
+    let (pa, pb) = (sample(a), sample(b));
+    let ra: Vec<f64> = pa.windows(2).map(|w| w[1] - w[0]).collect();
+    let rb: Vec<f64> = pb.windows(2).map(|w| w[1] - w[0]).collect();
+    let cov = ra.iter().zip(rb.iter()).map(|(x, y)| x * y).sum();
+    Ok(RealizedCovariance::new(cov, ra.iter().map(|x| x * x).sum(), rb.iter().map(|y| y * y).sum()))
+}
+
+/// Grid estimates over several sampling intervals next to the Hayashi-Yoshida estimate,
+/// grid correlations shrinking towards zero at short intervals being the Epps effect.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CovarianceSignature {
+    pub intervals: Vec<f64>,
+    pub grid: Vec<RealizedCovariance>,
+    pub hayashi_yoshida: RealizedCovariance,
+}
+
+/// Signature of two symbols over `intervals` seconds.
+///
+/// # Example
+///
+/// ```ignore
+/// let sig = event_list_signature(&spy, &qqq, &[0.1, 1.0, 5.0, 30.0, 60.0, 300.0])?;
+/// test_signature_graph(("SPY", "QQQ"), &sig)?;
+/// ```
+pub fn event_list_signature(a: &EventList, b: &EventList, intervals: &[f64]) -> Result<CovarianceSignature, Box<dyn Error>> {
+    let (ta, tb) = (tick_log_prices(a), tick_log_prices(b));
+    let hayashi_yoshida = hy_estimate(&ta, &tb)?;
+    let mut grid = Vec::with_capacity(intervals.len());
+    for interval in intervals {
+        grid.push(grid_estimate(&ta, &tb, *interval)?);
+    }
+    Ok(CovarianceSignature { intervals: intervals.to_vec(), grid, hayashi_yoshida })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_structs::test_event_list;
+    use approx::assert_relative_eq;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+    use rand_distr::{Distribution, Normal};
+
+    /// brute force double sum over overlapping intervals
+    fn naive(a: &[(f64, f64)], b: &[(f64, f64)]) -> f64 {
+        let mut sum = 0.0;
+        for i in 1..a.len() {
+            for j in 1..b.len() {
+                if a[i - 1].0 < b[j].0 && b[j - 1].0 < a[i].0 {
+                    sum += (a[i].1 - a[i - 1].1) * (b[j].1 - b[j - 1].1);
+                }
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn test_hayashi_yoshida() {
+        let mut rng = StdRng::seed_from_u64(8);
+        let step = Normal::new(0.0, 0.001).unwrap();
+        // one efficient price, observed at poisson times by two symbols
+        let n = 20_000;
+        let mut x = 0.0;
+        let path: Vec<f64> = (0..n).map(|_| { x += step.sample(&mut rng); x }).collect();
+        let observe = |rng: &mut StdRng, rate: f64| -> Vec<(f64, f64)> {
+            (0..n).filter(|_| rng.gen::<f64>() < rate).map(|i| (i as f64 * 0.1, path[i])).collect()
+        };
+        let a = observe(&mut rng, 0.3);
+        let b = observe(&mut rng, 0.05);
+        assert_relative_eq!(hayashi_yoshida(&a, &b), naive(&a, &b), epsilon = 1e-12);
+        let hy = hy_estimate(&a, &b).unwrap();
+        // common variance 0.001^2 per step
+        assert_relative_eq!(hy.covariance, n as f64 * 1e-6, max_relative = 0.1);
+        assert!(hy.correlation > 0.9);
+        let fine = grid_estimate(&a, &b, 0.1).unwrap();
+        let coarse = grid_estimate(&a, &b, 60.0).unwrap();
+        assert!(fine.correlation < 0.5, "{}", fine.correlation);
+        assert!(coarse.correlation > 0.8);
+    }
+
+    #[test]
+    fn test_event_list_signature() {
+        let tics: Vec<(i32, String, i32)> = (0..600)
+            .map(|i| (34_200 + i, format!("{:.2}", 100.0 + (i % 7) as f64 * 0.01), 100))
+            .collect();
+        let a = test_event_list(&tics);
+        let b = test_event_list(&tics.iter().step_by(3).cloned().collect::<Vec<_>>());
+        let sig = event_list_signature(&a, &b, &[1.0, 3.0, 30.0]).unwrap();
+        assert_eq!(sig.grid.len(), 3);
+        assert_relative_eq!(sig.grid[1].correlation, 1.0, epsilon = 1e-9);
+        assert!(sig.hayashi_yoshida.covariance > 0.0);
+        assert!(event_list_signature(&a, &EventList::new(), &[1.0]).is_err());
+    }
+}
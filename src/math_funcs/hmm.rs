@@ -0,0 +1,436 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// Gaussian hidden Markov models with diagonal covariances, fit by Baum-Welch and decoded by
+// Viterbi, for segmenting a session into regimes from bar features (return, volume, trade count).
+
+use std::error::Error;
+use std::f64::consts::PI;
+use rust_decimal::prelude::ToPrimitive;
+use crate::event_structs::EventList;
+
+// regime variances are floored at this fraction of the feature's pooled variance
+const VARIANCE_FLOOR: f64 = 1e-3;
+
+/// Gaussian HMM, `transition[i][j]` being P(state j | previous state i).
+#[derive(Debug, Clone, PartialEq)]
+pub struct GaussianHmm {
+    pub initial: Vec<f64>,
+    pub transition: Vec<Vec<f64>>,
+    pub means: Vec<Vec<f64>>,
+    pub variances: Vec<Vec<f64>>,
+}
+
+impl GaussianHmm {
+    pub fn states(&self) -> usize {
+        self.initial.len()
+    }
+
+    fn log_density(&self, state: usize, x: &[f64]) -> f64 {
+        x.iter().zip(self.means[state].iter()).zip(self.variances[state].iter())
+            .map(|((xi, m), v)| -0.5 * ((2.0 * PI * v).ln() + (xi - m).powi(2) / v))
+            .sum()
+    }
+
+    /// Emission densities scaled per observation by their maximum, returning the densities and
+    /// the log scale factors.
+    fn emissions(&self, obs: &[Vec<f64>]) -> (Vec<Vec<f64>>, Vec<f64>) {
+        let k = self.states();
+        let mut dens = Vec::with_capacity(obs.len());
+        let mut scale = Vec::with_capacity(obs.len());
+        for x in obs {
+            let logs: Vec<f64> = (0..k).map(|s| self.log_density(s, x)).collect();
+            let max = logs.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+            dens.push(logs.iter().map(|l| (l - max).exp()).collect());
+            scale.push(max);
+        }
+        (dens, scale)
+    }
+
+    /// Scaled forward-backward pass, returning the log likelihood, the state posteriors and
+    /// the summed transition posteriors.
+    fn forward_backward(&self, obs: &[Vec<f64>]) -> (f64, Vec<Vec<f64>>, Vec<Vec<f64>>) {
+        let n = obs.len();
+        let k = self.states();
+        let (dens, log_scale) = self.emissions(obs);
+        let mut alpha = vec![vec![0.0; k]; n];
+        let mut c = vec![0.0; n];
+        for t in 0..n {
+            for j in 0..k {
+                let prior = if t == 0 {
+                    self.initial[j]
+                } else {
+                    (0..k).map(|i| alpha[t - 1][i] * self.transition[i][j]).sum()
+                };
+                alpha[t][j] = prior * dens[t][j];
+            }
+            c[t] = alpha[t].iter().sum::<f64>().max(f64::MIN_POSITIVE);
+            alpha[t].iter_mut().for_each(|a| *a /= c[t]);
+        }
+        let mut beta = vec![vec![1.0; k]; n];
+        for t in (0..n - 1).rev() {
+            for i in 0..k {
+                beta[t][i] = (0..k).map(|j| self.transition[i][j] * dens[t + 1][j] * beta[t + 1][j]).sum::<f64>() / c[t + 1];
+            }
+        }
+        let gamma: Vec<Vec<f64>> = (0..n).map(|t| (0..k).map(|i| alpha[t][i] * beta[t][i]).collect()).collect();
+        let mut xi = vec![vec![0.0; k]; k];
+        for t in 0..n - 1 {
+            for i in 0..k {
+                for j in 0..k {
+                    xi[i][j] += alpha[t][i] * self.transition[i][j] * dens[t + 1][j] * beta[t + 1][j] / c[t + 1];
+                }
+            }
+        }
+        let log_likelihood = c.iter().map(|v| v.ln()).sum::<f64>() + log_scale.iter().sum::<f64>();
+        (log_likelihood, gamma, xi)
+    }
+
+    /// Log likelihood of a sequence of feature vectors.
+    pub fn log_likelihood(&self, obs: &[Vec<f64>]) -> f64 {
+        if obs.is_empty() {
+            return 0.0;
+        }
+        self.forward_backward(obs).0
+    }
+
+    /// Most likely state path.
+    pub fn viterbi(&self, obs: &[Vec<f64>]) -> Vec<usize> {
+        let n = obs.len();
+        let k = self.states();
+        if n == 0 {
+            return Vec::new();
+        }
+        let ln = |v: f64| if v > 0.0 { v.ln() } else { f64::NEG_INFINITY };
+        let mut delta: Vec<f64> = (0..k).map(|s| ln(self.initial[s]) + self.log_density(s, &obs[0])).collect();
+        let mut back = vec![vec![0usize; k]; n];
+        for t in 1..n {
+            let mut next = vec![f64::NEG_INFINITY; k];
+            for j in 0..k {
+                for (i, d) in delta.iter().enumerate() {
+                    let v = d + ln(self.transition[i][j]);
+                    if v > next[j] {
+                        next[j] = v;
+                        back[t][j] = i;
+                    }
+                }
+                next[j] += self.log_density(j, &obs[t]);
+            }
+            delta = next;
+        }
+        let mut state = (0..k).fold(0, |best, s| if delta[s] > delta[best] { s } else { best });
+        let mut path = vec![0; n];
+        for t in (0..n).rev() {
+            path[t] = state;
+            state = back[t][state];
+        }
+        path
+    }
+}
+
+/// Baum-Welch fit.
+///
+/// States are ordered by ascending variance of the first feature, so with returns first state 0
+/// is the calmest regime and the last state the most volatile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HmmFit {
+    pub model: GaussianHmm,
+    pub log_likelihood: f64,
+    pub iterations: usize,
+    pub converged: bool,
+    pub states: Vec<usize>,
+    pub posteriors: Vec<Vec<f64>>,
+}
+
+/// Fits a `states` regime diagonal Gaussian HMM by Baum-Welch.
+///
+/// Starts from splitting the observations into quantiles of their distance from the mean and
+/// stops when the log likelihood improves by less than `tol` or after `max_iter` steps.
+/// Regime variances are floored relative to each feature's pooled variance, so raw returns fit
+/// as well as standardized ones, but the start uses euclidean distances and features should
+/// still be on comparable scales, see `standardize`.
+///
+/// # Arguments
+///
+/// * `obs` - One feature vector per observation, all the same length
+/// * `states` - Number of regimes
+///
+/// # Returns
+///
+/// The fit with the Viterbi path and smoothed state probabilities
+pub fn fit_hmm(obs: &[Vec<f64>], states: usize, max_iter: usize, tol: f64) -> Result<HmmFit, Box<dyn Error>> {
+    let n = obs.len();
+    if states < 1 {
+        return Err("need at least one state".into());
+    }
+    if n < 5 * states {
+        return Err(format!("{} observations are too few for {} states", n, states).into());
+    }
+    let d = obs[0].len();
+    if d == 0 || obs.iter().any(|x| x.len() != d) {
+        return Err("feature vectors must be non empty and of equal length".into());
+    }
+    let k = states;
+
+    let centre: Vec<f64> = (0..d).map(|j| obs.iter().map(|x| x[j]).sum::<f64>() / n as f64).collect();
+    let floor: Vec<f64> = (0..d).map(|j| {
+        let pooled = obs.iter().map(|x| (x[j] - centre[j]).powi(2)).sum::<f64>() / n as f64;
+        if pooled > 0.0 { VARIANCE_FLOOR * pooled } else { VARIANCE_FLOOR }
+    }).collect();
+    let mut order: Vec<usize> = (0..n).collect();
+    let dist = |x: &Vec<f64>| x.iter().zip(centre.iter()).map(|(a, b)| (a - b).powi(2)).sum::<f64>();
+    order.sort_by(|a, b| dist(&obs[*a]).total_cmp(&dist(&obs[*b])));
+    let mut gamma = vec![vec![0.0; k]; n];
+    for (rank, t) in order.iter().enumerate() {
+        gamma[*t][rank * k / n] = 1.0;
+    }
+    let mut model = GaussianHmm {
+        initial: vec![1.0 / k as f64; k],
+        transition: (0..k).map(|i| (0..k).map(|j| if k == 1 { 1.0 } else if i == j { 0.9 } else { 0.1 / (k - 1) as f64 }).collect()).collect(),
+        means: vec![vec![0.0; d]; k],
+        variances: vec![vec![1.0; d]; k],
+    };
+    update_emissions(&mut model, obs, &gamma, &floor);
+
+    let mut previous = f64::NEG_INFINITY;
+    let mut iterations = 0;
+    let mut converged = false;
+    let mut log_likelihood;
+    loop {
+        let (ll, g, xi) = model.forward_backward(obs);
+        log_likelihood = ll;
+        gamma = g;
+        if ll - previous < tol {
+            converged = true;
+            break;
+        }
+        if iterations >= max_iter {
+            break;
+        }
+        previous = ll;
+        model.initial = gamma[0].clone();
+        for (row, counts) in model.transition.iter_mut().zip(xi.iter()) {
+            let total: f64 = counts.iter().sum();
+            if total > 0.0 {
+                *row = counts.iter().map(|v| v / total).collect();
+            }
+        }
+        update_emissions(&mut model, obs, &gamma, &floor);
+        iterations += 1;
+    }
+
+    // order regimes by the variance of the first feature
+    let mut rank: Vec<usize> = (0..k).collect();
+    rank.sort_by(|a, b| model.variances[*a][0].total_cmp(&model.variances[*b][0]));
+    let model = GaussianHmm {
+        initial: rank.iter().map(|s| model.initial[*s]).collect(),
+        transition: rank.iter().map(|i| rank.iter().map(|j| model.transition[*i][*j]).collect()).collect(),
+        means: rank.iter().map(|s| model.means[*s].clone()).collect(),
+        variances: rank.iter().map(|s| model.variances[*s].clone()).collect(),
+    };
+    let posteriors = gamma.iter().map(|g| rank.iter().map(|s| g[*s]).collect()).collect();
+    let states = model.viterbi(obs);
+    Ok(HmmFit { model, log_likelihood, iterations, converged, states, posteriors })
+}
+
+fn update_emissions(model: &mut GaussianHmm, obs: &[Vec<f64>], gamma: &[Vec<f64>], floor: &[f64]) {
+    let d = obs[0].len();
+    for s in 0..model.states() {
+        let weight: f64 = gamma.iter().map(|g| g[s]).sum();
+        if weight <= 1e-10 {
+            continue;
+        }
+        for j in 0..d {
+            let mean = gamma.iter().zip(obs.iter()).map(|(g, x)| g[s] * x[j]).sum::<f64>() / weight;
+            let var = gamma.iter().zip(obs.iter()).map(|(g, x)| g[s] * (x[j] - mean).powi(2)).sum::<f64>() / weight;
+            model.means[s][j] = mean;
+            model.variances[s][j] = var.max(floor[j]);
+        }
+    }
+}
+
+/// Scales every feature to zero mean and unit variance, constant features are only centred.
+pub fn standardize(obs: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    if obs.is_empty() {
+        return Vec::new();
+    }
+    let n = obs.len() as f64;
+    let d = obs[0].len();
+    let mean: Vec<f64> = (0..d).map(|j| obs.iter().map(|x| x[j]).sum::<f64>() / n).collect();
+    let sd: Vec<f64> = (0..d).map(|j| (obs.iter().map(|x| (x[j] - mean[j]).powi(2)).sum::<f64>() / n).sqrt()).collect();
+    obs.iter().map(|x| (0..d).map(|j| if sd[j] > 0.0 { (x[j] - mean[j]) / sd[j] } else { x[j] - mean[j] }).collect()).collect()
+}
+
+/// Bar features `[log return, ln volume, ln trade count]`, one per bar after the first.
+///
+/// # Returns
+///
+/// Bar start times, bar closes and the raw (unstandardized) features
+pub fn bar_features(event_list: &EventList, interval: i32) -> (Vec<i32>, Vec<f32>, Vec<Vec<f64>>) {
+    let bars = event_list.get_bars(interval);
+    let mut starts = Vec::new();
+    let mut closes = Vec::new();
+    let mut features = Vec::new();
+    for w in bars.windows(2) {
+        let prev = w[0].close.to_f64().unwrap_or(0.0);
+        let close = w[1].close.to_f64().unwrap_or(0.0);
+        if prev <= 0.0 || close <= 0.0 {
+            continue;
+        }
+        starts.push(w[1].start);
+        closes.push(close as f32);
+        features.push(vec![(close / prev).ln(), (w[1].volume.max(1) as f64).ln(), (w[1].tic_count.max(1) as f64).ln()]);
+    }
+    (starts, closes, features)
+}
+
+/// Regime labels per bar of one symbol.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regimes {
+    pub interval: i32,
+    pub starts: Vec<i32>,
+    pub closes: Vec<f32>,
+    pub fit: HmmFit,
+}
+
+impl Regimes {
+    /// Runs of consecutive bars in one regime as `(first start, last start + interval, state)`.
+    pub fn segments(&self) -> Vec<(i32, i32, usize)> {
+        let mut out: Vec<(i32, i32, usize)> = Vec::new();
+        for (start, state) in self.starts.iter().zip(self.fit.states.iter()) {
+            match out.last_mut() {
+                Some(last) if last.2 == *state => last.1 = start + self.interval,
+                _ => out.push((*start, start + self.interval, *state)),
+            }
+        }
+        out
+    }
+}
+
+/// Segments an `EventList` into `states` regimes over `interval` second bars.
+///
+/// # Example
+///
+/// ```ignore
+/// let regimes = event_list_regimes(&el, 60, 3, 200, 1e-6)?;
+/// for (start, end, state) in regimes.segments() {
+///     println!("{} - {} regime {}", s2hhmmss_32(start), s2hhmmss_32(end), state);
+/// }
+/// ```
+pub fn event_list_regimes(event_list: &EventList, interval: i32, states: usize, max_iter: usize, tol: f64) -> Result<Regimes, Box<dyn Error>> {
+    let (starts, closes, features) = bar_features(event_list, interval);
+    let fit = fit_hmm(&standardize(&features), states, max_iter, tol)?;
+    Ok(Regimes { interval, starts, closes, fit })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_structs::test_event_list;
+    use approx::assert_relative_eq;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use rand_distr::{Distribution, Normal};
+
+    fn two_regimes(n: usize, seed: u64) -> (Vec<usize>, Vec<Vec<f64>>) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let calm = Normal::new(0.0, 0.5).unwrap();
+        let wild = Normal::new(0.0, 3.0).unwrap();
+        let mut truth = Vec::with_capacity(n);
+        let mut obs = Vec::with_capacity(n);
+        for t in 0..n {
+            let s = (t / 100) % 2;
+            let x = if s == 0 { calm.sample(&mut rng) } else { wild.sample(&mut rng) };
+            truth.push(s);
+            obs.push(vec![x, if s == 0 { -1.0 } else { 1.0 } + calm.sample(&mut rng)]);
+        }
+        (truth, obs)
+    }
+
+    #[test]
+    fn test_fit_hmm() {
+        let (truth, obs) = two_regimes(1_000, 7);
+        let fit = fit_hmm(&obs, 2, 200, 1e-8).unwrap();
+        assert!(fit.converged);
+        let hits = fit.states.iter().zip(truth.iter()).filter(|(a, b)| a == b).count();
+        assert!(hits > 950, "{} of 1000 decoded", hits);
+        assert_relative_eq!(fit.model.variances[0][0].sqrt(), 0.5, max_relative = 0.2);
+        assert_relative_eq!(fit.model.variances[1][0].sqrt(), 3.0, max_relative = 0.2);
+        assert!(fit.model.transition[0][0] > 0.95);
+        for p in fit.posteriors.iter() {
+            assert_relative_eq!(p.iter().sum::<f64>(), 1.0, epsilon = 1e-9);
+        }
+        assert!(fit_hmm(&obs[..5], 2, 10, 1e-6).is_err());
+    }
+
+    #[test]
+    fn test_fit_hmm_raw_scale() {
+        // bar returns sized features, the regime variances are far below any absolute floor
+        let (truth, obs) = two_regimes(1_000, 7);
+        let raw: Vec<Vec<f64>> = obs.iter().map(|x| x.iter().map(|v| v * 1e-4).collect()).collect();
+        let fit = fit_hmm(&raw, 2, 200, 1e-8).unwrap();
+        let hits = fit.states.iter().zip(truth.iter()).filter(|(a, b)| a == b).count();
+        assert!(hits > 950, "{} of 1000 decoded", hits);
+        assert_relative_eq!(fit.model.variances[0][0].sqrt(), 0.5e-4, max_relative = 0.2);
+        assert_relative_eq!(fit.model.variances[1][0].sqrt(), 3.0e-4, max_relative = 0.2);
+    }
+
+    #[test]
+    fn test_viterbi_forward() {
+        // a single state model reduces to independent gaussians
+        let model = GaussianHmm { initial: vec![1.0], transition: vec![vec![1.0]], means: vec![vec![0.0]], variances: vec![vec![1.0]] };
+        let obs = vec![vec![0.0], vec![1.0]];
+        assert_relative_eq!(model.log_likelihood(&obs), -(2.0 * PI).ln() - 0.5, epsilon = 1e-12);
+        assert_eq!(model.viterbi(&obs), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_event_list_regimes() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let step = Normal::new(0.0, 1.0).unwrap();
+        let mut cents = 10_000.0f64;
+        let mut tics = Vec::new();
+        for secs in 34_200..34_200 + 3 * 3600 {
+            let wild = (secs - 34_200) / 1800 % 2 == 1;
+            cents += step.sample(&mut rng) * if wild { 8.0 } else { 1.0 };
+            tics.push((secs, format!("{:.2}", cents / 100.0), if wild { 500 } else { 100 }));
+        }
+        let el = test_event_list(&tics);
+        let regimes = event_list_regimes(&el, 60, 2, 200, 1e-6).unwrap();
+        assert_eq!(regimes.starts.len(), regimes.fit.states.len());
+        assert_eq!(regimes.fit.states[10], 0);
+        assert_eq!(regimes.fit.states[40], 1);
+        let segments = regimes.segments();
+        assert_eq!(segments.len(), 6);
+        assert_eq!(segments[1].0, 34_200 + 1800);
+    }
+}
@@ -0,0 +1,292 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// Jump tests on regularly sampled log returns.
+// Lee & Mykland (2008) test every return against a local bipower volatility and locate jumps;
+// Barndorff-Nielsen & Shephard (2006) test the whole window for any jump contribution.
+// Jumps at a halt resumption are expected, so detected jumps are flagged against T34 halts.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::f64::consts::PI;
+use crate::event_structs::EventList;
+use crate::math_funcs::distributions::{ln_gamma, norm_cdf};
+use crate::math_funcs::pre_processing::{resample_event_list, SeriesKind};
+use crate::nyse::base_funcs::{EventStats, HaltPeriod, HaltStats};
+
+/// A detected jump.
+///
+/// - `index`: Position in the return series.
+/// - `time`: Start of the sampling interval the return ends in, seconds after midnight.
+/// - `size`: The log return.
+/// - `statistic`: Lee-Mykland `L = r / sigma_local`.
+/// - `near_halt`: Set by `flag_halts` when the jump falls in or next to a halt.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Jump {
+    pub index: usize,
+    pub time: i32,
+    pub size: f32,
+    pub statistic: f64,
+    pub near_halt: bool,
+}
+
+/// Lee-Mykland jump test.
+///
+/// The local variance of return `i` is the bipower variation of the `window` returns before it,
+/// `sigma^2 = sum |r_j||r_j-1| / (window - 2)`. A return is a jump when its standardized maximum
+/// statistic exceeds the Gumbel critical value at level `significance`.
+///
+/// # Arguments
+///
+/// * `returns` - Regularly sampled log returns.
+/// * `window` - Returns used for the local volatility, e.g. sqrt(252 * samples per day).
+/// * `significance` - Test level, e.g. 0.01.
+///
+/// # Returns
+///
+/// Detected jumps with `time` left at 0, see `event_list_jumps` for timed output.
+pub fn lee_mykland(returns: &[f32], window: usize, significance: f64) -> Result<Vec<Jump>, Box<dyn Error>> {
+    let n = returns.len();
+    if window < 3 || n <= window {
+        return Err(format!("Lee-Mykland needs more than {} returns and a window of at least 3, got {}", window, n).into());
+    }
+    let abs: Vec<f64> = returns.iter().map(|r| (*r as f64).abs()).collect();
+    let bipower: Vec<f64> = abs.windows(2).map(|w| w[0] * w[1]).collect();
+    let c = (2.0 / PI).sqrt();
+    let tested = (n - window) as f64;
+    let log_n = tested.max(2.0).ln();
+    let c_n = (2.0 * log_n).sqrt() / c - (PI.ln() + log_n.ln()) / (2.0 * c * (2.0 * log_n).sqrt());
+    let s_n = 1.0 / (c * (2.0 * log_n).sqrt());
+    let critical = -(-(1.0 - significance).ln()).ln();
+
+    let mut jumps: Vec<Jump> = Vec::new();
+    let mut sum: f64 = bipower[1..window - 2].iter().sum();
+    for i in window..n {
+        // bipower terms |r_j||r_j+1| for j in i - window + 1 ..= i - 2
+        sum += bipower[i - 2];
+        if i > window {
+            sum -= bipower[i - window];
+        }
+        let sigma = (sum / (window - 2) as f64).sqrt();
+        if sigma <= 0.0 {
+            continue;
+        }
+        let statistic = returns[i] as f64 / sigma;
+        if (statistic.abs() - c_n) / s_n > critical {
+            jumps.push(Jump { index: i, time: 0, size: returns[i], statistic, near_halt: false });
+        }
+    }
+    Ok(jumps)
+}
+
+/// Barndorff-Nielsen-Shephard test result.
+///
+/// - `realized_variance`, `bipower_variation`, `tripower_quarticity`: Variation measures.
+/// - `jump_share`: `max(RV - BV, 0) / RV`, the share of variance attributed to jumps.
+/// - `statistic`, `p_value`: Ratio statistic with the max-adjusted quarticity and its one-sided p-value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BnsTest {
+    pub realized_variance: f64,
+    pub bipower_variation: f64,
+    pub tripower_quarticity: f64,
+    pub jump_share: f64,
+    pub statistic: f64,
+    pub p_value: f64,
+}
+
+/// Barndorff-Nielsen-Shephard ratio test for jumps anywhere in `returns`.
+pub fn bns_test(returns: &[f32]) -> Result<BnsTest, Box<dyn Error>> {
+    let n = returns.len();
+    if n < 10 {
+        return Err(format!("BNS test needs at least 10 returns, got {}", n).into());
+    }
+    let nf = n as f64;
+    let abs: Vec<f64> = returns.iter().map(|r| (*r as f64).abs()).collect();
+    let rv: f64 = abs.iter().map(|a| a * a).sum();
+    let bv = PI / 2.0 * nf / (nf - 1.0) * abs.windows(2).map(|w| w[0] * w[1]).sum::<f64>();
+    // mu_{4/3} = E|Z|^{4/3} = 2^{2/3} Gamma(7/6) / Gamma(1/2)
+    let mu43 = 2f64.powf(2.0 / 3.0) * (ln_gamma(7.0 / 6.0) - ln_gamma(0.5)).exp();
+    let tq = nf * mu43.powi(-3) * nf / (nf - 2.0)
+        * abs.windows(3).map(|w| (w[0] * w[1] * w[2]).powf(4.0 / 3.0)).sum::<f64>();
+    if rv <= 0.0 || bv <= 0.0 {
+        return Err("BNS test needs non-zero returns".into());
+    }
+    let theta = PI * PI / 4.0 + PI - 5.0;
+    let statistic = ((rv - bv) / rv) / (theta / nf * (tq / (bv * bv)).max(1.0)).sqrt();
+    Ok(BnsTest {
+        realized_variance: rv,
+        bipower_variation: bv,
+        tripower_quarticity: tq,
+        jump_share: ((rv - bv) / rv).max(0.0),
+        statistic,
+        p_value: 1.0 - norm_cdf(statistic),
+    })
+}
+
+/// Marks jumps whose interval `[time, time + interval]` overlaps a halt widened by `tolerance` seconds.
+pub fn flag_halts(jumps: &mut [Jump], halts: &[HaltPeriod], interval: i32, tolerance: f64) {
+    for jump in jumps.iter_mut() {
+        let start = jump.time as f64;
+        jump.near_halt = halts.iter().any(|h| {
+            h.contains(start, tolerance) || h.contains(start + interval as f64, tolerance)
+                || (h.start >= start && h.start <= start + interval as f64)
+        });
+    }
+}
+
+/// Jump test results for one symbol.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolJumps {
+    pub interval: i32,
+    pub jumps: Vec<Jump>,
+    pub bns: BnsTest,
+}
+
+/// Resamples an `EventList` to `interval` second log returns and runs both tests.
+///
+/// # Example
+///
+/// ```ignore
+/// // one minute returns, 30 minute local volatility window, 1% level
+/// let result = event_list_jumps(&event_list, 60, 30, 0.01)?;
+/// for jump in result.jumps { println!("{} {:.4} L={:.1}", s2hhmmss_32(jump.time), jump.size, jump.statistic); }
+/// ```
+pub fn event_list_jumps(event_list: &EventList, interval: i32, window: usize, significance: f64) -> Result<SymbolJumps, Box<dyn Error>> {
+    let start = *event_list.events.keys().next().ok_or("no trades")?;
+    let returns = resample_event_list(event_list, interval, SeriesKind::Return);
+    let mut jumps = lee_mykland(&returns, window, significance)?;
+    for jump in jumps.iter_mut() {
+        jump.time = start + (jump.index as i32 + 1) * interval;
+    }
+    Ok(SymbolJumps { interval, jumps, bns: bns_test(&returns)? })
+}
+
+/// Jump tests for every symbol in `EventStats`, with jumps flagged against the symbol's halts.
+/// Symbols too short for the tests are left out.
+pub fn event_stats_jumps(event_stats: &EventStats, halt_stats: &HaltStats, interval: i32, window: usize,
+                         significance: f64) -> HashMap<String, SymbolJumps> {
+    let mut out: HashMap<String, SymbolJumps> = HashMap::new();
+    for (symbol, event_list) in event_stats.symbol_events.iter() {
+        if let Ok(mut result) = event_list_jumps(event_list, interval, window, significance) {
+            flag_halts(&mut result.jumps, halt_stats.get_halts(symbol), interval, interval as f64);
+            out.insert(symbol.clone(), result);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_structs::test_event_list;
+    use approx::assert_relative_eq;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use rand_distr::{Distribution, Normal};
+    use crate::nyse::mt34::HaltCondition;
+
+    fn diffusion(n: usize, sigma: f32, seed: u64) -> Vec<f32> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let normal = Normal::new(0.0, sigma).unwrap();
+        (0..n).map(|_| normal.sample(&mut rng)).collect()
+    }
+
+    #[test]
+    fn test_lee_mykland() {
+        let mut returns = diffusion(2_000, 0.001, 1);
+        assert!(lee_mykland(&returns, 50, 0.01).unwrap().is_empty());
+        returns[700] = 0.02;
+        returns[1500] = -0.015;
+        let jumps = lee_mykland(&returns, 50, 0.01).unwrap();
+        assert_eq!(jumps.iter().map(|j| j.index).collect::<Vec<usize>>(), vec![700, 1500]);
+        assert!(jumps[0].statistic > 10.0 && jumps[1].statistic < -10.0);
+        assert_relative_eq!(jumps[0].size, 0.02);
+        assert!(lee_mykland(&returns[..10], 50, 0.01).is_err());
+    }
+
+    #[test]
+    fn test_lee_mykland_window() {
+        let returns: Vec<f32> = diffusion(500, 0.001, 4).iter().enumerate().map(|(i, r)| r * (1.0 + (i % 37) as f32)).collect();
+        let window = 6;
+        let jumps = lee_mykland(&returns, window, 0.5).unwrap();
+        assert!(jumps.len() > 10);
+        for jump in jumps {
+            let i = jump.index;
+            let sum: f64 = (i + 1 - window..=i - 2).map(|j| (returns[j] as f64).abs() * (returns[j + 1] as f64).abs()).sum();
+            let sigma = (sum / (window - 2) as f64).sqrt();
+            assert_relative_eq!(jump.statistic, returns[i] as f64 / sigma, max_relative = 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_bns() {
+        let mut returns = diffusion(2_000, 0.001, 2);
+        let clean = bns_test(&returns).unwrap();
+        assert!(clean.p_value > 0.01);
+        assert_relative_eq!(clean.bipower_variation / clean.realized_variance, 1.0, epsilon = 0.1);
+        // tripower quarticity estimates n * sum sigma^4 = (n sigma^2)^2 / n * n
+        assert_relative_eq!(clean.tripower_quarticity, 2_000.0 * 2_000.0 * 1e-12, max_relative = 0.15);
+        returns[1000] = 0.03;
+        let jumpy = bns_test(&returns).unwrap();
+        assert!(jumpy.p_value < 1e-4);
+        assert!(jumpy.jump_share > 0.1);
+    }
+
+    #[test]
+    fn test_event_list_jumps_and_halts() {
+        let returns = diffusion(400, 0.0005, 3);
+        let mut log_p = 50f64.ln();
+        let mut tics = Vec::with_capacity(returns.len());
+        for (i, r) in returns.iter().enumerate() {
+            log_p += *r as f64;
+            if i == 300 {
+                log_p += 0.03;
+            }
+            tics.push((34_200 + 60 * i as i32, format!("{:.4}", log_p.exp()), 100));
+        }
+        let el = test_event_list(&tics);
+        let mut stats = EventStats::new();
+        stats.symbol_events.insert("XYZ".to_string(), el.clone());
+        stats.symbol_events.insert("EMPTY".to_string(), EventList::new());
+        let mut halts = HaltStats::new();
+        halts.halts.insert("XYZ".to_string(), vec![HaltPeriod { start: 34_200.0 + 60.0 * 299.5, end: Some(34_200.0 + 60.0 * 299.9), condition: HaltCondition::LULDPause }]);
+        let result = event_stats_jumps(&stats, &halts, 60, 30, 0.01);
+        assert_eq!(result.len(), 1);
+        let jumps = &result["XYZ"].jumps;
+        assert_eq!(jumps.len(), 1);
+        assert_eq!(jumps[0].time, 34_200 + 60 * 300);
+        assert!(jumps[0].near_halt);
+        assert!(result["XYZ"].bns.p_value < 0.01);
+        let plain = event_list_jumps(&el, 60, 30, 0.01).unwrap();
+        assert!(!plain.jumps[0].near_halt);
+    }
+}
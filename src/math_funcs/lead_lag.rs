@@ -0,0 +1,244 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// Lead-lag between two symbols from their tick streams (Hoffmann, Rosenbaum & Yoshida 2013):
+// the Hayashi-Yoshida correlation of a with b shifted by a lag, so no grid limits the
+// resolution and lags can go down to nanoseconds. A positive lag means a moves first.
+
+use std::error::Error;
+use rust_decimal::prelude::ToPrimitive;
+use crate::event_structs::EventList;
+use crate::math_funcs::cross_section::most_active_symbols;
+use crate::math_funcs::hayashi_yoshida::{hayashi_yoshida, tick_log_prices};
+use crate::nyse::base_funcs::EventStats;
+
+/// What is cross correlated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeadLagSignal {
+    /// Log price returns.
+    Returns,
+    /// Trade signs (tick rule unless already classified), increments of the cumulative
+    /// signed trade count. Unsigned arrivals would only measure interval overlap.
+    SignedTrades,
+}
+
+/// Cumulative `(time, level)` path of a signal at every distinct trade timestamp.
+pub fn signal_path(event_list: &EventList, signal: LeadLagSignal) -> Vec<(f64, f64)> {
+    if signal == LeadLagSignal::Returns {
+        return tick_log_prices(event_list);
+    }
+    let el = event_list.with_trade_sides();
+    let mut out: Vec<(f64, f64)> = Vec::new();
+    let mut level = 0.0;
+    for tic in el.events.values().flat_map(|e| e.tics.iter()) {
+        level += tic.side.sign() as f64;
+        let time = tic.seconds.to_f64().unwrap_or(0.0);
+        match out.last_mut() {
+            Some(last) if last.0 == time => last.1 = level,
+            _ => out.push((time, level)),
+        }
+    }
+    out
+}
+
+/// Symmetric lags `-max..max` with `per_side` logarithmically spaced magnitudes from `min`
+/// and zero, e.g. `log_lags(1e-6, 10.0, 36)` for microseconds to ten seconds.
+pub fn log_lags(min: f64, max: f64, per_side: usize) -> Vec<f64> {
+    let mut positive = Vec::with_capacity(per_side);
+    if per_side == 1 {
+        positive.push(max);
+    } else if per_side > 1 {
+        let ratio = (max / min).ln() / (per_side - 1) as f64;
+        positive.extend((0..per_side).map(|k| min * (ratio * k as f64).exp()));
+    }
+    let mut lags: Vec<f64> = positive.iter().rev().map(|l| -l).collect();
+    lags.push(0.0);
+    lags.extend(positive);
+    lags
+}
+
+/// Cross correlation of two signals over a set of lags.
+///
+/// - `peak_lag`: Lag of the largest absolute correlation.
+/// - `ratio`: Sum of squared correlations at positive lags over those at negative lags, above
+///   one when `a` leads `b`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeadLag {
+    pub signal: LeadLagSignal,
+    pub lags: Vec<f64>,
+    pub correlations: Vec<f64>,
+    pub peak_lag: f64,
+    pub peak_correlation: f64,
+    pub ratio: f64,
+}
+
+/// Lagged Hayashi-Yoshida correlations of two cumulative `(time, level)` paths, `b` being
+/// compared `lag` seconds after `a`.
+pub fn lagged_correlations(a: &[(f64, f64)], b: &[(f64, f64)], lags: &[f64]) -> Vec<f64> {
+    let var = |p: &[(f64, f64)]| p.windows(2).map(|w| (w[1].1 - w[0].1).powi(2)).sum::<f64>();
+    let norm = (var(a) * var(b)).sqrt();
+    lags.iter().map(|lag| {
+        if norm <= 0.0 {
+            return 0.0;
+        }
+        // keep shifted times on the nanosecond grid of the feed so equal times stay equal
+        let shifted: Vec<(f64, f64)> = b.iter().map(|(t, v)| (((t - lag) * 1e9).round() / 1e9, *v)).collect();
+        hayashi_yoshida(a, &shifted) / norm
+    }).collect()
+}
+
+/// Lead-lag of `a` against `b`.
+///
+/// # Example
+///
+/// ```ignore
+/// let ll = lead_lag(&spy, &aapl, LeadLagSignal::Returns, &log_lags(1e-6, 10.0, 36))?;
+/// println!("peak at {:.6}s, ratio {:.2}", ll.peak_lag, ll.ratio);
+/// ```
+pub fn lead_lag(a: &EventList, b: &EventList, signal: LeadLagSignal, lags: &[f64]) -> Result<LeadLag, Box<dyn Error>> {
+    if lags.is_empty() {
+        return Err("no lags".into());
+    }
+    let (pa, pb) = (signal_path(a, signal), signal_path(b, signal));
+    if pa.len() < 2 || pb.len() < 2 {
+        return Err("need at least two trade timestamps per symbol".into());
+    }
+    let correlations = lagged_correlations(&pa, &pb, lags);
+    let peak = (0..lags.len()).fold(0, |best, k| if correlations[k].abs() > correlations[best].abs() { k } else { best });
+    let side = |positive: bool| lags.iter().zip(correlations.iter())
+        .filter(|(l, _)| if positive { **l > 0.0 } else { **l < 0.0 })
+        .map(|(_, c)| c * c)
+        .sum::<f64>();
+    let (lead, lag) = (side(true), side(false));
+    Ok(LeadLag {
+        signal,
+        lags: lags.to_vec(),
+        peak_lag: lags[peak],
+        peak_correlation: correlations[peak],
+        ratio: if lag > 0.0 { lead / lag } else if lead > 0.0 { f64::INFINITY } else { 1.0 },
+        correlations,
+    })
+}
+
+/// Lead-lag of one pair, `leader` being the side the ratio points to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PairLeadLag {
+    pub leader: String,
+    pub follower: String,
+    pub lead_lag: LeadLag,
+}
+
+/// Runs `lead_lag` over every pair of the `max_items` most active symbols, strongest lead-lag
+/// (largest ratio in either direction) first.
+pub fn scan_most_active(event_stats: &EventStats, max_items: usize, min_trades: usize, signal: LeadLagSignal,
+                        lags: &[f64]) -> Vec<PairLeadLag> {
+    let symbols = most_active_symbols(event_stats, max_items, min_trades);
+    let mut out = Vec::new();
+    for (i, a) in symbols.iter().enumerate() {
+        for b in symbols.iter().skip(i + 1) {
+            let (ea, eb) = (&event_stats.symbol_events[a], &event_stats.symbol_events[b]);
+            if let Ok(mut ll) = lead_lag(ea, eb, signal, lags) {
+                if ll.ratio >= 1.0 {
+                    out.push(PairLeadLag { leader: a.clone(), follower: b.clone(), lead_lag: ll });
+                } else {
+                    // flip so the ratio reads as leader over follower
+                    ll.lags.reverse();
+                    ll.lags.iter_mut().for_each(|l| *l = -*l);
+                    ll.correlations.reverse();
+                    ll.peak_lag = -ll.peak_lag;
+                    ll.ratio = 1.0 / ll.ratio;
+                    out.push(PairLeadLag { leader: b.clone(), follower: a.clone(), lead_lag: ll });
+                }
+            }
+        }
+    }
+    out.sort_by(|x, y| y.lead_lag.ratio.total_cmp(&x.lead_lag.ratio));
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_structs::test_event_list;
+    use approx::assert_relative_eq;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    /// `b` repeats every price move of `a` 30 milliseconds later, after 1 to 20 ms between trades
+    fn leader_follower(seed: u64) -> (EventList, EventList) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut a = Vec::new();
+        let mut b = Vec::new();
+        let mut cents = 10_000i64;
+        let mut nanos = 34_200i64 * 1_000_000_000;
+        for _ in 0..3_000 {
+            nanos += rng.gen_range(1_000_000..20_000_000);
+            cents += if rng.gen::<bool>() { 1 } else { -1 };
+            let price = format!("{}.{:02}", cents / 100, cents % 100);
+            a.push((nanos as f64 / 1e9, price.clone(), 100));
+            b.push(((nanos + 30_000_000) as f64 / 1e9, price, 100));
+        }
+        (test_event_list(&a), test_event_list(&b))
+    }
+
+    #[test]
+    fn test_log_lags() {
+        let lags = log_lags(1e-6, 1.0, 7);
+        assert_eq!(lags.len(), 15);
+        assert_eq!(lags[7], 0.0);
+        assert_relative_eq!(lags[8], 1e-6, epsilon = 1e-15);
+        assert_relative_eq!(lags[9], 1e-5, max_relative = 1e-9);
+        assert_relative_eq!(lags[0], -1.0, max_relative = 1e-9);
+    }
+
+    #[test]
+    fn test_lead_lag() {
+        let (a, b) = leader_follower(9);
+        let lags = [-0.1, -0.03, -0.000001, 0.0, 0.000001, 0.03, 0.1];
+        let ll = lead_lag(&a, &b, LeadLagSignal::Returns, &lags).unwrap();
+        assert_eq!(ll.peak_lag, 0.03);
+        assert_relative_eq!(ll.peak_correlation, 1.0, epsilon = 1e-9);
+        assert!(ll.ratio > 1.0);
+        let back = lead_lag(&b, &a, LeadLagSignal::SignedTrades, &lags).unwrap();
+        assert_eq!(back.peak_lag, -0.03);
+        assert!(back.peak_correlation > 0.9);
+        assert!(back.ratio < 1.0);
+
+        let mut stats = EventStats::new();
+        stats.symbol_events.insert("ETF".to_string(), b);
+        stats.symbol_events.insert("ONE".to_string(), a);
+        let scan = scan_most_active(&stats, 2, 100, LeadLagSignal::Returns, &lags);
+        assert_eq!(scan.len(), 1);
+        assert_eq!(scan[0].leader, "ONE");
+        assert_eq!(scan[0].lead_lag.peak_lag, 0.03);
+    }
+}
@@ -0,0 +1,386 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// Price impact and illiquidity measures from trades only.
+// Trade direction comes from the tick rule when the `EventList` has not been classified yet.
+// Dollar amounts are scaled to $1M so lambdas and Amihud ratios are readable.
+
+use std::collections::HashMap;
+use std::error::Error;
+use nalgebra::{DMatrix, DVector};
+use rust_decimal::prelude::ToPrimitive;
+use crate::event_structs::EventList;
+use crate::nyse::base_funcs::EventStats;
+
+const DOLLAR_SCALE: f64 = 1_000_000.0;
+
+/// Ordinary least squares fit.
+///
+/// - `beta`: Coefficients in column order of the design matrix.
+/// - `std_err`: Conventional (homoskedastic) standard errors.
+/// - `r_squared`: Centred R squared.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OlsFit {
+    pub beta: DVector<f64>,
+    pub std_err: DVector<f64>,
+    pub r_squared: f64,
+}
+
+/// Least squares regression of `y` on the columns of `x` (include a column of ones for an intercept).
+pub fn ols(x: &DMatrix<f64>, y: &DVector<f64>) -> Result<OlsFit, Box<dyn Error>> {
+    let (n, k) = x.shape();
+    if n <= k {
+        return Err(format!("{} observations cannot identify {} coefficients", n, k).into());
+    }
+    let xtx_inv = (x.transpose() * x).try_inverse().ok_or("regressors are collinear")?;
+    let beta = &xtx_inv * x.transpose() * y;
+    let resid = y - x * &beta;
+    let sse = resid.norm_squared();
+    let sigma2 = sse / (n - k) as f64;
+    let std_err = DVector::from_iterator(k, (0..k).map(|i| (sigma2 * xtx_inv[(i, i)]).max(0.0).sqrt()));
+    let mean = y.mean();
+    let sst = y.iter().map(|v| (v - mean).powi(2)).sum::<f64>();
+    let r_squared = if sst > 0.0 { 1.0 - sse / sst } else { 0.0 };
+    Ok(OlsFit { beta, std_err, r_squared })
+}
+
+/// Per trade (price, volume, sign), the sign taken from the tic side as it is (0 if `Unknown`).
+fn trades(event_list: &EventList) -> Vec<(f64, f64, f64)> {
+    event_list.events.values()
+        .flat_map(|e| e.tics.iter())
+        .map(|t| (t.price.to_f64().unwrap_or(0.0), t.volume as f64, t.side.sign() as f64))
+        .collect()
+}
+
+/// Per trade (price, volume, sign), tick-rule classified if no tic carries a side yet.
+fn signed_trades(event_list: &EventList) -> Vec<(f64, f64, f64)> {
+    trades(&event_list.with_trade_sides())
+}
+
+/// Per interval (close, signed dollar volume, dollar volume).
+fn interval_flows(event_list: &EventList, signs: &[(f64, f64, f64)], interval: i32) -> Vec<(f64, f64, f64)> {
+    let mut out: Vec<(f64, f64, f64)> = Vec::new();
+    let mut last_start: Option<i32> = None;
+    let tics = event_list.events.iter().flat_map(|(sec, e)| e.tics.iter().map(move |_| *sec));
+    for (sec, (price, volume, sign)) in tics.zip(signs.iter()) {
+        let start = sec.div_euclid(interval);
+        let dollars = price * volume;
+        match out.last_mut() {
+            Some(last) if last_start == Some(start) => {
+                last.0 = *price;
+                last.1 += sign * dollars;
+                last.2 += dollars;
+            }
+            _ => {
+                out.push((*price, sign * dollars, dollars));
+                last_start = Some(start);
+            }
+        }
+    }
+    out
+}
+
+/// Kyle's lambda, the slope of interval log returns on signed dollar volume.
+///
+/// # Returns
+///
+/// The fit of `r_k = c + lambda * q_k`, with `q_k` in $1M, so `beta[1]` is the return per $1M net buying.
+pub fn kyle_lambda(event_list: &EventList, interval: i32) -> Result<OlsFit, Box<dyn Error>> {
+    if interval <= 0 {
+        return Err("interval must be positive".into());
+    }
+    let trades = signed_trades(event_list);
+    let flows = interval_flows(event_list, &trades, interval);
+    if flows.iter().any(|f| f.0 <= 0.0) {
+        return Err("Kyle's lambda needs positive prices".into());
+    }
+    let n = flows.len().saturating_sub(1);
+    let x = DMatrix::from_fn(n, 2, |i, j| if j == 0 { 1.0 } else { flows[i + 1].1 / DOLLAR_SCALE });
+    let y = DVector::from_fn(n, |i, _| (flows[i + 1].0 / flows[i].0).ln());
+    ols(&x, &y)
+}
+
+/// Amihud illiquidity, the mean of `|r_k| / dollar volume_k` over intervals, per $1M.
+pub fn amihud_illiquidity(event_list: &EventList, interval: i32) -> Result<f64, Box<dyn Error>> {
+    if interval <= 0 {
+        return Err("interval must be positive".into());
+    }
+    // only dollar volume is used, so the trades are not signed
+    let flows = interval_flows(event_list, &trades(event_list), interval);
+    let ratios: Vec<f64> = flows.windows(2)
+        .filter(|w| w[0].0 > 0.0 && w[1].2 > 0.0)
+        .map(|w| (w[1].0 / w[0].0).ln().abs() / (w[1].2 / DOLLAR_SCALE))
+        .collect();
+    if ratios.is_empty() {
+        return Err("Amihud ratio needs at least 2 intervals".into());
+    }
+    Ok(ratios.iter().sum::<f64>() / ratios.len() as f64)
+}
+
+/// Hasbrouck (1991) impulse response of trade-by-trade log returns to a unit buy.
+///
+/// Fits the VAR `r_t = sum a_i r_t-i + sum_0 b_i x_t-i`, `x_t = sum c_i r_t-i + sum_1 d_i x_t-i`
+/// with `x` the trade sign, then feeds in `x_0 = 1`.
+///
+/// # Returns
+///
+/// Cumulative return after `0..=horizon` trades; the last value approximates the permanent impact.
+pub fn hasbrouck_impulse(event_list: &EventList, lags: usize, horizon: usize) -> Result<Vec<f64>, Box<dyn Error>> {
+    if lags == 0 {
+        return Err("need at least one lag".into());
+    }
+    let trades = signed_trades(event_list);
+    if trades.iter().any(|t| t.0 <= 0.0) {
+        return Err("impulse response needs positive prices".into());
+    }
+    let r: Vec<f64> = std::iter::once(0.0).chain(trades.windows(2).map(|w| (w[1].0 / w[0].0).ln())).collect();
+    let x: Vec<f64> = trades.iter().map(|t| t.2).collect();
+    if r.len() <= 3 * lags + 2 {
+        return Err(format!("{} trades is too few for {} lags", r.len(), lags).into());
+    }
+    // first return is undefined, so start once a full lag window of returns exists
+    let rows: Vec<usize> = (lags + 1..r.len()).collect();
+    let n = rows.len();
+    let xr = DMatrix::from_fn(n, 2 * lags + 2, |i, j| {
+        let t = rows[i];
+        match j {
+            0 => 1.0,
+            j if j <= lags => r[t - j],
+            j => x[t - (j - lags - 1)],
+        }
+    });
+    let xx = DMatrix::from_fn(n, 2 * lags + 1, |i, j| {
+        let t = rows[i];
+        match j {
+            0 => 1.0,
+            j if j <= lags => r[t - j],
+            j => x[t - (j - lags)],
+        }
+    });
+    let fit_r = ols(&xr, &DVector::from_fn(n, |i, _| r[rows[i]]))?;
+    let fit_x = ols(&xx, &DVector::from_fn(n, |i, _| x[rows[i]]))?;
+
+    let mut ir: Vec<f64> = vec![0.0; horizon + 1];
+    let mut ix: Vec<f64> = vec![0.0; horizon + 1];
+    for t in 0..=horizon {
+        ix[t] = if t == 0 {
+            1.0
+        } else {
+            (1..=lags.min(t)).map(|i| fit_x.beta[i] * ir[t - i] + fit_x.beta[lags + i] * ix[t - i]).sum()
+        };
+        ir[t] = (0..=lags.min(t)).map(|i| fit_r.beta[lags + 1 + i] * ix[t - i]).sum::<f64>()
+            + (1..=lags.min(t)).map(|i| fit_r.beta[i] * ir[t - i]).sum::<f64>();
+    }
+    Ok(ir.iter().scan(0.0, |acc, v| {
+        *acc += v;
+        Some(*acc)
+    }).collect())
+}
+
+/// Liquidity measures for one symbol. Measures that could not be estimated are NaN.
+///
+/// - `kyle_lambda`: Log return per $1M of net buying over `interval`.
+/// - `kyle_t_stat`, `kyle_r_squared`: Fit quality of the lambda regression.
+/// - `amihud`: Mean absolute return per $1M traded over `interval`.
+/// - `impulse_response`: Cumulative return to a unit buy, by trades since the shock.
+/// - `permanent_impact`: Last value of `impulse_response`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiquidityMetrics {
+    pub interval: i32,
+    pub trades: usize,
+    pub kyle_lambda: f64,
+    pub kyle_t_stat: f64,
+    pub kyle_r_squared: f64,
+    pub amihud: f64,
+    pub impulse_response: Vec<f64>,
+    pub permanent_impact: f64,
+}
+
+/// Computes all liquidity measures for an `EventList`.
+///
+/// # Example
+///
+/// ```ignore
+/// let metrics = event_list_liquidity(&event_list, 60, 5, 20);
+/// println!("lambda {:.6} per $1M, amihud {:.6}", metrics.kyle_lambda, metrics.amihud);
+/// ```
+pub fn event_list_liquidity(event_list: &EventList, interval: i32, lags: usize, horizon: usize) -> LiquidityMetrics {
+    // classify once, the signed measures below then borrow the classified list
+    let classified = event_list.with_trade_sides();
+    let event_list: &EventList = &classified;
+    let (kyle_lambda, kyle_t_stat, kyle_r_squared) = match kyle_lambda(event_list, interval) {
+        Ok(fit) => (fit.beta[1], fit.beta[1] / fit.std_err[1], fit.r_squared),
+        Err(_) => (f64::NAN, f64::NAN, f64::NAN),
+    };
+    let impulse_response = hasbrouck_impulse(event_list, lags, horizon).unwrap_or_default();
+    LiquidityMetrics {
+        interval,
+        trades: event_list.get_event_count(),
+        kyle_lambda,
+        kyle_t_stat,
+        kyle_r_squared,
+        amihud: amihud_illiquidity(event_list, interval).unwrap_or(f64::NAN),
+        permanent_impact: impulse_response.last().copied().unwrap_or(f64::NAN),
+        impulse_response,
+    }
+}
+
+/// Liquidity rankings, built after parsing from `EventStats`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LiquidityStats {
+    pub metrics: HashMap<String, LiquidityMetrics>,
+}
+
+impl LiquidityStats {
+    pub fn new() -> LiquidityStats {
+        LiquidityStats {
+            metrics: HashMap::new(),
+        }
+    }
+
+    /// Computes metrics for every symbol with at least `min_trades` trades.
+    pub fn update(&mut self, event_stats: &EventStats, interval: i32, lags: usize, horizon: usize, min_trades: usize) {
+        for (symbol, event_list) in event_stats.symbol_events.iter() {
+            if event_list.get_event_count() >= min_trades {
+                self.metrics.insert(symbol.clone(), event_list_liquidity(event_list, interval, lags, horizon));
+            }
+        }
+    }
+
+    fn ranked(&self, max_items: usize, value: fn(&LiquidityMetrics) -> f64) -> Vec<(String, f64)> {
+        let mut symbols: Vec<(String, f64)> = self.metrics.iter()
+            .map(|(symbol, m)| (symbol.clone(), value(m)))
+            .filter(|(_, v)| v.is_finite())
+            .collect();
+        symbols.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        symbols.truncate(max_items);
+        symbols
+    }
+
+    /// Symbols with the largest Kyle's lambda first.
+    pub fn get_highest_lambda(&self, max_items: usize) -> Vec<(String, f64)> {
+        self.ranked(max_items, |m| m.kyle_lambda)
+    }
+
+    /// Symbols with the largest Amihud ratio (least liquid) first.
+    pub fn get_most_illiquid(&self, max_items: usize) -> Vec<(String, f64)> {
+        self.ranked(max_items, |m| m.amihud)
+    }
+
+    /// Symbols with the largest permanent impact of a buy first.
+    pub fn get_highest_impact(&self, max_items: usize) -> Vec<(String, f64)> {
+        self.ranked(max_items, |m| m.permanent_impact)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_structs::test_event_list;
+    use approx::assert_relative_eq;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    /// Every trade moves the price by `impact` (log) in its direction, so the tick rule is exact.
+    fn impact_list(n: usize, price: f64, impact: f64, seed: u64) -> EventList {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut log_p = price.ln();
+        let mut tics = Vec::with_capacity(n);
+        for i in 0..n {
+            log_p += if rng.gen_bool(0.5) { impact } else { -impact };
+            tics.push((34_200 + i as i32, format!("{:.6}", log_p.exp()), 100));
+        }
+        test_event_list(&tics)
+    }
+
+    #[test]
+    fn test_ols() {
+        let x = DMatrix::from_fn(5, 2, |i, j| if j == 0 { 1.0 } else { i as f64 });
+        let y = DVector::from_vec(vec![1.0, 3.0, 5.0, 7.0, 9.0]);
+        let fit = ols(&x, &y).unwrap();
+        assert_relative_eq!(fit.beta[0], 1.0, epsilon = 1e-10);
+        assert_relative_eq!(fit.beta[1], 2.0, epsilon = 1e-10);
+        assert_relative_eq!(fit.r_squared, 1.0, epsilon = 1e-10);
+        assert!(ols(&x.rows(0, 2).into_owned(), &y.rows(0, 2).into_owned()).is_err());
+    }
+
+    #[test]
+    fn test_kyle_and_amihud() {
+        let el = impact_list(6_000, 50.0, 0.0002, 1);
+        let fit = kyle_lambda(&el, 60).unwrap();
+        // 100 shares at ~$50 per trade moves 2bp, i.e. 0.0002 / $5000
+        assert_relative_eq!(fit.beta[1], 0.0002 / 5_000.0 * DOLLAR_SCALE, max_relative = 0.05);
+        assert!(fit.r_squared > 0.95);
+        let amihud = amihud_illiquidity(&el, 60).unwrap();
+        assert!(amihud > 0.0);
+        let thin = amihud_illiquidity(&impact_list(6_000, 50.0, 0.001, 2), 60).unwrap();
+        assert!(thin > 3.0 * amihud);
+        assert!(kyle_lambda(&el, 0).is_err());
+    }
+
+    #[test]
+    fn test_hasbrouck_impulse() {
+        let el = impact_list(5_000, 50.0, 0.0002, 3);
+        let ir = hasbrouck_impulse(&el, 3, 10).unwrap();
+        assert_eq!(ir.len(), 11);
+        assert_relative_eq!(ir[0], 0.0002, max_relative = 0.02);
+        assert_relative_eq!(ir[10], 0.0002, max_relative = 0.05);
+        let metrics = event_list_liquidity(&el, 60, 3, 10);
+        assert_eq!(metrics.trades, 5_000);
+        assert_relative_eq!(metrics.permanent_impact, ir[10]);
+        assert!(metrics.kyle_t_stat > 10.0);
+        assert!(hasbrouck_impulse(&impact_list(5, 50.0, 0.0002, 4), 3, 10).is_err());
+    }
+
+    #[test]
+    fn test_liquidity_stats_ranking() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut events = EventStats::new();
+        events.init("DEEP");
+        events.init("THIN");
+        events.init("ONE");
+        for i in 0..600 {
+            let t = format!("10:{:02}:{:02}.000000000", i / 60, i % 60);
+            let up = rng.gen_bool(0.5);
+            let deep = if up { "50.00" } else { "50.01" };
+            let thin = 20.0 + if up { 0.0 } else { 0.05 } + 0.01 * rng.gen_range(0..5) as f64;
+            events.update("DEEP", &t, deep, 1000).unwrap();
+            events.update("THIN", &t, &format!("{:.2}", thin), 100).unwrap();
+        }
+        events.update("ONE", "10:00:00.000000000", "10.00", 100).unwrap();
+        let mut stats = LiquidityStats::new();
+        stats.update(&events, 60, 2, 5, 100);
+        assert_eq!(stats.metrics.len(), 2);
+        assert_eq!(stats.get_most_illiquid(1)[0].0, "THIN");
+        assert_eq!(stats.get_most_illiquid(10).len(), 2);
+    }
+}
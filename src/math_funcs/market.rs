@@ -0,0 +1,227 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// Market wide intraday series across every symbol of a file: equal and volume weighted return
+// indices, advancers/decliners/unchanged against the previous close, and new session highs/lows.
+
+use std::error::Error;
+use std::fmt::Write;
+use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
+use crate::nyse::base_funcs::{EventStats, ReferenceStats};
+
+/// One row per `interval` second bar, from the first to the last trade of the file.
+///
+/// - `equal_weighted`, `volume_weighted`: Return indices starting at 100, averaging the log
+///   returns of every symbol that has traded so far, resp. weighting them by bar volume.
+/// - `advancers`, `decliners`, `unchanged`: Symbols whose last price is above, below or at their
+///   reference, the type 3 previous close or else their first trade.
+/// - `new_highs`, `new_lows`: Symbols whose bar high (low) beats their session high (low) so far.
+/// - `symbols`: Symbols that have traded by the end of the bar.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketBreadth {
+    pub interval: i32,
+    pub times: Vec<i32>,
+    pub equal_weighted: Vec<f32>,
+    pub volume_weighted: Vec<f32>,
+    pub advancers: Vec<i32>,
+    pub decliners: Vec<i32>,
+    pub unchanged: Vec<i32>,
+    pub new_highs: Vec<i32>,
+    pub new_lows: Vec<i32>,
+    pub symbols: Vec<i32>,
+}
+
+/// Builds the market index and breadth series over `interval` second bars.
+///
+/// # Example
+///
+/// ```ignore
+/// let breadth = market_breadth(&stats.event_stats, &stats.reference_stats, 60)?;
+/// breadth.write_csv(&format!("plots/{}-market-breadth.csv", day))?;
+/// test_market_breadth_graph(day, &breadth)?;
+/// ```
+pub fn market_breadth(event_stats: &EventStats, reference: &ReferenceStats, interval: i32) -> Result<MarketBreadth, Box<dyn Error>> {
+    if interval <= 0 {
+        return Err("interval must be positive".into());
+    }
+    let first = event_stats.symbol_events.values().filter_map(|el| el.events.keys().next()).min().ok_or("no trades")?;
+    let last = event_stats.symbol_events.values().filter_map(|el| el.events.keys().next_back()).max().ok_or("no trades")?;
+    let start = first - first.rem_euclid(interval);
+    let n = ((last - start) / interval + 1) as usize;
+
+    let mut eq_sum = vec![0.0f64; n];
+    let mut vw_sum = vec![0.0f64; n];
+    let mut vw_weight = vec![0.0f64; n];
+    let mut out = MarketBreadth {
+        interval,
+        times: (0..n).map(|k| start + k as i32 * interval).collect(),
+        equal_weighted: Vec::with_capacity(n),
+        volume_weighted: Vec::with_capacity(n),
+        advancers: vec![0; n],
+        decliners: vec![0; n],
+        unchanged: vec![0; n],
+        new_highs: vec![0; n],
+        new_lows: vec![0; n],
+        symbols: vec![0; n],
+    };
+    for (symbol, event_list) in event_stats.symbol_events.iter() {
+        let bars = event_list.get_bars(interval);
+        let Some(first_bar) = bars.first() else { continue };
+        let open = first_bar.open.to_f64().unwrap_or(0.0);
+        if open <= 0.0 {
+            continue;
+        }
+        // breadth compares the exact feed prices, only the returns go through f64
+        let reference = reference.get_prev_close(symbol).filter(|p| *p > Decimal::ZERO).unwrap_or(first_bar.open);
+        let mut last = first_bar.open;
+        let mut previous = open;
+        let mut high = f64::NEG_INFINITY;
+        let mut low = f64::INFINITY;
+        let mut next_bar = bars.iter().peekable();
+        let first_slot = ((first_bar.start - start) / interval) as usize;
+        for k in first_slot..n {
+            let mut close = previous;
+            if let Some(bar) = next_bar.next_if(|b| b.start == out.times[k]) {
+                last = bar.close;
+                close = bar.close.to_f64().unwrap_or(previous);
+                let (bar_high, bar_low) = (bar.high.to_f64().unwrap_or(close), bar.low.to_f64().unwrap_or(close));
+                if k > first_slot {
+                    out.new_highs[k] += (bar_high > high) as i32;
+                    out.new_lows[k] += (bar_low < low) as i32;
+                }
+                high = high.max(bar_high);
+                low = low.min(bar_low);
+                let r = (close / previous).ln();
+                eq_sum[k] += r;
+                vw_sum[k] += r * bar.volume as f64;
+                vw_weight[k] += bar.volume as f64;
+            }
+            out.symbols[k] += 1;
+            if last > reference {
+                out.advancers[k] += 1;
+            } else if last < reference {
+                out.decliners[k] += 1;
+            } else {
+                out.unchanged[k] += 1;
+            }
+            previous = close;
+        }
+    }
+    let (mut eq, mut vw) = (0.0f64, 0.0f64);
+    for k in 0..n {
+        if out.symbols[k] > 0 {
+            eq += eq_sum[k] / out.symbols[k] as f64;
+        }
+        if vw_weight[k] > 0.0 {
+            vw += vw_sum[k] / vw_weight[k];
+        }
+        out.equal_weighted.push((100.0 * eq.exp()) as f32);
+        out.volume_weighted.push((100.0 * vw.exp()) as f32);
+    }
+    Ok(out)
+}
+
+impl MarketBreadth {
+    /// Advancers minus decliners per bar.
+    pub fn net_advancers(&self) -> Vec<i32> {
+        self.advancers.iter().zip(self.decliners.iter()).map(|(a, d)| a - d).collect()
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("time,equal_weighted,volume_weighted,advancers,decliners,unchanged,new_highs,new_lows,symbols\n");
+        for k in 0..self.times.len() {
+            writeln!(out, "{},{:.6},{:.6},{},{},{},{},{},{}", self.times[k], self.equal_weighted[k], self.volume_weighted[k],
+                     self.advancers[k], self.decliners[k], self.unchanged[k], self.new_highs[k], self.new_lows[k], self.symbols[k]).unwrap();
+        }
+        out
+    }
+
+    pub fn write_csv(&self, file_name: &str) -> Result<(), Box<dyn Error>> {
+        std::fs::write(file_name, self.to_csv())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_structs::test_event_list;
+    use approx::assert_relative_eq;
+    use crate::nyse::mt3::T3;
+
+    #[test]
+    fn test_market_breadth() {
+        let mut stats = EventStats::new();
+        // UP rallies 10.00 -> 11.00 over three minutes, DOWN falls from its previous close
+        stats.symbol_events.insert("UP".to_string(),
+            test_event_list(&[(34_205, "10.00", 100), (34_265, "10.50", 100), (34_325, "11.00", 100)]));
+        stats.symbol_events.insert("DOWN".to_string(),
+            test_event_list(&[(34_210, "20.00", 300), (34_270, "19.00", 300), (34_330, "19.50", 300)]));
+        stats.symbol_events.insert("LATE".to_string(), test_event_list(&[(34_350, "5.00", 100)]));
+        let mut reference = ReferenceStats::new();
+        let line = "3,1,DOWN,1,0,N,C,100,21.00,1000,0,Y,0.01,1";
+        reference.add(&T3::new(line.split(',').map(|s| s.to_string()).collect()).unwrap());
+
+        let breadth = market_breadth(&stats, &reference, 60).unwrap();
+        assert_eq!(breadth.times, vec![34_200, 34_260, 34_320]);
+        assert_eq!(breadth.symbols, vec![2, 2, 3]);
+        assert_eq!(breadth.advancers, vec![0, 1, 1]);
+        assert_eq!(breadth.decliners, vec![1, 1, 1]);
+        assert_eq!(breadth.unchanged, vec![1, 0, 1]);
+        assert_eq!(breadth.new_highs, vec![0, 1, 1]);
+        assert_eq!(breadth.new_lows, vec![0, 1, 0]);
+        assert_eq!(breadth.net_advancers(), vec![-1, 0, 0]);
+        // minute 2: UP +ln(1.05), DOWN +ln(0.95)
+        let r = (1.05f64.ln() + 0.95f64.ln()) / 2.0;
+        assert_relative_eq!(breadth.equal_weighted[1] as f64, 100.0 * r.exp(), epsilon = 1e-4);
+        let v = (1.05f64.ln() * 100.0 + 0.95f64.ln() * 300.0) / 400.0;
+        assert_relative_eq!(breadth.volume_weighted[1] as f64, 100.0 * v.exp(), epsilon = 1e-4);
+        assert_eq!(breadth.to_csv().lines().count(), 4);
+    }
+
+    #[test]
+    fn test_market_breadth_inexact_close() {
+        // 141.21 has no exact binary form, a flat stock must still count as unchanged
+        let mut stats = EventStats::new();
+        stats.symbol_events.insert("IBM".to_string(),
+            test_event_list(&[(34_205, "141.21", 100), (34_265, "141.22", 100), (34_325, "141.20", 100)]));
+        let mut reference = ReferenceStats::new();
+        let line = "3,12,IBM,1,0,N,C,100,141.21,3459010,0,Y,0.01,1";
+        reference.add(&T3::new(line.split(',').map(|s| s.to_string()).collect()).unwrap());
+
+        let breadth = market_breadth(&stats, &reference, 60).unwrap();
+        assert_eq!(breadth.unchanged, vec![1, 0, 0]);
+        assert_eq!(breadth.advancers, vec![0, 1, 0]);
+        assert_eq!(breadth.decliners, vec![0, 0, 1]);
+    }
+}
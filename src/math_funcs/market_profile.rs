@@ -0,0 +1,273 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// Volume at price and TPO (time price opportunity) market profiles of an `EventList`, with
+// point of control and value area, in price buckets of a chosen number of ticks.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write;
+use rust_decimal::Decimal;
+use crate::event_structs::EventList;
+use crate::time_funcs::{s2hhmmss_32, CORE_CLOSE_SECS, CORE_OPEN_SECS};
+
+const TPO_LETTERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Lower edge of the bucket holding `price`.
+pub fn price_bucket(price: Decimal, bucket: Decimal) -> Decimal {
+    (price / bucket).floor() * bucket
+}
+
+/// Point of control and value area of a per level count, levels ascending.
+///
+/// Starting at the point of control (the largest count, the lower one on ties) the value area
+/// grows by whichever neighbouring level holds more until it covers `value_area` of the total.
+fn value_area(levels: &[(Decimal, i64)], value_area: f64) -> (usize, usize, usize) {
+    let poc = (0..levels.len()).fold(0, |best, i| if levels[i].1 > levels[best].1 { i } else { best });
+    let total: i64 = levels.iter().map(|l| l.1).sum();
+    let target = value_area * total as f64;
+    let (mut lo, mut hi) = (poc, poc);
+    let mut covered = levels[poc].1;
+    while (covered as f64) < target && (lo > 0 || hi + 1 < levels.len()) {
+        let below = if lo > 0 { levels[lo - 1].1 } else { -1 };
+        let above = if hi + 1 < levels.len() { levels[hi + 1].1 } else { -1 };
+        if above >= below {
+            hi += 1;
+            covered += above;
+        } else {
+            lo -= 1;
+            covered += below;
+        }
+    }
+    (poc, lo, hi)
+}
+
+/// Volume traded per price bucket.
+///
+/// - `levels`: `(bucket low, volume)`, ascending in price.
+/// - `poc`: Bucket with the most volume.
+/// - `value_area_low`, `value_area_high`: Bucket range holding `value_area` of the volume.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VolumeProfile {
+    pub bucket: Decimal,
+    pub levels: Vec<(Decimal, i64)>,
+    pub total_volume: i64,
+    pub value_area: f64,
+    pub poc: Decimal,
+    pub value_area_low: Decimal,
+    pub value_area_high: Decimal,
+}
+
+/// Builds the volume profile of an `EventList`.
+///
+/// # Arguments
+///
+/// * `bucket` - Bucket size in price, e.g. `Decimal::new(5, 2)` for five cent buckets
+/// * `value_area` - Share of volume in the value area, conventionally 0.7
+///
+/// # Example
+///
+/// ```ignore
+/// let profile = volume_profile(&el, Decimal::new(1, 2), 0.7)?;
+/// println!("POC {} value area {} - {}", profile.poc, profile.value_area_low, profile.value_area_high);
+/// ```
+pub fn volume_profile(event_list: &EventList, bucket: Decimal, value_area_share: f64) -> Result<VolumeProfile, Box<dyn Error>> {
+    if bucket <= Decimal::ZERO {
+        return Err("bucket must be positive".into());
+    }
+    let mut volume: BTreeMap<Decimal, i64> = BTreeMap::new();
+    for tic in event_list.events.values().flat_map(|e| e.tics.iter()) {
+        *volume.entry(price_bucket(tic.price, bucket)).or_default() += tic.volume as i64;
+    }
+    if volume.is_empty() {
+        return Err("no trades".into());
+    }
+    let levels: Vec<(Decimal, i64)> = volume.into_iter().collect();
+    let (poc, lo, hi) = value_area(&levels, value_area_share);
+    Ok(VolumeProfile {
+        bucket,
+        total_volume: levels.iter().map(|l| l.1).sum(),
+        value_area: value_area_share,
+        poc: levels[poc].0,
+        value_area_low: levels[lo].0,
+        value_area_high: levels[hi].0,
+        levels,
+    })
+}
+
+impl VolumeProfile {
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("price,volume,poc,value_area\n");
+        for (price, volume) in self.levels.iter() {
+            let in_area = *price >= self.value_area_low && *price <= self.value_area_high;
+            writeln!(out, "{},{},{},{}", price, volume, (*price == self.poc) as i32, in_area as i32).unwrap();
+        }
+        out
+    }
+}
+
+/// TPO market profile, one letter per period in which price traded in a bucket.
+///
+/// - `periods`: `(period start, letter)` of the periods that traded, `A` being the period starting
+///   at the 09:30 open; periods without trades keep their letter but are not listed.
+/// - `levels`: `(bucket low, letters)`, ascending in price.
+/// - `poc`, `value_area_low`, `value_area_high`: As for `VolumeProfile` on letter counts.
+/// - `initial_balance`: Low and high of periods `A` and `B`, or of the first traded period when
+///   neither traded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TpoProfile {
+    pub bucket: Decimal,
+    pub period: i32,
+    pub periods: Vec<(i32, char)>,
+    pub levels: Vec<(Decimal, String)>,
+    pub value_area: f64,
+    pub poc: Decimal,
+    pub value_area_low: Decimal,
+    pub value_area_high: Decimal,
+    pub initial_balance: (Decimal, Decimal),
+}
+
+/// Builds the TPO profile of an `EventList` over `period` second periods (1800 for the usual
+/// 30 minutes) of the core session, at most 52 periods. Pre- and post-market trades are ignored.
+pub fn tpo_profile(event_list: &EventList, bucket: Decimal, period: i32, value_area_share: f64) -> Result<TpoProfile, Box<dyn Error>> {
+    if bucket <= Decimal::ZERO || period <= 0 {
+        return Err("bucket and period must be positive".into());
+    }
+    let letters: Vec<char> = TPO_LETTERS.chars().collect();
+    if (CORE_CLOSE_SECS - CORE_OPEN_SECS + period - 1) / period > letters.len() as i32 {
+        return Err(format!("more than {} periods in the core session", letters.len()).into());
+    }
+    // low and high per period index, counted from the open
+    let mut ranges: BTreeMap<usize, (Decimal, Decimal)> = BTreeMap::new();
+    for (second, event) in event_list.events.range(CORE_OPEN_SECS..CORE_CLOSE_SECS) {
+        let index = ((second - CORE_OPEN_SECS) / period) as usize;
+        for tic in event.tics.iter() {
+            let range = ranges.entry(index).or_insert((tic.price, tic.price));
+            *range = (range.0.min(tic.price), range.1.max(tic.price));
+        }
+    }
+    let first = *ranges.values().next().ok_or("no core session trades")?;
+    let initial_balance = ranges.range(..2).map(|(_, r)| *r).reduce(|a, b| (a.0.min(b.0), a.1.max(b.1))).unwrap_or(first);
+    let mut touched: BTreeMap<Decimal, String> = BTreeMap::new();
+    let mut periods = Vec::with_capacity(ranges.len());
+    for (index, (low, high)) in ranges.into_iter() {
+        let letter = letters[index];
+        periods.push((CORE_OPEN_SECS + index as i32 * period, letter));
+        let mut level = price_bucket(low, bucket);
+        while level <= high {
+            touched.entry(level).or_default().push(letter);
+            level += bucket;
+        }
+    }
+    let levels: Vec<(Decimal, String)> = touched.into_iter().collect();
+    let counts: Vec<(Decimal, i64)> = levels.iter().map(|(p, l)| (*p, l.len() as i64)).collect();
+    let (poc, lo, hi) = value_area(&counts, value_area_share);
+    Ok(TpoProfile {
+        bucket,
+        period,
+        periods,
+        value_area: value_area_share,
+        poc: levels[poc].0,
+        value_area_low: levels[lo].0,
+        value_area_high: levels[hi].0,
+        initial_balance,
+        levels,
+    })
+}
+
+impl TpoProfile {
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("price,tpo_count,letters,poc,value_area\n");
+        for (price, letters) in self.levels.iter() {
+            let in_area = *price >= self.value_area_low && *price <= self.value_area_high;
+            writeln!(out, "{},{},{},{},{}", price, letters.len(), letters, (*price == self.poc) as i32, in_area as i32).unwrap();
+        }
+        out
+    }
+
+    /// Period start times as `HH:MM:SS` per letter.
+    pub fn legend(&self) -> Vec<(char, String)> {
+        self.periods.iter().map(|(t, c)| (*c, s2hhmmss_32(*t))).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_structs::test_event_list;
+    use std::str::FromStr;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_volume_profile() {
+        let el = test_event_list(&[(34_200, "10.00", 100), (34_201, "10.01", 300), (34_202, "10.02", 500), (34_203, "10.03", 200),
+                                   (34_204, "10.04", 50), (34_205, "10.06", 100), (34_206, "10.07", 10)]);
+        let profile = volume_profile(&el, dec("0.01"), 0.7).unwrap();
+        assert_eq!(profile.total_volume, 1260);
+        assert_eq!(profile.poc, dec("10.02"));
+        // 500 + 300 + 200 = 1000 >= 882
+        assert_eq!((profile.value_area_low, profile.value_area_high), (dec("10.01"), dec("10.03")));
+        let coarse = volume_profile(&el, dec("0.05"), 0.7).unwrap();
+        assert_eq!(coarse.levels, vec![(dec("10.00"), 1150), (dec("10.05"), 110)]);
+        assert!(profile.to_csv().contains("\n10.02,500,1,1\n"));
+        assert!(volume_profile(&EventList::new(), dec("0.01"), 0.7).is_err());
+    }
+
+    #[test]
+    fn test_tpo_profile() {
+        // A trades 10.00-10.02, B 10.01-10.03, C 10.02 only,
+        // the pre-market and after-hours trades get no letter
+        let el = test_event_list(&[(30_000, "9.00", 100), (34_200, "10.00", 100), (34_201, "10.02", 100),
+                                   (36_000, "10.01", 100), (36_001, "10.03", 100), (37_800, "10.02", 100),
+                                   (37_801, "10.02", 100), (58_000, "11.00", 100)]);
+        let tpo = tpo_profile(&el, dec("0.01"), 1800, 0.7).unwrap();
+        assert_eq!(tpo.periods.iter().map(|p| p.1).collect::<String>(), "ABC");
+        assert_eq!(tpo.periods[0].0, CORE_OPEN_SECS);
+        assert_eq!(tpo.levels, vec![(dec("10.00"), "A".to_string()), (dec("10.01"), "AB".to_string()),
+                                    (dec("10.02"), "ABC".to_string()), (dec("10.03"), "B".to_string())]);
+        assert_eq!(tpo.poc, dec("10.02"));
+        assert_eq!(tpo.initial_balance, (dec("10.00"), dec("10.03")));
+        assert_eq!(tpo.legend()[1], ('B', "10:00:00".to_string()));
+        assert!(tpo.to_csv().contains("\n10.02,3,ABC,1,1\n"));
+        assert!(tpo_profile(&el, dec("0.01"), 300, 0.7).is_err());
+
+        // a session whose first trade comes in the second period starts at B
+        let late = test_event_list(&[(29_000, "9.50", 100), (36_100, "10.00", 100), (37_900, "10.05", 100)]);
+        let tpo = tpo_profile(&late, dec("0.05"), 1800, 0.7).unwrap();
+        assert_eq!(tpo.periods, vec![(36_000, 'B'), (37_800, 'C')]);
+        assert_eq!(tpo.initial_balance, (dec("10.00"), dec("10.00")));
+        assert!(tpo_profile(&EventList::new(), dec("0.01"), 1800, 0.7).is_err());
+    }
+}
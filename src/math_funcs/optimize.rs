@@ -0,0 +1,241 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// Derivative free minimisation and finite difference derivatives for the maximum
+// likelihood fits. Likelihoods here are cheap and low dimensional, so Nelder-Mead is enough.
+
+use std::error::Error;
+use nalgebra::DMatrix;
+
+/// Result of a minimisation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Minimum {
+    pub x: Vec<f64>,
+    pub value: f64,
+    pub iterations: usize,
+    pub converged: bool,
+}
+
+/// Nelder-Mead simplex minimisation of `f` starting at `x0`.
+///
+/// The initial simplex steps each coordinate by `step`. Stops when the spread of the
+/// function values across the simplex falls below `tol` or after `max_iter` iterations.
+/// Non-finite function values are treated as +inf, so `f` can reject infeasible points.
+///
+/// # Example
+///
+/// ```
+/// # use feed_parser::math_funcs::optimize::nelder_mead;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let min = nelder_mead(|x| (x[0] - 1.0).powi(2) + (x[1] + 2.0).powi(2), &[0.0, 0.0], 0.5, 1e-12, 2_000)?;
+/// assert!((min.x[0] - 1.0).abs() < 1e-4 && (min.x[1] + 2.0).abs() < 1e-4);
+/// # Ok(())
+/// # }
+/// ```
+pub fn nelder_mead<F: Fn(&[f64]) -> f64>(f: F, x0: &[f64], step: f64, tol: f64, max_iter: usize) -> Result<Minimum, Box<dyn Error>> {
+    let n = x0.len();
+    if n == 0 {
+        return Err("nothing to minimise".into());
+    }
+    let eval = |x: &[f64]| {
+        let v = f(x);
+        if v.is_finite() { v } else { f64::INFINITY }
+    };
+    let mut simplex: Vec<(Vec<f64>, f64)> = Vec::with_capacity(n + 1);
+    simplex.push((x0.to_vec(), eval(x0)));
+    if !simplex[0].1.is_finite() {
+        return Err("objective is not finite at the starting point".into());
+    }
+    for i in 0..n {
+        let mut x = x0.to_vec();
+        x[i] += if x[i].abs() > 1e-8 { step * x[i].abs().max(1.0) } else { step };
+        let v = eval(&x);
+        simplex.push((x, v));
+    }
+
+    let mut iterations = 0;
+    let mut converged = false;
+    while iterations < max_iter {
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let best = simplex[0].1;
+        let worst = simplex[n].1;
+        if (worst - best).abs() <= tol * (best.abs() + tol) {
+            converged = true;
+            break;
+        }
+        iterations += 1;
+
+        let centroid: Vec<f64> = (0..n).map(|j| simplex[..n].iter().map(|(x, _)| x[j]).sum::<f64>() / n as f64).collect();
+        let along = |t: f64| -> Vec<f64> {
+            centroid.iter().zip(simplex[n].0.iter()).map(|(c, w)| c + t * (w - c)).collect()
+        };
+        let reflected = along(-1.0);
+        let fr = eval(&reflected);
+        if fr < simplex[0].1 {
+            let expanded = along(-2.0);
+            let fe = eval(&expanded);
+            simplex[n] = if fe < fr { (expanded, fe) } else { (reflected, fr) };
+        } else if fr < simplex[n - 1].1 {
+            simplex[n] = (reflected, fr);
+        } else {
+            let contracted = if fr < worst { along(-0.5) } else { along(0.5) };
+            let fc = eval(&contracted);
+            if fc < worst.min(fr) {
+                simplex[n] = (contracted, fc);
+            } else {
+                // shrink towards the best point
+                let best_x = simplex[0].0.clone();
+                for vertex in simplex.iter_mut().skip(1) {
+                    let x: Vec<f64> = best_x.iter().zip(vertex.0.iter()).map(|(b, v)| b + 0.5 * (v - b)).collect();
+                    let v = eval(&x);
+                    *vertex = (x, v);
+                }
+            }
+        }
+    }
+    simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+    let (x, value) = simplex.swap_remove(0);
+    Ok(Minimum { x, value, iterations, converged })
+}
+
+/// Runs `nelder_mead` and restarts it from the best point until a restart no longer improves
+/// the minimum, which guards against a collapsed simplex.
+pub fn nelder_mead_restarts<F: Fn(&[f64]) -> f64>(f: F, x0: &[f64], step: f64, tol: f64, max_iter: usize) -> Result<Minimum, Box<dyn Error>> {
+    let mut best = nelder_mead(&f, x0, step, tol, max_iter)?;
+    for _ in 0..5 {
+        let next = nelder_mead(&f, &best.x, step, tol, max_iter)?;
+        let improved = next.value < best.value - tol * (best.value.abs() + tol);
+        let iterations = best.iterations + next.iterations;
+        if next.value <= best.value {
+            best = Minimum { iterations, ..next };
+        }
+        if !improved {
+            break;
+        }
+    }
+    Ok(best)
+}
+
+/// Central difference gradient of `f` at `x` with relative step `h`.
+pub fn numerical_gradient<F: Fn(&[f64]) -> f64>(f: F, x: &[f64], h: f64) -> Vec<f64> {
+    let mut xp = x.to_vec();
+    (0..x.len()).map(|i| {
+        let hi = h * x[i].abs().max(1.0);
+        xp[i] = x[i] + hi;
+        let fp = f(&xp);
+        xp[i] = x[i] - hi;
+        let fm = f(&xp);
+        xp[i] = x[i];
+        (fp - fm) / (2.0 * hi)
+    }).collect()
+}
+
+/// Central difference Hessian of `f` at `x` with relative step `h`.
+pub fn numerical_hessian<F: Fn(&[f64]) -> f64>(f: F, x: &[f64], h: f64) -> DMatrix<f64> {
+    let n = x.len();
+    let steps: Vec<f64> = x.iter().map(|v| h * v.abs().max(1.0)).collect();
+    let f0 = f(x);
+    let mut hess = DMatrix::zeros(n, n);
+    let mut xp = x.to_vec();
+    for i in 0..n {
+        xp[i] = x[i] + steps[i];
+        let fp = f(&xp);
+        xp[i] = x[i] - steps[i];
+        let fm = f(&xp);
+        xp[i] = x[i];
+        hess[(i, i)] = (fp - 2.0 * f0 + fm) / (steps[i] * steps[i]);
+        for j in 0..i {
+            let mut corner = |si: f64, sj: f64| {
+                xp[i] = x[i] + si * steps[i];
+                xp[j] = x[j] + sj * steps[j];
+                let v = f(&xp);
+                xp[i] = x[i];
+                xp[j] = x[j];
+                v
+            };
+            let v = (corner(1.0, 1.0) - corner(1.0, -1.0) - corner(-1.0, 1.0) + corner(-1.0, -1.0))
+                / (4.0 * steps[i] * steps[j]);
+            hess[(i, j)] = v;
+            hess[(j, i)] = v;
+        }
+    }
+    hess
+}
+
+/// Standard errors from the inverse Hessian of a negative log likelihood at its minimum.
+///
+/// Parameters whose variance comes out negative (a Hessian that is not positive definite) get NaN.
+pub fn hessian_std_errors<F: Fn(&[f64]) -> f64>(neg_log_likelihood: F, x: &[f64], h: f64) -> Result<Vec<f64>, Box<dyn Error>> {
+    let cov = numerical_hessian(neg_log_likelihood, x, h).try_inverse().ok_or("Hessian is singular")?;
+    Ok((0..x.len()).map(|i| if cov[(i, i)] > 0.0 { cov[(i, i)].sqrt() } else { f64::NAN }).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_nelder_mead() {
+        let quad = |x: &[f64]| (x[0] - 1.0).powi(2) + 10.0 * (x[1] + 2.0).powi(2);
+        let min = nelder_mead(quad, &[0.0, 0.0], 0.5, 1e-14, 5_000).unwrap();
+        assert!(min.converged);
+        assert_relative_eq!(min.x[0], 1.0, epsilon = 1e-5);
+        assert_relative_eq!(min.x[1], -2.0, epsilon = 1e-5);
+
+        let rosenbrock = |x: &[f64]| (1.0 - x[0]).powi(2) + 100.0 * (x[1] - x[0] * x[0]).powi(2);
+        let min = nelder_mead_restarts(rosenbrock, &[-1.2, 1.0], 0.5, 1e-14, 10_000).unwrap();
+        assert_relative_eq!(min.x[0], 1.0, epsilon = 1e-4);
+        assert_relative_eq!(min.x[1], 1.0, epsilon = 1e-4);
+
+        // infeasible region is rejected
+        let bounded = |x: &[f64]| if x[0] <= 0.0 { f64::NAN } else { x[0] - x[0].ln() };
+        let min = nelder_mead(bounded, &[3.0], 0.5, 1e-14, 1_000).unwrap();
+        assert_relative_eq!(min.x[0], 1.0, epsilon = 1e-4);
+        assert!(nelder_mead(bounded, &[-1.0], 0.5, 1e-14, 1_000).is_err());
+    }
+
+    #[test]
+    fn test_derivatives() {
+        let f = |x: &[f64]| 3.0 * x[0] * x[0] + 2.0 * x[0] * x[1] + x[1] * x[1] * x[1];
+        let g = numerical_gradient(f, &[1.0, 2.0], 1e-5);
+        assert_relative_eq!(g[0], 10.0, epsilon = 1e-6);
+        assert_relative_eq!(g[1], 14.0, epsilon = 1e-6);
+        let h = numerical_hessian(f, &[1.0, 2.0], 1e-4);
+        assert_relative_eq!(h[(0, 0)], 6.0, epsilon = 1e-4);
+        assert_relative_eq!(h[(0, 1)], 2.0, epsilon = 1e-4);
+        assert_relative_eq!(h[(1, 1)], 12.0, epsilon = 1e-4);
+        // Gaussian mean: se = sigma / sqrt(n) with n = 4, sigma = 2
+        let nll = |x: &[f64]| 4.0 * (x[0] - 0.3).powi(2) / 8.0;
+        assert_relative_eq!(hessian_std_errors(nll, &[0.3], 1e-4).unwrap()[0], 1.0, epsilon = 1e-4);
+    }
+}
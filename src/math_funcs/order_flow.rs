@@ -0,0 +1,122 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// Signed order flow built from tick-rule classified trades.
+// Run `EventList::classify_trades` first, unclassified tics contribute nothing.
+
+use crate::event_structs::EventList;
+
+/// Order flow for one interval.
+///
+/// - `start`: Interval start in seconds after midnight.
+/// - `buy_volume`, `sell_volume`: Classified volume in the interval.
+/// - `imbalance`: `(buy - sell) / (buy + sell)`, 0 when nothing was classified.
+/// - `cumulative`: Running sum of `buy - sell` up to and including this interval.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderFlow {
+    pub start: i32,
+    pub buy_volume: i64,
+    pub sell_volume: i64,
+    pub imbalance: f32,
+    pub cumulative: i64,
+}
+
+/// Signed volume of every tic as f32, positive for buyer initiated trades.
+pub fn signed_volume(event_list: &EventList) -> Vec<f32> {
+    event_list.get_signed_volume_series().iter().map(|(_, v)| *v as f32).collect()
+}
+
+/// Buy/sell volume and cumulative order flow imbalance per `interval` seconds.
+///
+/// Intervals are aligned to multiples of `interval` and run from the first to the last
+/// trade, with empty intervals included so the cumulative series stays evenly spaced.
+///
+/// # Example
+///
+/// ```ignore
+/// event_list.classify_trades(TickRule::Tick);
+/// let flow = order_flow_imbalance(&event_list, 60);
+/// ```
+pub fn order_flow_imbalance(event_list: &EventList, interval: i32) -> Vec<OrderFlow> {
+    let seconds = event_list.get_sec_buy_sell_volume();
+    let (first, last) = match (event_list.events.keys().next(), event_list.events.keys().last()) {
+        (Some(f), Some(l)) if interval > 0 => (f.div_euclid(interval), l.div_euclid(interval)),
+        _ => return Vec::new(),
+    };
+    let mut out: Vec<OrderFlow> = (first..=last).map(|b| OrderFlow {
+        start: b * interval,
+        buy_volume: 0,
+        sell_volume: 0,
+        imbalance: 0.0,
+        cumulative: 0,
+    }).collect();
+    for ((_, buy, sell), idx) in seconds.iter().zip(event_list.events.keys()) {
+        let bucket = &mut out[(idx.div_euclid(interval) - first) as usize];
+        bucket.buy_volume += *buy as i64;
+        bucket.sell_volume += *sell as i64;
+    }
+    let mut cumulative: i64 = 0;
+    for bucket in out.iter_mut() {
+        let total = bucket.buy_volume + bucket.sell_volume;
+        if total > 0 {
+            bucket.imbalance = (bucket.buy_volume - bucket.sell_volume) as f32 / total as f32;
+        }
+        cumulative += bucket.buy_volume - bucket.sell_volume;
+        bucket.cumulative = cumulative;
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_structs::TickRule;
+
+    #[test]
+    fn test_order_flow_imbalance() {
+        let mut el = EventList::new();
+        let _ = el.update("09:30:00.100000000", "10.00", 100);
+        let _ = el.update("09:30:10.100000000", "10.01", 300);
+        let _ = el.update("09:30:50.100000000", "10.00", 100);
+        let _ = el.update("09:32:05.100000000", "10.02", 200);
+        assert!(order_flow_imbalance(&el, 60).iter().all(|f| f.cumulative == 0));
+        el.classify_trades(TickRule::Tick);
+        assert_eq!(signed_volume(&el), vec![0.0, 300.0, -100.0, 200.0]);
+        let flow = order_flow_imbalance(&el, 60);
+        assert_eq!(flow.len(), 3);
+        assert_eq!(flow[0], OrderFlow { start: 34200, buy_volume: 300, sell_volume: 100, imbalance: 0.5, cumulative: 200 });
+        assert_eq!(flow[1].imbalance, 0.0);
+        assert_eq!(flow[1].cumulative, 200);
+        assert_eq!(flow[2].cumulative, 400);
+        assert!(order_flow_imbalance(&EventList::new(), 60).is_empty());
+    }
+}
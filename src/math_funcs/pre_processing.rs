@@ -37,6 +37,11 @@ use welch_sde::{Build, PowerSpectrum, SpectralDensity};
 use std::fmt::Write;
 use rand::{thread_rng, Rng};
 use std::collections::HashMap;
+use rust_decimal::prelude::ToPrimitive;
+use std::error::Error;
+use nalgebra::{DMatrix, DVector};
+use crate::event_structs::EventList;
+use crate::time_funcs::{CORE_CLOSE_SECS, CORE_OPEN_SECS};
 
 /// Extracts and separates the elements from a vector of tuples into individual vectors.
 ///
@@ -135,6 +140,135 @@ pub fn detrend(inp: &Vec<f32>) -> Vec<f32> {
     detrended
 }
 
+/// Detrending methods available to `detrend_with`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DetrendMode {
+    /// Least squares straight line, the same fit as `detrend`.
+    Linear,
+    /// Least squares polynomial of the given degree.
+    Polynomial(usize),
+    /// Separate least squares line in each segment. The values are the indices where a new
+    /// segment starts, see `session_breakpoints`.
+    PiecewiseLinear(Vec<usize>),
+    /// Centred rolling mean over the given number of points.
+    RollingMean(usize),
+    /// First differences, the trend removed from each point is the previous point.
+    Difference,
+}
+
+/// Least squares polynomial trend of the given degree, evaluated at every point.
+///
+/// The abscissa is scaled to [-1, 1] and the system is solved by SVD so high degrees stay well conditioned.
+fn polynomial_trend(data: &[f64], degree: usize) -> Result<Vec<f64>, Box<dyn Error>> {
+    let n = data.len();
+    if n == 0 {
+        return Ok(Vec::new());
+    }
+    if n <= degree {
+        return Err(format!("{} points cannot fit a polynomial of degree {}", n, degree).into());
+    }
+    let half = ((n - 1) as f64 / 2.0).max(1.0);
+    let x: Vec<f64> = (0..n).map(|i| (i as f64 - half) / half).collect();
+    let a = DMatrix::from_fn(n, degree + 1, |r, c| x[r].powi(c as i32));
+    let y = DVector::from_column_slice(data);
+    let coeffs = a.clone().svd(true, true).solve(&y, 1e-12)?;
+    Ok((a * coeffs).iter().copied().collect())
+}
+
+/// Finds the indices where a series crosses from one trading session to the next.
+///
+/// `times` are the seconds after midnight of each point, in order. Sessions are the pre-open
+/// (before 09:30), the core session and the late session (16:00 onward). The result is suitable
+/// for `DetrendMode::PiecewiseLinear`.
+///
+/// # Example
+///
+/// ```
+/// # use feed_parser::math_funcs::pre_processing::session_breakpoints;
+/// let breaks = session_breakpoints(&[30_000, 34_200, 40_000, 58_000]);
+/// assert_eq!(breaks, vec![1, 3]);
+/// ```
+pub fn session_breakpoints(times: &[i32]) -> Vec<usize> {
+    let session = |t: i32| -> u8 {
+        if t < CORE_OPEN_SECS { 0 } else if t < CORE_CLOSE_SECS { 1 } else { 2 }
+    };
+    (1..times.len()).filter(|i| session(times[*i]) != session(times[i - 1])).collect()
+}
+
+/// Removes a trend from a series using the selected method.
+///
+/// This generalises `detrend`, which only removes a single straight line. A full trading day
+/// is usually U-shaped, so a polynomial, a line per session or a rolling mean fits it far better.
+///
+/// # Arguments
+///
+/// * `inp` - The series to detrend.
+/// * `mode` - The detrending method.
+///
+/// # Returns
+///
+/// * `Ok((detrended, trend))` - The detrended series and the trend that was removed, so
+///   `detrended[i] + trend[i]` gives back the input. For `DetrendMode::Difference` both are one
+///   point shorter than the input and `trend[i]` is the previous value `inp[i]`, so
+///   `detrended[i] + trend[i]` gives back `inp[i + 1]`.
+/// * `Err` if a polynomial has more coefficients than there are points.
+///
+/// # Example
+///
+/// ```
+/// # use feed_parser::math_funcs::pre_processing::{detrend_with, DetrendMode};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let prices: Vec<f32> = (0..100).map(|i| 50.0 + 0.01 * (i as f32 - 50.0).powi(2)).collect();
+/// let (detrended, trend) = detrend_with(&prices, &DetrendMode::Polynomial(3))?;
+/// # assert_eq!(detrended.len(), trend.len());
+/// # Ok(())
+/// # }
+/// ```
+pub fn detrend_with(inp: &[f32], mode: &DetrendMode) -> Result<(Vec<f32>, Vec<f32>), Box<dyn Error>> {
+    let data: Vec<f64> = inp.iter().map(|x| *x as f64).collect();
+    let trend: Vec<f64> = match mode {
+        DetrendMode::Linear => polynomial_trend(&data, 1)?,
+        DetrendMode::Polynomial(degree) => polynomial_trend(&data, *degree)?,
+        DetrendMode::PiecewiseLinear(breaks) => {
+            let mut bounds: Vec<usize> = breaks.iter().copied().filter(|b| *b > 0 && *b < data.len()).collect();
+            bounds.sort_unstable();
+            bounds.dedup();
+            bounds.insert(0, 0);
+            bounds.push(data.len());
+            let mut trend = Vec::with_capacity(data.len());
+            for seg in bounds.windows(2) {
+                let segment = &data[seg[0]..seg[1]];
+                if segment.len() < 2 {
+                    trend.extend_from_slice(segment);
+                } else {
+                    trend.extend(polynomial_trend(segment, 1)?);
+                }
+            }
+            trend
+        }
+        DetrendMode::RollingMean(window) => {
+            let half = (*window).max(1) / 2;
+            let mut prefix = vec![0.0_f64; data.len() + 1];
+            for (i, x) in data.iter().enumerate() {
+                prefix[i + 1] = prefix[i] + x;
+            }
+            (0..data.len()).map(|i| {
+                let lo = i.saturating_sub(half);
+                let hi = (i + half + 1).min(data.len());
+                (prefix[hi] - prefix[lo]) / (hi - lo) as f64
+            }).collect()
+        }
+        DetrendMode::Difference => {
+            let diffs = diff_series(inp);
+            let trend: Vec<f32> = inp.iter().take(diffs.len()).copied().collect();
+            return Ok((diffs, trend));
+        }
+    };
+
+    let detrended: Vec<f32> = data.iter().zip(trend.iter()).map(|(y, t)| (y - t) as f32).collect();
+    Ok((detrended, trend.iter().map(|t| *t as f32).collect()))
+}
+
 /// Calculates the variance of a given list of floating point numbers.
 ///
 /// The variance is a measure of how spread out the numbers in a data set are.
@@ -279,6 +413,98 @@ pub fn spectral_density(inp: &Vec<f32>) -> (Vec<f32>, f32) {
 }
 
 
+/// Selects the quantity that `resample` extracts from an `EventList`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SeriesKind {
+    /// Last traded price in each interval, carried forward over intervals without trades.
+    Price,
+    /// Log return between consecutive resampled prices (one fewer point than the grid).
+    Return,
+    /// Number of trades in each interval.
+    TradeCount,
+    /// Shares traded in each interval.
+    Volume,
+}
+
+/// Resamples an `EventList` onto a regular grid of whole-second intervals.
+///
+/// The grid starts at `start` and covers every second up to and including `end`, so two
+/// lists resampled with the same `start`, `end` and `interval` line up point for point.
+/// Intervals without trades carry the previous price forward; leading empty intervals take
+/// the last price before `start`, or failing that the first price on the grid.
+///
+/// # Arguments
+///
+/// * `event_list` - The trades to resample.
+/// * `start` - First second of the grid (same units as the `EventList` keys).
+/// * `end` - Last second of the grid.
+/// * `interval` - Width of each grid bucket in seconds.
+/// * `kind` - The quantity to extract.
+///
+/// # Returns
+///
+/// The resampled series. Empty if the grid is empty or, for price based kinds, if there
+/// are no trades to take a price from.
+///
+/// # Example
+///
+/// ```ignore
+/// let prices = resample(&event_list, 34_200, 57_600, 60, SeriesKind::Price);
+/// ```
+pub fn resample(event_list: &EventList, start: i32, end: i32, interval: i32, kind: SeriesKind) -> Vec<f32> {
+    if interval <= 0 || end < start {
+        return Vec::new();
+    }
+    let buckets = ((end - start) / interval + 1) as usize;
+    let mut last_price: Vec<Option<f32>> = vec![None; buckets];
+    let mut counts: Vec<f32> = vec![0.0; buckets];
+    let mut volumes: Vec<f32> = vec![0.0; buckets];
+
+    for (sec, event) in event_list.events.range(start..=end) {
+        let idx = ((sec - start) / interval) as usize;
+        last_price[idx] = event.tics.last().and_then(|t| t.price.to_f32());
+        counts[idx] += event.tic_count as f32;
+        volumes[idx] += event.total_volume as f32;
+    }
+
+    match kind {
+        SeriesKind::TradeCount => counts,
+        SeriesKind::Volume => volumes,
+        SeriesKind::Price | SeriesKind::Return => {
+            let mut carry = event_list.events.range(..start).next_back()
+                .and_then(|(_, e)| e.tics.last())
+                .and_then(|t| t.price.to_f32())
+                .or_else(|| last_price.iter().flatten().next().copied());
+            if carry.is_none() {
+                return Vec::new();
+            }
+            let prices: Vec<f32> = last_price.iter().map(|p| {
+                if p.is_some() {
+                    carry = *p;
+                }
+                carry.unwrap()
+            }).collect();
+
+            if kind == SeriesKind::Price {
+                prices
+            } else {
+                prices.windows(2).map(|w| (w[1] as f64 / w[0] as f64).ln() as f32).collect()
+            }
+        }
+    }
+}
+
+/// Resamples an `EventList` over its own first to last traded second.
+///
+/// Convenience wrapper around `resample` for single symbol work where no common grid is needed.
+pub fn resample_event_list(event_list: &EventList, interval: i32, kind: SeriesKind) -> Vec<f32> {
+    match (event_list.events.keys().next(), event_list.events.keys().next_back()) {
+        (Some(start), Some(end)) => resample(event_list, *start, *end, interval, kind),
+        _ => Vec::new(),
+    }
+}
+
+
 ///// INTERNAL TEST FUNCTIONS /////
 pub  fn generate_sin_wave(samples: usize, frequency: f32, max_time_seconds: f32) -> Vec<(String, f32, f32)> {
     let mut result = Vec::new();
@@ -377,11 +603,11 @@ pub  fn  sd_graph(inp:&Vec<f32>){
 ///
 /// # Arguments
 ///
-/// * `inp` - A slice  of i32 integers.
+/// * `inp` - A slice of numbers, e.g. integer prices in cents or `f32` prices.
 ///
 /// # Returns
 ///
-/// Returns a new `Vec<i32>` containing the differences between adjacent elements
+/// Returns a new `Vec` containing the differences between adjacent elements
 /// of the input vector.
 ///
 /// # Examples
@@ -392,8 +618,8 @@ pub  fn  sd_graph(inp:&Vec<f32>){
 /// let output = diff_series(&input);
 /// assert_eq!(output, vec![1, 1, 2, 3]);
 /// ```
-pub fn  diff_series(inp:&[i32]) ->Vec<i32>{
-    let  mut outp:Vec<i32> = Vec::with_capacity(inp.len()-1);
+pub fn  diff_series<T: Copy + std::ops::Sub<Output = T>>(inp:&[T]) ->Vec<T>{
+    let  mut outp:Vec<T> = Vec::with_capacity(inp.len().saturating_sub(1));
     for ctr  in 1..inp.len()  {
         outp.push(inp[ctr] - inp[ctr-1] );
     }
@@ -430,6 +656,7 @@ mod test {
     use std::collections::HashMap;
     use approx::assert_relative_eq;
     use crate::math_funcs::pre_processing::*;
+    use crate::event_structs::EventList;
 
 
     #[test]
@@ -531,6 +758,81 @@ mod test {
 
     }
 
+    fn resample_list() -> EventList {
+        let mut el = EventList::new();
+        let _ = el.update("09:30:00.100000000", "10.0", 100);
+        let _ = el.update("09:30:00.200000000", "10.5", 50);
+        let _ = el.update("09:30:02.100000000", "11.0", 10);
+        let _ = el.update("09:30:05.100000000", "10.0", 20);
+        el
+    }
+
+    #[test]
+    fn test_resample_price() {
+        let el = resample_list();
+        let out = resample(&el, 34_200, 34_205, 1, SeriesKind::Price);
+        assert_eq!(out, vec![10.5, 10.5, 11.0, 11.0, 11.0, 10.0]);
+        let out = resample(&el, 34_198, 34_201, 2, SeriesKind::Price);
+        assert_eq!(out, vec![10.5, 10.5]);
+    }
+
+    #[test]
+    fn test_resample_counts_and_returns() {
+        let el = resample_list();
+        assert_eq!(resample(&el, 34_200, 34_205, 3, SeriesKind::TradeCount), vec![3.0, 1.0]);
+        assert_eq!(resample(&el, 34_200, 34_205, 3, SeriesKind::Volume), vec![160.0, 20.0]);
+        let rets = resample_event_list(&el, 1, SeriesKind::Return);
+        assert_eq!(rets.len(), 5);
+        assert_relative_eq!(rets[1], (11.0_f32 / 10.5).ln(), epsilon = 1e-6);
+        assert_relative_eq!(rets.iter().sum::<f32>(), (10.0_f32 / 10.5).ln(), epsilon = 1e-6);
+        assert!(resample_event_list(&EventList::new(), 1, SeriesKind::Price).is_empty());
+    }
+
+    #[test]
+    fn test_detrend_with_linear_matches_detrend() {
+        let inp: Vec<f32> = vec![1.0, 2.0, 3.0, 4.0, 5.0, 5.5, 4.5, 4.0, 4.1, 4.2];
+        let (out, trend) = detrend_with(&inp, &DetrendMode::Linear).unwrap();
+        for ((a, b), (t, x)) in out.iter().zip(detrend(&inp).iter()).zip(trend.iter().zip(inp.iter())) {
+            assert_relative_eq!(*a, *b, epsilon = 1e-5);
+            assert_relative_eq!(*a + *t, *x, epsilon = 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_detrend_with_polynomial() {
+        // U shaped day
+        let inp: Vec<f32> = (0..50).map(|i| 100.0 + 0.01 * ((i - 25) * (i - 25)) as f32).collect();
+        let (out, trend) = detrend_with(&inp, &DetrendMode::Polynomial(2)).unwrap();
+        assert_eq!(trend.len(), inp.len());
+        assert!(out.iter().all(|x| x.abs() < 1e-3));
+        let (linear, _) = detrend_with(&inp, &DetrendMode::Linear).unwrap();
+        assert!(linear.iter().any(|x| x.abs() > 1.0));
+        assert!(detrend_with(&inp[..2], &DetrendMode::Polynomial(2)).is_err());
+    }
+
+    #[test]
+    fn test_detrend_with_sessions() {
+        let times: Vec<i32> = vec![30_000, 31_000, 34_200, 35_000, 36_000, 57_600, 58_000];
+        let breaks = session_breakpoints(&times);
+        assert_eq!(breaks, vec![2, 5]);
+        let inp: Vec<f32> = vec![1.0, 2.0, 10.0, 8.0, 6.0, 3.0, 3.0];
+        let (out, trend) = detrend_with(&inp, &DetrendMode::PiecewiseLinear(breaks)).unwrap();
+        assert!(out.iter().all(|x| x.abs() < 1e-5));
+        assert_eq!(trend.len(), inp.len());
+    }
+
+    #[test]
+    fn test_detrend_with_rolling_and_difference() {
+        let inp: Vec<f32> = vec![1.0, 3.0, 5.0, 7.0, 9.0];
+        let (out, trend) = detrend_with(&inp, &DetrendMode::RollingMean(3)).unwrap();
+        assert_eq!(trend, vec![2.0, 3.0, 5.0, 7.0, 8.0]);
+        assert_eq!(out, vec![-1.0, 0.0, 0.0, 0.0, 1.0]);
+        let (out, trend) = detrend_with(&inp, &DetrendMode::Difference).unwrap();
+        assert_eq!(out, vec![2.0, 2.0, 2.0, 2.0]);
+        assert_eq!(trend, vec![1.0, 3.0, 5.0, 7.0]);
+        assert_eq!(diff_series(&[1.5_f32, 1.0]), vec![-0.5]);
+    }
+
     #[test]
     fn test_min_res(){
         let  dta:[(i32,u32);9]= [(0,3),(1,275),(-2,1),(4,1),(643,2),(14,5),(-5,1),(-11,1),(111,5)];
@@ -0,0 +1,574 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// Intraday seasonality: per time of day slot volume, trade count, realized volatility and Roll
+// spread, collected over many days (e.g. every file under NYSE_TRADE_DATA_DIR) into mean and
+// quantile profiles per symbol and market wide, saved as CSV and used to deseasonalize a day.
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::Write;
+use rust_decimal::prelude::ToPrimitive;
+use walkdir::WalkDir;
+use crate::event_structs::EventList;
+use crate::general::parsing;
+use crate::math_funcs::spreads::roll_spread;
+use crate::nyse::base_funcs::EventStats;
+
+/// Profile key of the market wide aggregate: summed volume and trades, average volatility and spread.
+pub const MARKET: &str = "*MARKET*";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SeasonalMetric {
+    Volume,
+    TradeCount,
+    /// Square root of the summed squared tick log returns in the slot.
+    Volatility,
+    /// Roll spread of the slot's trade prices, as a fraction of price.
+    Spread,
+}
+
+impl SeasonalMetric {
+    pub fn get(name: &str) -> Option<SeasonalMetric> {
+        match name {
+            "volume" => Some(SeasonalMetric::Volume),
+            "trades" => Some(SeasonalMetric::TradeCount),
+            "volatility" => Some(SeasonalMetric::Volatility),
+            "spread" => Some(SeasonalMetric::Spread),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SeasonalMetric::Volume => "volume",
+            SeasonalMetric::TradeCount => "trades",
+            SeasonalMetric::Volatility => "volatility",
+            SeasonalMetric::Spread => "spread",
+        }
+    }
+
+    const ALL: [SeasonalMetric; 4] = [SeasonalMetric::Volume, SeasonalMetric::TradeCount, SeasonalMetric::Volatility, SeasonalMetric::Spread];
+}
+
+/// One symbol's metrics in one slot of one day, volatility and spread needing trades to exist.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SlotMetrics {
+    pub volume: f64,
+    pub trades: f64,
+    pub volatility: Option<f64>,
+    pub spread: Option<f64>,
+}
+
+impl SlotMetrics {
+    pub fn get(&self, metric: SeasonalMetric) -> Option<f64> {
+        match metric {
+            SeasonalMetric::Volume => Some(self.volume),
+            SeasonalMetric::TradeCount => Some(self.trades),
+            SeasonalMetric::Volatility => self.volatility,
+            SeasonalMetric::Spread => self.spread,
+        }
+    }
+}
+
+/// Slot metrics of an `EventList` keyed by slot start (seconds after midnight, a multiple of
+/// `interval`). Slots without trades are absent.
+pub fn slot_metrics(event_list: &EventList, interval: i32) -> BTreeMap<i32, SlotMetrics> {
+    let mut out: BTreeMap<i32, SlotMetrics> = BTreeMap::new();
+    let mut prices: BTreeMap<i32, Vec<f64>> = BTreeMap::new();
+    let mut previous: Option<f64> = None;
+    for (sec, event) in event_list.events.iter() {
+        let slot = sec - sec.rem_euclid(interval);
+        let entry = out.entry(slot).or_default();
+        let slot_prices = prices.entry(slot).or_insert_with(|| previous.into_iter().collect());
+        for tic in event.tics.iter() {
+            entry.volume += tic.volume as f64;
+            entry.trades += 1.0;
+            if let Some(p) = tic.price.to_f64().filter(|p| *p > 0.0) {
+                slot_prices.push(p);
+                previous = Some(p);
+            }
+        }
+    }
+    for (slot, p) in prices {
+        let entry = out.get_mut(&slot).unwrap();
+        if p.len() >= 2 {
+            entry.volatility = Some(p.windows(2).map(|w| (w[1] / w[0]).ln().powi(2)).sum::<f64>().sqrt());
+        }
+        entry.spread = roll_spread(&p).ok();
+    }
+    out
+}
+
+/// Mean and quantiles of one metric in one slot across days.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SlotStats {
+    pub count: usize,
+    pub mean: f64,
+    pub q10: f64,
+    pub q25: f64,
+    pub median: f64,
+    pub q75: f64,
+    pub q90: f64,
+}
+
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let pos = q * (sorted.len() - 1) as f64;
+    let lo = pos.floor() as usize;
+    let hi = pos.ceil() as usize;
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (pos - lo as f64)
+}
+
+impl SlotStats {
+    pub fn new(values: &[f64]) -> SlotStats {
+        if values.is_empty() {
+            return SlotStats::default();
+        }
+        let mut sorted = values.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        SlotStats {
+            count: sorted.len(),
+            mean: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            q10: quantile(&sorted, 0.10),
+            q25: quantile(&sorted, 0.25),
+            median: quantile(&sorted, 0.50),
+            q75: quantile(&sorted, 0.75),
+            q90: quantile(&sorted, 0.90),
+        }
+    }
+}
+
+/// Marker probabilities of `QuantileSketch`: the extremes, the five `SlotStats` quantiles and the
+/// midpoints between them.
+const SKETCH_MARKERS: [f64; 13] = [0.0, 0.05, 0.10, 0.175, 0.25, 0.375, 0.50, 0.625, 0.75, 0.825, 0.90, 0.95, 1.0];
+
+/// Streaming mean and quantiles in constant memory, the extended P-square algorithm of
+/// Raatikainen with the markers of `SKETCH_MARKERS`. Exact until the markers are filled.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct QuantileSketch {
+    count: usize,
+    sum: f64,
+    heights: Vec<f64>,
+    positions: Vec<f64>,
+    desired: Vec<f64>,
+}
+
+impl QuantileSketch {
+    pub fn new() -> QuantileSketch {
+        QuantileSketch::default()
+    }
+
+    pub fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        let m = SKETCH_MARKERS.len();
+        if self.count <= m {
+            let at = self.heights.partition_point(|h| *h <= value);
+            self.heights.insert(at, value);
+            if self.count == m {
+                self.positions = (1..=m).map(|i| i as f64).collect();
+                self.desired = SKETCH_MARKERS.iter().map(|p| 1.0 + (m - 1) as f64 * p).collect();
+            }
+            return;
+        }
+        let q = &mut self.heights;
+        let k = if value < q[0] {
+            q[0] = value;
+            0
+        } else if value >= q[m - 1] {
+            q[m - 1] = value;
+            m - 2
+        } else {
+            q.partition_point(|h| *h <= value) - 1
+        };
+        for n in self.positions[k + 1..].iter_mut() {
+            *n += 1.0;
+        }
+        for (desired, p) in self.desired.iter_mut().zip(SKETCH_MARKERS.iter()) {
+            *desired += p;
+        }
+        let n = &mut self.positions;
+        for i in 1..m - 1 {
+            let d = self.desired[i] - n[i];
+            if (d >= 1.0 && n[i + 1] - n[i] > 1.0) || (d <= -1.0 && n[i - 1] - n[i] < -1.0) {
+                let d = d.signum();
+                let parabolic = q[i] + d / (n[i + 1] - n[i - 1])
+                    * ((n[i] - n[i - 1] + d) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                    + (n[i + 1] - n[i] - d) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]));
+                q[i] = if q[i - 1] < parabolic && parabolic < q[i + 1] {
+                    parabolic
+                } else {
+                    let j = if d > 0.0 { i + 1 } else { i - 1 };
+                    q[i] + d * (q[j] - q[i]) / (n[j] - n[i])
+                };
+                n[i] += d;
+            }
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Quantile `SKETCH_MARKERS[marker]`.
+    fn marker(&self, marker: usize) -> f64 {
+        if self.count < SKETCH_MARKERS.len() {
+            quantile(&self.heights, SKETCH_MARKERS[marker])
+        } else {
+            self.heights[marker]
+        }
+    }
+
+    pub fn stats(&self) -> SlotStats {
+        if self.count == 0 {
+            return SlotStats::default();
+        }
+        SlotStats {
+            count: self.count,
+            mean: self.sum / self.count as f64,
+            q10: self.marker(2),
+            q25: self.marker(4),
+            median: self.marker(6),
+            q75: self.marker(8),
+            q90: self.marker(10),
+        }
+    }
+}
+
+/// Profile of one symbol (or `MARKET`), metric -> slot start -> statistics.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SeasonalProfile {
+    pub days: usize,
+    pub slots: HashMap<SeasonalMetric, BTreeMap<i32, SlotStats>>,
+}
+
+impl SeasonalProfile {
+    pub fn get(&self, metric: SeasonalMetric, slot: i32) -> Option<&SlotStats> {
+        self.slots.get(&metric).and_then(|m| m.get(&slot))
+    }
+
+    /// Mean at `slot` over the average of the slot means, 1.0 being a typical slot.
+    pub fn factor(&self, metric: SeasonalMetric, slot: i32) -> Option<f64> {
+        let slots = self.slots.get(&metric)?;
+        let level = slots.values().map(|s| s.mean).sum::<f64>() / slots.len() as f64;
+        slots.get(&slot).map(|s| s.mean / level).filter(|f| f.is_finite() && *f > 0.0)
+    }
+}
+
+/// Collects daily slot metrics into per slot quantile sketches, so memory does not grow with the
+/// number of days; every day a symbol trades contributes zero volume and trade counts for the
+/// slots of that day's session in which it did not trade.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SeasonalityBuilder {
+    pub interval: i32,
+    samples: HashMap<String, HashMap<SeasonalMetric, BTreeMap<i32, QuantileSketch>>>,
+    days: HashMap<String, usize>,
+}
+
+impl SeasonalityBuilder {
+    pub fn new(interval: i32) -> SeasonalityBuilder {
+        SeasonalityBuilder { interval, samples: HashMap::new(), days: HashMap::new() }
+    }
+
+    fn push(&mut self, symbol: &str, slot: i32, metrics: &SlotMetrics) {
+        let entry = self.samples.entry(symbol.to_string()).or_default();
+        for metric in SeasonalMetric::ALL {
+            if let Some(v) = metrics.get(metric) {
+                entry.entry(metric).or_default().entry(slot).or_default().add(v);
+            }
+        }
+    }
+
+    /// Adds one day (one parsed file).
+    pub fn add_day(&mut self, event_stats: &EventStats) {
+        let per_symbol: Vec<(&String, BTreeMap<i32, SlotMetrics>)> = event_stats.symbol_events.iter()
+            .filter(|(_, el)| !el.events.is_empty())
+            .map(|(s, el)| (s, slot_metrics(el, self.interval)))
+            .collect();
+        let first = per_symbol.iter().filter_map(|(_, m)| m.keys().next()).min().cloned();
+        let last = per_symbol.iter().filter_map(|(_, m)| m.keys().next_back()).max().cloned();
+        let (Some(first), Some(last)) = (first, last) else { return };
+        let mut market: BTreeMap<i32, (SlotMetrics, f64, f64)> = BTreeMap::new();
+        for (symbol, metrics) in per_symbol.iter() {
+            *self.days.entry(symbol.to_string()).or_default() += 1;
+            for slot in (first..=last).step_by(self.interval as usize) {
+                let m = metrics.get(&slot).cloned().unwrap_or_default();
+                self.push(symbol, slot, &m);
+                let agg = market.entry(slot).or_default();
+                agg.0.volume += m.volume;
+                agg.0.trades += m.trades;
+                if let Some(v) = m.volatility {
+                    agg.0.volatility = Some(agg.0.volatility.unwrap_or(0.0) + v);
+                    agg.1 += 1.0;
+                }
+                if let Some(s) = m.spread {
+                    agg.0.spread = Some(agg.0.spread.unwrap_or(0.0) + s);
+                    agg.2 += 1.0;
+                }
+            }
+        }
+        *self.days.entry(MARKET.to_string()).or_default() += 1;
+        for (slot, (mut m, vol_n, spread_n)) in market {
+            m.volatility = m.volatility.map(|v| v / vol_n);
+            m.spread = m.spread.map(|s| s / spread_n);
+            self.push(MARKET, slot, &m);
+        }
+    }
+
+    pub fn build(&self) -> Seasonality {
+        let mut profiles = HashMap::new();
+        for (symbol, metrics) in self.samples.iter() {
+            let slots = metrics.iter()
+                .map(|(metric, slots)| (*metric, slots.iter().map(|(slot, sketch)| (*slot, sketch.stats())).collect()))
+                .collect();
+            profiles.insert(symbol.clone(), SeasonalProfile { days: self.days.get(symbol).cloned().unwrap_or(0), slots });
+        }
+        Seasonality { interval: self.interval, profiles }
+    }
+}
+
+/// Seasonal profiles per symbol and `MARKET`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Seasonality {
+    pub interval: i32,
+    pub profiles: HashMap<String, SeasonalProfile>,
+}
+
+impl Seasonality {
+    /// Parses every file under `data_dir` (e.g. `NYSE_TRADE_DATA_DIR`), one file per day. A file
+    /// that cannot be read or parsed is returned as an error.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use feed_parser::math_funcs::seasonality::Seasonality;
+    /// # use std::env;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let seasonality = Seasonality::from_dir(&env::var("NYSE_TRADE_DATA_DIR")?, 300)?;
+    /// seasonality.write_csv("plots/seasonality.csv")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_dir(data_dir: &str, interval: i32) -> Result<Seasonality, Box<dyn Error>> {
+        if interval <= 0 {
+            return Err("interval must be positive".into());
+        }
+        let mut builder = SeasonalityBuilder::new(interval);
+        for file in WalkDir::new(data_dir).into_iter().filter_map(|file| file.ok()) {
+            if file.file_type().is_file() {
+                let stats = parsing::try_proc_file(&file.path().display().to_string())?;
+                builder.add_day(&stats.event_stats);
+            }
+        }
+        Ok(builder.build())
+    }
+
+    /// The symbol's profile, or the market profile for symbols never seen.
+    pub fn get(&self, symbol: &str) -> Option<&SeasonalProfile> {
+        self.profiles.get(symbol).or_else(|| self.profiles.get(MARKET))
+    }
+
+    /// Divides a day's slot values of `metric` by the seasonal factor, slots without a factor
+    /// are dropped.
+    pub fn deseasonalize(&self, symbol: &str, metric: SeasonalMetric, series: &[(i32, f64)]) -> Vec<(i32, f64)> {
+        let Some(profile) = self.get(symbol) else { return Vec::new() };
+        series.iter()
+            .filter_map(|(time, v)| {
+                let slot = time - time.rem_euclid(self.interval);
+                profile.factor(metric, slot).map(|f| (*time, v / f))
+            })
+            .collect()
+    }
+
+    /// Slot metrics of a new day's `EventList`, deseasonalized.
+    pub fn deseasonalize_event_list(&self, symbol: &str, metric: SeasonalMetric, event_list: &EventList) -> Vec<(i32, f64)> {
+        let series: Vec<(i32, f64)> = slot_metrics(event_list, self.interval).iter()
+            .filter_map(|(slot, m)| m.get(metric).map(|v| (*slot, v)))
+            .collect();
+        self.deseasonalize(symbol, metric, &series)
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("symbol,interval,days,metric,slot,count,mean,q10,q25,median,q75,q90\n");
+        let mut symbols: Vec<&String> = self.profiles.keys().collect();
+        symbols.sort();
+        for symbol in symbols {
+            let profile = &self.profiles[symbol];
+            for metric in SeasonalMetric::ALL {
+                for (slot, s) in profile.slots.get(&metric).into_iter().flatten() {
+                    writeln!(out, "{},{},{},{},{},{},{},{},{},{},{},{}", symbol, self.interval, profile.days, metric.name(), slot,
+                             s.count, s.mean, s.q10, s.q25, s.median, s.q75, s.q90).unwrap();
+                }
+            }
+        }
+        out
+    }
+
+    pub fn from_csv(text: &str) -> Result<Seasonality, Box<dyn Error>> {
+        let mut out = Seasonality::default();
+        for (n, line) in text.lines().enumerate().skip(1) {
+            let tokens: Vec<&str> = line.split(',').collect();
+            if tokens.len() != 12 {
+                return Err(format!("line {}: expected 12 fields, got {}", n + 1, tokens.len()).into());
+            }
+            out.interval = tokens[1].parse()?;
+            let metric = SeasonalMetric::get(tokens[3]).ok_or(format!("line {}: unknown metric {}", n + 1, tokens[3]))?;
+            let profile = out.profiles.entry(tokens[0].to_string()).or_default();
+            profile.days = tokens[2].parse()?;
+            let stats = SlotStats {
+                count: tokens[5].parse()?,
+                mean: tokens[6].parse()?,
+                q10: tokens[7].parse()?,
+                q25: tokens[8].parse()?,
+                median: tokens[9].parse()?,
+                q75: tokens[10].parse()?,
+                q90: tokens[11].parse()?,
+            };
+            profile.slots.entry(metric).or_default().insert(tokens[4].parse()?, stats);
+        }
+        Ok(out)
+    }
+
+    pub fn write_csv(&self, file_name: &str) -> Result<(), Box<dyn Error>> {
+        std::fs::write(file_name, self.to_csv())?;
+        Ok(())
+    }
+
+    pub fn read_csv(file_name: &str) -> Result<Seasonality, Box<dyn Error>> {
+        Seasonality::from_csv(&std::fs::read_to_string(file_name)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_structs::test_event_list;
+    use approx::assert_relative_eq;
+
+    /// `scale` times more volume in the first and last slot of a three slot session
+    fn day(scale: i32) -> EventStats {
+        let mut tics = Vec::new();
+        for slot in 0..3 {
+            let trades = if slot == 1 { 2 } else { 2 * scale };
+            for i in 0..trades {
+                tics.push((34_200 + slot * 300 + i, if i % 2 == 0 { "10.00" } else { "10.02" }, 100));
+            }
+        }
+        let mut stats = EventStats::new();
+        stats.symbol_events.insert("AAA".to_string(), test_event_list(&tics));
+        stats.symbol_events.insert("BBB".to_string(), test_event_list(&[(34_200, "5.00", 10)]));
+        stats
+    }
+
+    #[test]
+    fn test_slot_metrics() {
+        let stats = day(3);
+        let m = slot_metrics(&stats.symbol_events["AAA"], 300);
+        assert_eq!(m.keys().cloned().collect::<Vec<i32>>(), vec![34_200, 34_500, 34_800]);
+        assert_eq!(m[&34_200].trades, 6.0);
+        assert_eq!(m[&34_500].volume, 200.0);
+        // the slot starts from the previous slot's last price, bouncing every trade
+        let r = (10.02f64 / 10.0).ln();
+        assert_relative_eq!(m[&34_500].volatility.unwrap(), (2.0 * r * r).sqrt(), epsilon = 1e-12);
+        assert!(m[&34_200].spread.unwrap() > 0.0);
+    }
+
+    #[test]
+    fn test_seasonality() {
+        let mut builder = SeasonalityBuilder::new(300);
+        builder.add_day(&day(2));
+        builder.add_day(&day(4));
+        let seasonality = builder.build();
+        let aaa = &seasonality.profiles["AAA"];
+        assert_eq!(aaa.days, 2);
+        let open = aaa.get(SeasonalMetric::TradeCount, 34_200).unwrap();
+        assert_eq!(open.count, 2);
+        assert_relative_eq!(open.mean, 6.0);
+        assert_relative_eq!(open.q25, 5.0);
+        // BBB only trades in the first slot, later slots count as zero volume
+        assert_eq!(seasonality.profiles["BBB"].get(SeasonalMetric::Volume, 34_800).unwrap().mean, 0.0);
+        assert_relative_eq!(seasonality.profiles[MARKET].get(SeasonalMetric::Volume, 34_500).unwrap().mean, 200.0);
+
+        // U shape 6 / 2 / 6 trades, level 14 / 3
+        let factor = aaa.factor(SeasonalMetric::TradeCount, 34_500).unwrap();
+        assert_relative_eq!(factor, 2.0 / (14.0 / 3.0), epsilon = 1e-12);
+        let flat = seasonality.deseasonalize_event_list("AAA", SeasonalMetric::TradeCount, &day(3).symbol_events["AAA"]);
+        assert_relative_eq!(flat[0].1, flat[1].1, epsilon = 1e-9);
+        assert_eq!(seasonality.get("NEW").unwrap().days, 2);
+
+        let restored = Seasonality::from_csv(&seasonality.to_csv()).unwrap();
+        assert_eq!(restored.interval, 300);
+        assert_eq!(restored.profiles["AAA"].get(SeasonalMetric::TradeCount, 34_200), Some(open));
+        assert_eq!(restored.profiles.len(), 3);
+        assert!(Seasonality::from_csv("header\nAAA,300").is_err());
+    }
+
+    #[test]
+    fn test_quantile_sketch() {
+        let mut sketch = QuantileSketch::new();
+        for v in [4.0, 1.0, 3.0, 2.0] {
+            sketch.add(v);
+        }
+        assert_eq!(sketch.stats(), SlotStats::new(&[1.0, 2.0, 3.0, 4.0]));
+        // a shuffled ramp 0..=9999, every quantile q at about 9999 q
+        let mut sketch = QuantileSketch::new();
+        for i in 0..10_000u64 {
+            sketch.add(((i * 7_919) % 10_000) as f64);
+        }
+        let stats = sketch.stats();
+        assert_eq!(stats.count, 10_000);
+        assert_relative_eq!(stats.mean, 4_999.5);
+        for (value, q) in [(stats.q10, 0.10), (stats.q25, 0.25), (stats.median, 0.5), (stats.q75, 0.75), (stats.q90, 0.90)] {
+            assert_relative_eq!(value, 9_999.0 * q, epsilon = 100.0);
+        }
+        // zero filled slots: half the days without trades
+        let mut sketch = QuantileSketch::new();
+        for i in 0..10_000u64 {
+            sketch.add(if i % 2 == 0 { 0.0 } else { ((i * 7_919) % 10_000) as f64 });
+        }
+        let stats = sketch.stats();
+        assert_relative_eq!(stats.q10, 0.0, epsilon = 1e-6);
+        assert_relative_eq!(stats.q25, 0.0, epsilon = 1e-6);
+        assert_relative_eq!(stats.q75, 5_000.0, epsilon = 150.0);
+        assert_relative_eq!(stats.q90, 8_000.0, epsilon = 150.0);
+    }
+
+    #[test]
+    fn test_from_dir_bad_line() {
+        let dir = std::env::temp_dir().join(format!("seasonality-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("day1.csv"), "220,1,09:30:00.000000000,AAA,1,2,bad,100,@,F,T,I\n").unwrap();
+        let result = Seasonality::from_dir(&dir.display().to_string(), 300);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(result.unwrap_err().to_string().contains("line 1"));
+    }
+}
@@ -0,0 +1,405 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+// Time-frequency analysis of resampled EventList series.
+// Everything here works on a regularly sampled series, use pre_processing::resample first.
+
+use std::error::Error;
+use std::f32::consts::PI;
+use rustfft::{FftPlanner, num_complex::Complex};
+use crate::event_structs::EventList;
+use crate::math_funcs::distributions::norm_ppf;
+use crate::math_funcs::pre_processing::{resample, SeriesKind};
+
+/// Short-time Fourier transform power of a series.
+///
+/// - `times`: Centre of each window in seconds from the first sample.
+/// - `frequencies`: Frequency of each bin in Hz, from 0 up to the Nyquist frequency.
+/// - `power`: One-sided power spectral density, indexed `power[window][frequency]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrogram {
+    pub times: Vec<f32>,
+    pub frequencies: Vec<f32>,
+    pub power: Vec<Vec<f32>>,
+}
+
+impl Spectrogram {
+    /// Returns the frequency with the most power in each window, ignoring the DC bin.
+    pub fn dominant_frequencies(&self) -> Vec<f32> {
+        self.power.iter().map(|row| {
+            let mut best = 1;
+            for (idx, p) in row.iter().enumerate().skip(1) {
+                if *p > row[best] {
+                    best = idx;
+                }
+            }
+            self.frequencies[best.min(self.frequencies.len() - 1)]
+        }).collect()
+    }
+}
+
+/// Generates a periodic Hann window of the given length.
+pub fn hann_window(len: usize) -> Vec<f32> {
+    (0..len).map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / len as f32).cos()).collect()
+}
+
+/// Returns the non-negative frequency FFT bins of a single windowed segment.
+///
+/// The segment mean is removed before windowing so a price level does not swamp the DC bin.
+/// `window` must be the same length as `segment`.
+pub(crate) fn segment_spectrum(segment: &[f32], window: &[f32], fft: &dyn rustfft::Fft<f32>) -> Vec<Complex<f32>> {
+    let mean = segment.iter().sum::<f32>() / segment.len() as f32;
+    let mut buffer: Vec<Complex<f32>> = segment.iter().zip(window.iter())
+        .map(|(x, w)| Complex::new((x - mean) * w, 0.0))
+        .collect();
+    fft.process(&mut buffer);
+    buffer.truncate(segment.len() / 2 + 1);
+    buffer
+}
+
+/// Computes the spectrogram (short-time Fourier transform power) of a regularly sampled series.
+///
+/// The series is cut into Hann windowed segments of `window` samples, each starting `hop`
+/// samples after the previous one. Each segment is de-meaned and its power spectral density is
+/// scaled the same way as Welch's method so the values are comparable with `spectral_density`.
+///
+/// # Arguments
+///
+/// * `inp` - The regularly sampled series, for example the output of `resample`.
+/// * `fs` - Sampling frequency in Hz (`1.0 / interval` for a grid in seconds).
+/// * `window` - Number of samples in each segment.
+/// * `hop` - Number of samples between the starts of consecutive segments.
+///
+/// # Returns
+///
+/// * `Ok(Spectrogram)` on success.
+/// * `Err` if the window, hop or sampling frequency are invalid or the series is shorter than one window.
+///
+/// # Example
+///
+/// ```ignore
+/// let counts = resample_event_list(&event_list, 1, SeriesKind::TradeCount);
+/// let spec = spectrogram(&counts, 1.0, 600, 60)?;
+/// ```
+pub fn spectrogram(inp: &[f32], fs: f32, window: usize, hop: usize) -> Result<Spectrogram, Box<dyn Error>> {
+    if window < 2 || hop == 0 {
+        return Err("window must be at least 2 samples and hop at least 1".into());
+    }
+    if fs <= 0.0 {
+        return Err("sampling frequency must be positive".into());
+    }
+    if inp.len() < window {
+        return Err(format!("series of {} points is shorter than the window of {}", inp.len(), window).into());
+    }
+
+    let taper = hann_window(window);
+    let scale = 1.0 / (fs * taper.iter().map(|w| w * w).sum::<f32>());
+    let mut planner = FftPlanner::<f32>::new();
+    let fft = planner.plan_fft_forward(window);
+
+    let bins = window / 2 + 1;
+    let frequencies: Vec<f32> = (0..bins).map(|k| k as f32 * fs / window as f32).collect();
+    let mut times: Vec<f32> = Vec::new();
+    let mut power: Vec<Vec<f32>> = Vec::new();
+
+    let mut start = 0;
+    while start + window <= inp.len() {
+        let spectrum = segment_spectrum(&inp[start..start + window], &taper, fft.as_ref());
+        let row: Vec<f32> = spectrum.iter().enumerate().map(|(k, x)| {
+            let p = x.norm_sqr() * scale;
+            // fold the negative frequencies in, DC and Nyquist have no mirror image
+            if k == 0 || (k == bins - 1 && 2 * k == window) { p } else { 2.0 * p }
+        }).collect();
+        times.push((start as f32 + window as f32 / 2.0) / fs);
+        power.push(row);
+        start += hop;
+    }
+
+    Ok(Spectrogram { times, frequencies, power })
+}
+
+
+/// Welch averaged cross-spectral estimates between two series `x` and `y`.
+///
+/// - `frequencies`: Frequency of each bin in Hz.
+/// - `cross_density`: Cross-spectral density `Pxy = E[conj(X) Y]`.
+/// - `coherence`: Magnitude-squared coherence in `[0, 1]`.
+/// - `coherence_lower` / `coherence_upper`: Confidence bounds on the coherence estimate.
+/// - `coherence_threshold`: Coherence below this level is not distinguishable from zero.
+/// - `phase`: Phase of `Pxy` in radians.
+/// - `phase_ci`: Half width of the phase confidence interval in radians.
+/// - `phase_lag`: Time lag implied by the phase in seconds, positive when `y` lags `x`.
+/// - `segments`: Number of segments averaged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrossSpectrum {
+    pub frequencies: Vec<f32>,
+    pub cross_density: Vec<Complex<f32>>,
+    pub coherence: Vec<f32>,
+    pub coherence_lower: Vec<f32>,
+    pub coherence_upper: Vec<f32>,
+    pub coherence_threshold: f32,
+    pub phase: Vec<f32>,
+    pub phase_ci: Vec<f32>,
+    pub phase_lag: Vec<f32>,
+    pub segments: usize,
+}
+
+/// Estimates the cross-spectral density, coherence and phase between two aligned series.
+///
+/// Both series are cut into the same Hann windowed, de-meaned segments as `spectrogram` and the
+/// auto and cross spectra are averaged over segments (Welch's method). Coherence needs more than one
+/// segment to be meaningful, with a single segment it is identically one.
+///
+/// Confidence bounds follow Bendat & Piersol: the random error of the coherence estimate is
+/// `sqrt(2) (1 - C) / (sqrt(C) sqrt(K))` and the standard deviation of the phase is
+/// `sqrt((1 - C) / (2 K C))` for `K` segments. The zero coherence threshold is
+/// `1 - (1 - confidence)^(1 / (K - 1))`. Overlapping segments are not independent so with
+/// `hop < window` the bounds are somewhat optimistic.
+///
+/// # Arguments
+///
+/// * `x` - First regularly sampled series.
+/// * `y` - Second series on the same grid as `x`.
+/// * `fs` - Sampling frequency in Hz.
+/// * `window` - Number of samples in each segment.
+/// * `hop` - Number of samples between the starts of consecutive segments.
+/// * `confidence` - Confidence level of the bounds, e.g. `0.95`.
+///
+/// # Returns
+///
+/// * `Ok(CrossSpectrum)` on success.
+/// * `Err` if the series differ in length, are shorter than one window, or the parameters are invalid.
+pub fn cross_spectrum(x: &[f32], y: &[f32], fs: f32, window: usize, hop: usize, confidence: f32) -> Result<CrossSpectrum, Box<dyn Error>> {
+    if x.len() != y.len() {
+        return Err(format!("series lengths differ: {} and {}", x.len(), y.len()).into());
+    }
+    if window < 2 || hop == 0 {
+        return Err("window must be at least 2 samples and hop at least 1".into());
+    }
+    if fs <= 0.0 {
+        return Err("sampling frequency must be positive".into());
+    }
+    if !(0.0..1.0).contains(&confidence) {
+        return Err("confidence must be in [0, 1)".into());
+    }
+    if x.len() < window {
+        return Err(format!("series of {} points is shorter than the window of {}", x.len(), window).into());
+    }
+
+    let taper = hann_window(window);
+    let scale = 1.0 / (fs * taper.iter().map(|w| w * w).sum::<f32>());
+    let mut planner = FftPlanner::<f32>::new();
+    let fft = planner.plan_fft_forward(window);
+
+    let bins = window / 2 + 1;
+    let mut pxx = vec![0.0_f32; bins];
+    let mut pyy = vec![0.0_f32; bins];
+    let mut pxy = vec![Complex::new(0.0_f32, 0.0); bins];
+    let mut segments = 0;
+
+    let mut start = 0;
+    while start + window <= x.len() {
+        let sx = segment_spectrum(&x[start..start + window], &taper, fft.as_ref());
+        let sy = segment_spectrum(&y[start..start + window], &taper, fft.as_ref());
+        for k in 0..bins {
+            pxx[k] += sx[k].norm_sqr();
+            pyy[k] += sy[k].norm_sqr();
+            pxy[k] += sx[k].conj() * sy[k];
+        }
+        segments += 1;
+        start += hop;
+    }
+
+    let k_seg = segments as f32;
+    let z = norm_ppf(0.5 + confidence as f64 / 2.0) as f32;
+    let frequencies: Vec<f32> = (0..bins).map(|k| k as f32 * fs / window as f32).collect();
+    let mut cross_density = Vec::with_capacity(bins);
+    let mut coherence = Vec::with_capacity(bins);
+    let mut coherence_lower = Vec::with_capacity(bins);
+    let mut coherence_upper = Vec::with_capacity(bins);
+    let mut phase = Vec::with_capacity(bins);
+    let mut phase_ci = Vec::with_capacity(bins);
+    let mut phase_lag = Vec::with_capacity(bins);
+
+    for k in 0..bins {
+        let one_sided = if k == 0 || (k == bins - 1 && 2 * k == window) { 1.0 } else { 2.0 };
+        cross_density.push(pxy[k] * (one_sided * scale / k_seg));
+
+        let denom = pxx[k] * pyy[k];
+        let c = if denom > 0.0 { (pxy[k].norm_sqr() / denom).min(1.0) } else { 0.0 };
+        coherence.push(c);
+
+        let err = if c > 0.0 { std::f32::consts::SQRT_2 * (1.0 - c) / (c.sqrt() * k_seg.sqrt()) } else { 1.0 };
+        coherence_lower.push((c * (1.0 - z * err)).max(0.0));
+        coherence_upper.push((c * (1.0 + z * err)).min(1.0));
+
+        let ph = pxy[k].arg();
+        phase.push(ph);
+        phase_ci.push(if c > 0.0 { (z * ((1.0 - c) / (2.0 * k_seg * c)).sqrt()).min(PI) } else { PI });
+        phase_lag.push(if k == 0 { 0.0 } else { -ph / (2.0 * PI * frequencies[k]) });
+    }
+
+    let coherence_threshold = if segments > 1 {
+        1.0 - (1.0 - confidence).powf(1.0 / (k_seg - 1.0))
+    } else {
+        1.0
+    };
+
+    Ok(CrossSpectrum {
+        frequencies,
+        cross_density,
+        coherence,
+        coherence_lower,
+        coherence_upper,
+        coherence_threshold,
+        phase,
+        phase_ci,
+        phase_lag,
+        segments,
+    })
+}
+
+/// Returns the overlapping traded range `(start, end)` of two `EventList`s in whole seconds.
+pub fn common_range(a: &EventList, b: &EventList) -> Option<(i32, i32)> {
+    let start = *a.events.keys().next()?.max(b.events.keys().next()?);
+    let end = *a.events.keys().next_back()?.min(b.events.keys().next_back()?);
+    if end < start {
+        return None;
+    }
+    Some((start, end))
+}
+
+/// Resamples two `EventList`s onto their common grid and estimates their cross spectrum.
+///
+/// The grid runs over the period where both symbols traded, see `common_range`.
+/// For `SeriesKind::Return` the series are one point shorter than the grid.
+///
+/// # Example
+///
+/// ```ignore
+/// let cs = event_list_cross_spectrum(&spy, &ivv, SeriesKind::Return, 1, 512, 256, 0.95)?;
+/// ```
+pub fn event_list_cross_spectrum(a: &EventList, b: &EventList, kind: SeriesKind, interval: i32, window: usize, hop: usize,
+                                 confidence: f32) -> Result<CrossSpectrum, Box<dyn Error>> {
+    let (start, end) = common_range(a, b).ok_or("symbols have no overlapping trading period")?;
+    let x = resample(a, start, end, interval, kind);
+    let y = resample(b, start, end, interval, kind);
+    cross_spectrum(&x, &y, 1.0 / interval as f32, window, hop, confidence)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    fn sine(samples: usize, freq: f32, fs: f32) -> Vec<f32> {
+        (0..samples).map(|i| 100.0 + (2.0 * PI * freq * i as f32 / fs).sin()).collect()
+    }
+
+    #[test]
+    fn test_hann_window() {
+        let w = hann_window(4);
+        assert_eq!(w.len(), 4);
+        assert_relative_eq!(w[0], 0.0);
+        assert_relative_eq!(w[2], 1.0);
+    }
+
+    #[test]
+    fn test_spectrogram_shape() {
+        let inp = sine(1000, 0.125, 1.0);
+        let spec = spectrogram(&inp, 1.0, 64, 32).unwrap();
+        assert_eq!(spec.frequencies.len(), 33);
+        assert_eq!(spec.power.len(), (1000 - 64) / 32 + 1);
+        assert_eq!(spec.times.len(), spec.power.len());
+        assert_relative_eq!(spec.times[0], 32.0);
+        assert_relative_eq!(*spec.frequencies.last().unwrap(), 0.5);
+    }
+
+    #[test]
+    fn test_spectrogram_tracks_frequency_change() {
+        let mut inp = sine(512, 0.0625, 1.0);
+        inp.extend(sine(512, 0.25, 1.0));
+        let spec = spectrogram(&inp, 1.0, 128, 128).unwrap();
+        let peaks = spec.dominant_frequencies();
+        assert_relative_eq!(peaks[0], 0.0625);
+        assert_relative_eq!(*peaks.last().unwrap(), 0.25);
+    }
+
+    #[test]
+    fn test_cross_spectrum_lag() {
+        // y is x delayed by 2 samples so the phase lag at every coherent frequency is 2 seconds
+        let x: Vec<f32> = (0..2048).map(|i| (2.0 * PI * 0.05 * i as f32).sin() + (2.0 * PI * 0.11 * i as f32).cos()).collect();
+        let mut y = vec![0.0_f32; 2];
+        y.extend_from_slice(&x[..2046]);
+        let cs = cross_spectrum(&x, &y, 1.0, 200, 100, 0.95).unwrap();
+        assert_eq!(cs.segments, 19);
+        let k = 10; // 0.05 Hz
+        assert_relative_eq!(cs.frequencies[k], 0.05);
+        assert!(cs.coherence[k] > 0.99);
+        assert!(cs.coherence[k] > cs.coherence_threshold);
+        assert_relative_eq!(cs.phase_lag[k], 2.0, epsilon = 0.05);
+        assert!(cs.coherence_lower[k] <= cs.coherence[k] && cs.coherence[k] <= cs.coherence_upper[k]);
+    }
+
+    #[test]
+    fn test_cross_spectrum_errors() {
+        let x = vec![1.0_f32; 100];
+        assert!(cross_spectrum(&x, &x[..99], 1.0, 10, 5, 0.95).is_err());
+        assert!(cross_spectrum(&x, &x, 1.0, 10, 5, 1.5).is_err());
+        assert!(cross_spectrum(&x, &x, 1.0, 200, 5, 0.95).is_err());
+    }
+
+    #[test]
+    fn test_event_list_cross_spectrum() {
+        let mut a = EventList::new();
+        let mut b = EventList::new();
+        for i in 0..600 {
+            let t = format!("10:{:02}:{:02}.000000100", i / 60, i % 60);
+            let p = 50.0 + (2.0 * PI * i as f32 / 20.0).sin();
+            let _ = a.update(&t, &format!("{:.4}", p), 100);
+            let _ = b.update(&t, &format!("{:.4}", 2.0 * p), 100);
+        }
+        let _ = b.update("09:59:00.000000000", "100.0", 1);
+        assert_eq!(common_range(&a, &b), Some((36_000, 36_599)));
+        let cs = event_list_cross_spectrum(&a, &b, SeriesKind::Price, 1, 100, 50, 0.95).unwrap();
+        assert!(cs.coherence[5] > 0.99);
+        assert!(common_range(&a, &EventList::new()).is_none());
+    }
+
+    #[test]
+    fn test_spectrogram_errors() {
+        let inp = sine(10, 0.1, 1.0);
+        assert!(spectrogram(&inp, 1.0, 64, 1).is_err());
+        assert!(spectrogram(&inp, 1.0, 8, 0).is_err());
+        assert!(spectrogram(&inp, 0.0, 8, 1).is_err());
+    }
+}
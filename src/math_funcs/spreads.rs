@@ -0,0 +1,299 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// Effective spread estimators that only need trade prices.
+// All spreads are proportional (fraction of price) so symbols can be compared directly.
+//
+// Roll (1984): bid-ask bounce gives cov(dp_t, dp_t-1) = -s^2 / 4.
+// Corwin & Schultz (2012): high/low over one bar mixes volatility and spread, over two bars
+//   volatility doubles while the spread does not.
+// Abdi & Ranaldo (2017): close against the mid-range of the current and next bar.
+
+use std::collections::HashMap;
+use std::error::Error;
+use rust_decimal::prelude::ToPrimitive;
+use crate::event_structs::{Bar, EventList};
+use crate::nyse::base_funcs::EventStats;
+
+/// Roll covariance spread estimate from a series of prices.
+///
+/// Works on log prices, so the result is a fraction of price. A non-negative
+/// first-order autocovariance means no bounce is visible and the estimate is 0.
+///
+/// # Arguments
+///
+/// * `prices` - Trade prices (or bar closes) in time order.
+///
+/// # Returns
+///
+/// `2 * sqrt(-cov(dp_t, dp_t-1))`, or an error with fewer than 3 prices.
+pub fn roll_spread(prices: &[f64]) -> Result<f64, Box<dyn Error>> {
+    if prices.len() < 3 {
+        return Err(format!("Roll estimator needs at least 3 prices, got {}", prices.len()).into());
+    }
+    if prices.iter().any(|p| *p <= 0.0) {
+        return Err("Roll estimator needs positive prices".into());
+    }
+    let dp: Vec<f64> = prices.windows(2).map(|w| (w[1] / w[0]).ln()).collect();
+    let mean = dp.iter().sum::<f64>() / dp.len() as f64;
+    let cov = dp.windows(2).map(|w| (w[1] - mean) * (w[0] - mean)).sum::<f64>() / (dp.len() - 1) as f64;
+    Ok(if cov < 0.0 { 2.0 * (-cov).sqrt() } else { 0.0 })
+}
+
+fn bar_hlc(bar: &Bar) -> (f64, f64, f64) {
+    (bar.high.to_f64().unwrap_or(0.0), bar.low.to_f64().unwrap_or(0.0), bar.close.to_f64().unwrap_or(0.0))
+}
+
+/// Corwin-Schultz high-low spread estimate, averaged over consecutive bar pairs.
+///
+/// When the second bar opens away from the first close its high and low are shifted
+/// by the gap, as in the paper's overnight adjustment. Negative pair estimates are set to 0.
+pub fn corwin_schultz_spread(bars: &[Bar]) -> Result<f64, Box<dyn Error>> {
+    if bars.len() < 2 {
+        return Err(format!("Corwin-Schultz needs at least 2 bars, got {}", bars.len()).into());
+    }
+    let k = 3.0 - 2.0 * std::f64::consts::SQRT_2;
+    let mut total = 0.0;
+    for pair in bars.windows(2) {
+        let (h0, l0, c0) = bar_hlc(&pair[0]);
+        let (mut h1, mut l1, _) = bar_hlc(&pair[1]);
+        if l0 <= 0.0 || l1 <= 0.0 {
+            return Err("Corwin-Schultz needs positive prices".into());
+        }
+        if l1 > c0 {
+            h1 -= l1 - c0;
+            l1 = c0;
+        } else if h1 < c0 {
+            l1 += c0 - h1;
+            h1 = c0;
+        }
+        let beta = (h0 / l0).ln().powi(2) + (h1 / l1).ln().powi(2);
+        let gamma = (h0.max(h1) / l0.min(l1)).ln().powi(2);
+        let alpha = ((2.0 * beta).sqrt() - beta.sqrt()) / k - (gamma / k).sqrt();
+        let spread = 2.0 * (alpha.exp() - 1.0) / (1.0 + alpha.exp());
+        total += spread.max(0.0);
+    }
+    Ok(total / (bars.len() - 1) as f64)
+}
+
+/// Abdi-Ranaldo close-high-low spread estimate.
+///
+/// `s^2 = 4 E[(c_t - eta_t)(c_t - eta_t+1)]` with `c` the log close and `eta` the log mid-range;
+/// a negative expectation gives 0.
+pub fn abdi_ranaldo_spread(bars: &[Bar]) -> Result<f64, Box<dyn Error>> {
+    if bars.len() < 2 {
+        return Err(format!("Abdi-Ranaldo needs at least 2 bars, got {}", bars.len()).into());
+    }
+    let mut logs: Vec<(f64, f64)> = Vec::with_capacity(bars.len());
+    for bar in bars {
+        let (h, l, c) = bar_hlc(bar);
+        if l <= 0.0 || c <= 0.0 {
+            return Err("Abdi-Ranaldo needs positive prices".into());
+        }
+        logs.push((c.ln(), (h.ln() + l.ln()) / 2.0));
+    }
+    let s2 = 4.0 * logs.windows(2).map(|w| (w[0].0 - w[0].1) * (w[0].0 - w[1].1)).sum::<f64>()
+        / (logs.len() - 1) as f64;
+    Ok(s2.max(0.0).sqrt())
+}
+
+/// Spread estimators available in a `SpreadReport`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SpreadEstimator {
+    /// Roll on every trade.
+    RollTick,
+    /// Roll on bar closes.
+    RollBar,
+    CorwinSchultz,
+    AbdiRanaldo,
+}
+
+/// Spread estimates for one symbol at one bar interval. Estimators without enough data are NaN.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpreadEstimates {
+    pub interval: i32,
+    pub bars: usize,
+    pub roll_tick: f64,
+    pub roll_bar: f64,
+    pub corwin_schultz: f64,
+    pub abdi_ranaldo: f64,
+}
+
+impl SpreadEstimates {
+    pub fn get(&self, estimator: SpreadEstimator) -> f64 {
+        match estimator {
+            SpreadEstimator::RollTick => self.roll_tick,
+            SpreadEstimator::RollBar => self.roll_bar,
+            SpreadEstimator::CorwinSchultz => self.corwin_schultz,
+            SpreadEstimator::AbdiRanaldo => self.abdi_ranaldo,
+        }
+    }
+}
+
+/// Computes every estimator for an `EventList` using bars of `interval` seconds.
+///
+/// # Example
+///
+/// ```ignore
+/// let est = event_list_spreads(&event_list, 60);
+/// println!("Corwin-Schultz {:.4}%", 100.0 * est.corwin_schultz);
+/// ```
+pub fn event_list_spreads(event_list: &EventList, interval: i32) -> SpreadEstimates {
+    let prices: Vec<f64> = event_list.get_full_time_series().iter()
+        .map(|(_, p, _)| p.to_f64().unwrap_or(0.0)).collect();
+    let bars = event_list.get_bars(interval);
+    let closes: Vec<f64> = bars.iter().map(|b| b.close.to_f64().unwrap_or(0.0)).collect();
+    SpreadEstimates {
+        interval,
+        bars: bars.len(),
+        roll_tick: roll_spread(&prices).unwrap_or(f64::NAN),
+        roll_bar: roll_spread(&closes).unwrap_or(f64::NAN),
+        corwin_schultz: corwin_schultz_spread(&bars).unwrap_or(f64::NAN),
+        abdi_ranaldo: abdi_ranaldo_spread(&bars).unwrap_or(f64::NAN),
+    }
+}
+
+/// Spread estimates for every symbol and interval, with rankings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpreadReport {
+    pub intervals: Vec<i32>,
+    pub estimates: HashMap<String, Vec<SpreadEstimates>>,
+}
+
+impl SpreadReport {
+    /// Builds the report for every symbol in `EventStats` with at least `min_trades` trades.
+    pub fn new(event_stats: &EventStats, intervals: &[i32], min_trades: usize) -> SpreadReport {
+        let mut estimates: HashMap<String, Vec<SpreadEstimates>> = HashMap::new();
+        for (symbol, event_list) in event_stats.symbol_events.iter() {
+            if event_list.get_event_count() < min_trades {
+                continue;
+            }
+            estimates.insert(symbol.clone(), intervals.iter().map(|i| event_list_spreads(event_list, *i)).collect());
+        }
+        SpreadReport { intervals: intervals.to_vec(), estimates }
+    }
+
+    /// Estimates for `symbol` at `interval`, if present.
+    pub fn get(&self, symbol: &str, interval: i32) -> Option<&SpreadEstimates> {
+        self.estimates.get(symbol)?.iter().find(|e| e.interval == interval)
+    }
+
+    fn ranked(&self, interval: i32, estimator: SpreadEstimator) -> Vec<(String, f64)> {
+        let mut ranked: Vec<(String, f64)> = self.estimates.keys()
+            .filter_map(|s| self.get(s, interval).map(|e| (s.clone(), e.get(estimator))))
+            .filter(|(_, v)| v.is_finite())
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ranked
+    }
+
+    /// Symbols with the widest estimated spread first.
+    pub fn get_widest(&self, interval: i32, estimator: SpreadEstimator, max_items: usize) -> Vec<(String, f64)> {
+        let mut ranked = self.ranked(interval, estimator);
+        ranked.truncate(max_items);
+        ranked
+    }
+
+    /// Symbols with the tightest estimated spread first.
+    pub fn get_tightest(&self, interval: i32, estimator: SpreadEstimator, max_items: usize) -> Vec<(String, f64)> {
+        let mut ranked = self.ranked(interval, estimator);
+        ranked.reverse();
+        ranked.truncate(max_items);
+        ranked
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_structs::test_event_list;
+    use approx::assert_relative_eq;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+    use rand_distr::{Distribution, Normal};
+
+    /// Trades bouncing between bid and ask around a random walk mid, one trade per second.
+    fn bounce_list(n: usize, mid: f64, spread: f64, vol: f64, seed: u64) -> EventList {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let step = Normal::new(0.0, vol).unwrap();
+        let mut log_mid = mid.ln();
+        let mut tics = Vec::with_capacity(n);
+        for i in 0..n {
+            log_mid += step.sample(&mut rng);
+            let side = if rng.gen_bool(0.5) { 1.0 } else { -1.0 };
+            let price = (log_mid + side * spread / 2.0).exp();
+            tics.push((34_200 + i as i32, format!("{:.4}", price), 100));
+        }
+        test_event_list(&tics)
+    }
+
+    #[test]
+    fn test_roll() {
+        let prices = [10.0, 10.1, 10.0, 10.1, 10.0, 10.1, 10.0];
+        assert!(roll_spread(&prices).unwrap() > 0.009);
+        assert_eq!(roll_spread(&[10.0, 10.1, 10.3, 10.6, 11.0]).unwrap(), 0.0);
+        assert!(roll_spread(&[10.0, 10.1]).is_err());
+        let el = bounce_list(20_000, 50.0, 0.002, 0.0001, 1);
+        let est = event_list_spreads(&el, 60);
+        assert_relative_eq!(est.roll_tick, 0.002, epsilon = 0.0002);
+    }
+
+    #[test]
+    fn test_high_low_estimators() {
+        let el = bounce_list(30_000, 50.0, 0.002, 0.0001, 2);
+        let est = event_list_spreads(&el, 60);
+        assert_eq!(est.bars, 500);
+        assert!(est.corwin_schultz > 0.0005 && est.corwin_schultz < 0.004);
+        assert!(est.abdi_ranaldo > 0.0005 && est.abdi_ranaldo < 0.004);
+        let wide = event_list_spreads(&bounce_list(30_000, 50.0, 0.006, 0.0001, 3), 60);
+        assert!(wide.corwin_schultz > est.corwin_schultz);
+        assert!(wide.abdi_ranaldo > est.abdi_ranaldo);
+        assert!(corwin_schultz_spread(&[]).is_err());
+        assert!(abdi_ranaldo_spread(&el.get_bars(60)[..1]).is_err());
+    }
+
+    #[test]
+    fn test_report_ranking() {
+        let mut stats = EventStats::new();
+        stats.symbol_events.insert("TIGHT".to_string(), bounce_list(3_000, 50.0, 0.001, 0.0001, 4));
+        stats.symbol_events.insert("WIDE".to_string(), bounce_list(3_000, 20.0, 0.01, 0.0001, 5));
+        stats.symbol_events.insert("THIN".to_string(), bounce_list(10, 20.0, 0.01, 0.0001, 6));
+        let report = SpreadReport::new(&stats, &[30, 60], 100);
+        assert_eq!(report.estimates.len(), 2);
+        assert_eq!(report.get("WIDE", 30).unwrap().bars, 100);
+        assert!(report.get("WIDE", 15).is_none());
+        for estimator in [SpreadEstimator::RollTick, SpreadEstimator::CorwinSchultz, SpreadEstimator::AbdiRanaldo] {
+            assert_eq!(report.get_widest(60, estimator, 1)[0].0, "WIDE");
+            assert_eq!(report.get_tightest(60, estimator, 5)[0].0, "TIGHT");
+        }
+    }
+}
@@ -0,0 +1,291 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// Linear Gaussian state space models
+//   x_t = F x_t-1 + w_t,  w_t ~ N(0, Q)
+//   y_t = H x_t + v_t,    v_t ~ N(0, R)
+// with scalar observations, a Kalman filter, a Rauch-Tung-Striebel smoother, and EM for the
+// local level plus microstructure noise model of trade prices (F = H = 1), in trade time.
+
+use std::error::Error;
+use std::f64::consts::PI;
+use nalgebra::{DMatrix, DVector};
+use rust_decimal::prelude::ToPrimitive;
+use crate::event_structs::EventList;
+
+/// State space model with a scalar observation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateSpace {
+    pub transition: DMatrix<f64>,
+    pub observation: DVector<f64>,
+    pub state_cov: DMatrix<f64>,
+    pub obs_var: f64,
+}
+
+/// Kalman filter output, `predicted_*` being `x_t|t-1` and `filtered_*` being `x_t|t`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KalmanFilter {
+    pub predicted_mean: Vec<DVector<f64>>,
+    pub predicted_cov: Vec<DMatrix<f64>>,
+    pub filtered_mean: Vec<DVector<f64>>,
+    pub filtered_cov: Vec<DMatrix<f64>>,
+    pub log_likelihood: f64,
+}
+
+/// RTS smoother output, `lag_cov[t]` being `Cov(x_t, x_t-1 | all data)` (zero at t = 0).
+#[derive(Debug, Clone, PartialEq)]
+pub struct KalmanSmoother {
+    pub mean: Vec<DVector<f64>>,
+    pub cov: Vec<DMatrix<f64>>,
+    pub lag_cov: Vec<DMatrix<f64>>,
+}
+
+impl StateSpace {
+    /// Local level model, a random walk observed with noise.
+    pub fn local_level(state_var: f64, noise_var: f64) -> StateSpace {
+        StateSpace {
+            transition: DMatrix::identity(1, 1),
+            observation: DVector::from_element(1, 1.0),
+            state_cov: DMatrix::from_element(1, 1, state_var),
+            obs_var: noise_var,
+        }
+    }
+
+    /// Runs the Kalman filter from the prior `N(x0, p0)` for the state before the first observation.
+    pub fn filter(&self, obs: &[f64], x0: &DVector<f64>, p0: &DMatrix<f64>) -> KalmanFilter {
+        let n = obs.len();
+        let mut out = KalmanFilter {
+            predicted_mean: Vec::with_capacity(n),
+            predicted_cov: Vec::with_capacity(n),
+            filtered_mean: Vec::with_capacity(n),
+            filtered_cov: Vec::with_capacity(n),
+            log_likelihood: 0.0,
+        };
+        let h = &self.observation;
+        let mut x = x0.clone();
+        let mut p = p0.clone();
+        for y in obs {
+            let xp = &self.transition * &x;
+            let pp = &self.transition * &p * self.transition.transpose() + &self.state_cov;
+            let ph = &pp * h;
+            let s = h.dot(&ph) + self.obs_var;
+            let innovation = y - h.dot(&xp);
+            let gain = &ph / s;
+            x = &xp + &gain * innovation;
+            p = &pp - &gain * ph.transpose();
+            out.log_likelihood -= 0.5 * ((2.0 * PI).ln() + s.ln() + innovation * innovation / s);
+            out.predicted_mean.push(xp);
+            out.predicted_cov.push(pp);
+            out.filtered_mean.push(x.clone());
+            out.filtered_cov.push(p.clone());
+        }
+        out
+    }
+
+    /// Rauch-Tung-Striebel fixed interval smoother over a filter run.
+    pub fn smooth(&self, filtered: &KalmanFilter) -> Result<KalmanSmoother, Box<dyn Error>> {
+        let n = filtered.filtered_mean.len();
+        let k = self.transition.nrows();
+        if n == 0 {
+            return Ok(KalmanSmoother { mean: Vec::new(), cov: Vec::new(), lag_cov: Vec::new() });
+        }
+        let mut mean = filtered.filtered_mean.clone();
+        let mut cov = filtered.filtered_cov.clone();
+        let mut lag_cov = vec![DMatrix::zeros(k, k); n];
+        for t in (0..n - 1).rev() {
+            let pp_inv = filtered.predicted_cov[t + 1].clone().try_inverse().ok_or("singular predicted covariance")?;
+            let j = &filtered.filtered_cov[t] * self.transition.transpose() * pp_inv;
+            mean[t] = &filtered.filtered_mean[t] + &j * (&mean[t + 1] - &filtered.predicted_mean[t + 1]);
+            cov[t] = &filtered.filtered_cov[t] + &j * (&cov[t + 1] - &filtered.predicted_cov[t + 1]) * j.transpose();
+            lag_cov[t + 1] = &cov[t + 1] * j.transpose();
+        }
+        Ok(KalmanSmoother { mean, cov, lag_cov })
+    }
+}
+
+/// Local level fit by EM.
+///
+/// - `state_var`: Variance of the efficient price innovation per trade.
+/// - `noise_var`: Variance of the microstructure noise.
+/// - `filtered`, `smoothed`: Efficient price estimates, one per trade.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalLevelFit {
+    pub state_var: f64,
+    pub noise_var: f64,
+    pub log_likelihood: f64,
+    pub iterations: usize,
+    pub converged: bool,
+    pub filtered: Vec<f32>,
+    pub smoothed: Vec<f32>,
+}
+
+/// Estimates the local level noise variances by EM (Shumway & Stoffer) and returns the final
+/// filtered and smoothed series.
+///
+/// The first observation is the prior mean with the noise variance as prior variance.
+/// Iteration stops when the log likelihood improves by less than `tol` or after `max_iter` steps.
+///
+/// # Example
+///
+/// ```
+/// # use feed_parser::math_funcs::state_space::fit_local_level;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let prices: Vec<f32> = (0..500).map(|i| 100.0 + 0.01 * (i % 7) as f32 + 0.001 * i as f32).collect();
+/// let fit = fit_local_level(&prices, 200, 1e-6)?;
+/// println!("signal/noise {:.3}", fit.state_var / fit.noise_var);
+/// # Ok(())
+/// # }
+/// ```
+pub fn fit_local_level(obs: &[f32], max_iter: usize, tol: f64) -> Result<LocalLevelFit, Box<dyn Error>> {
+    let n = obs.len();
+    if n < 10 {
+        return Err(format!("local level EM needs at least 10 observations, got {}", n).into());
+    }
+    let y: Vec<f64> = obs.iter().map(|v| *v as f64).collect();
+    let diff_var = y.windows(2).map(|w| (w[1] - w[0]).powi(2)).sum::<f64>() / (n - 1) as f64;
+    if diff_var <= 0.0 {
+        return Err("observations never change".into());
+    }
+    // method of moments start: var(dy) = q + 2r, cov(dy_t, dy_t-1) = -r
+    let d: Vec<f64> = y.windows(2).map(|w| w[1] - w[0]).collect();
+    let lag_cov = d.windows(2).map(|w| w[0] * w[1]).sum::<f64>() / (d.len() - 1) as f64;
+    let mut r = (-lag_cov).clamp(0.05 * diff_var, 0.45 * diff_var);
+    let mut q = (diff_var - 2.0 * r).max(0.05 * diff_var);
+
+    let x0 = DVector::from_element(1, y[0]);
+    let mut previous = f64::NEG_INFINITY;
+    let mut iterations = 0;
+    let mut converged = false;
+    while iterations < max_iter {
+        let model = StateSpace::local_level(q, r);
+        let p0 = DMatrix::from_element(1, 1, r);
+        let filtered = model.filter(&y, &x0, &p0);
+        if filtered.log_likelihood - previous < tol {
+            converged = true;
+            break;
+        }
+        previous = filtered.log_likelihood;
+        let smoothed = model.smooth(&filtered)?;
+        let m: Vec<f64> = smoothed.mean.iter().map(|v| v[0]).collect();
+        let p: Vec<f64> = smoothed.cov.iter().map(|c| c[(0, 0)]).collect();
+        r = y.iter().zip(m.iter()).zip(p.iter()).map(|((yt, mt), pt)| (yt - mt).powi(2) + pt).sum::<f64>() / n as f64;
+        q = (1..n).map(|t| (m[t] - m[t - 1]).powi(2) + p[t] + p[t - 1] - 2.0 * smoothed.lag_cov[t][(0, 0)])
+            .sum::<f64>() / (n - 1) as f64;
+        r = r.max(f64::MIN_POSITIVE);
+        q = q.max(f64::MIN_POSITIVE);
+        iterations += 1;
+    }
+    let model = StateSpace::local_level(q, r);
+    let filtered = model.filter(&y, &x0, &DMatrix::from_element(1, 1, r));
+    let smoothed = model.smooth(&filtered)?;
+    Ok(LocalLevelFit {
+        state_var: q,
+        noise_var: r,
+        log_likelihood: filtered.log_likelihood,
+        iterations,
+        converged,
+        filtered: filtered.filtered_mean.iter().map(|v| v[0] as f32).collect(),
+        smoothed: smoothed.mean.iter().map(|v| v[0] as f32).collect(),
+    })
+}
+
+/// Fits the local level model to every trade price of an `EventList`.
+///
+/// The filtered and smoothed series line up with `get_full_time_series_s`.
+pub fn event_list_efficient_price(event_list: &EventList, max_iter: usize, tol: f64) -> Result<LocalLevelFit, Box<dyn Error>> {
+    let prices: Vec<f32> = event_list.get_full_time_series().iter().map(|(_, p, _)| p.to_f32().unwrap_or(0.0)).collect();
+    fit_local_level(&prices, max_iter, tol)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_structs::test_event_list;
+    use approx::assert_relative_eq;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use rand_distr::{Distribution, Normal};
+
+    fn noisy_walk(n: usize, q: f64, r: f64, seed: u64) -> (Vec<f64>, Vec<f32>) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let step = Normal::new(0.0, q.sqrt()).unwrap();
+        let noise = Normal::new(0.0, r.sqrt()).unwrap();
+        let mut x = 50.0;
+        let mut truth = Vec::with_capacity(n);
+        let mut obs = Vec::with_capacity(n);
+        for _ in 0..n {
+            x += step.sample(&mut rng);
+            truth.push(x);
+            obs.push((x + noise.sample(&mut rng)) as f32);
+        }
+        (truth, obs)
+    }
+
+    #[test]
+    fn test_filter_scalar() {
+        // steady state of the local level filter with q = r = 1: p = (sqrt(5) - 1) / 2
+        let model = StateSpace::local_level(1.0, 1.0);
+        let out = model.filter(&[0.0; 50], &DVector::from_element(1, 0.0), &DMatrix::from_element(1, 1, 10.0));
+        assert_relative_eq!(out.filtered_cov[49][(0, 0)], (5f64.sqrt() - 1.0) / 2.0, epsilon = 1e-10);
+        // one step by hand: prior 0 var 1, predicted var 2, gain 2/3
+        let out = model.filter(&[3.0], &DVector::from_element(1, 0.0), &DMatrix::from_element(1, 1, 1.0));
+        assert_relative_eq!(out.filtered_mean[0][0], 2.0, epsilon = 1e-12);
+        assert_relative_eq!(out.filtered_cov[0][(0, 0)], 2.0 / 3.0, epsilon = 1e-12);
+        assert_relative_eq!(out.log_likelihood, -0.5 * ((2.0 * PI).ln() + 3f64.ln() + 3.0), epsilon = 1e-12);
+        let smoothed = model.smooth(&out).unwrap();
+        assert_eq!(smoothed.mean, out.filtered_mean);
+    }
+
+    #[test]
+    fn test_local_level_em() {
+        let (truth, obs) = noisy_walk(5_000, 0.0001, 0.0004, 1);
+        let fit = fit_local_level(&obs, 500, 1e-8).unwrap();
+        assert_relative_eq!(fit.state_var, 0.0001, max_relative = 0.25);
+        assert_relative_eq!(fit.noise_var, 0.0004, max_relative = 0.1);
+        let rmse = |est: &[f32]| (est.iter().zip(truth.iter()).map(|(e, t)| (*e as f64 - t).powi(2)).sum::<f64>() / truth.len() as f64).sqrt();
+        assert!(rmse(&fit.smoothed) < rmse(&fit.filtered));
+        assert!(rmse(&fit.filtered) < rmse(&obs));
+        assert!(fit_local_level(&[1.0; 20], 10, 1e-6).is_err());
+    }
+
+    #[test]
+    fn test_event_list_efficient_price() {
+        let (_, obs) = noisy_walk(300, 0.0001, 0.0004, 2);
+        let tics: Vec<(i32, String, i32)> = obs.iter().enumerate()
+            .map(|(i, p)| (34_200 + i as i32, format!("{:.4}", p), 100))
+            .collect();
+        let el = test_event_list(&tics);
+        let fit = event_list_efficient_price(&el, 200, 1e-8).unwrap();
+        assert_eq!(fit.smoothed.len(), el.get_full_time_series_s().len());
+        assert!(fit.iterations > 0);
+    }
+}
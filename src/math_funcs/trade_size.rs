@@ -0,0 +1,299 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// Trade size analytics: size histograms on fixed buckets, odd/round/mixed lot shares by count
+// and volume, and odd lot price improvement against the nearest round lot trade. Shares rather
+// than counts make the reports comparable across days.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use rust_decimal::prelude::ToPrimitive;
+use crate::event_structs::{EventList, TradeSide};
+use crate::nyse::base_funcs::{EventStats, ReferenceStats, TradeStats};
+
+/// Lower bucket edges in shares, the last bucket being open ended. Fixed so days line up.
+pub const SIZE_EDGES: [i32; 14] = [1, 2, 5, 10, 25, 50, 100, 101, 200, 500, 1000, 2500, 5000, 10000];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LotClass {
+    /// Fewer shares than a round lot.
+    Odd,
+    /// A whole number of round lots.
+    Round,
+    /// More than a round lot but not a multiple of it.
+    Mixed,
+}
+
+impl LotClass {
+    pub fn classify(volume: i32, lot_size: i32) -> LotClass {
+        let lot = lot_size.max(1);
+        if volume < lot {
+            LotClass::Odd
+        } else if volume % lot == 0 {
+            LotClass::Round
+        } else {
+            LotClass::Mixed
+        }
+    }
+}
+
+/// Trade count and volume per `SIZE_EDGES` bucket.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizeHistogram {
+    pub counts: Vec<i64>,
+    pub volumes: Vec<i64>,
+}
+
+impl SizeHistogram {
+    pub fn new(sizes: &[i32]) -> SizeHistogram {
+        let mut out = SizeHistogram { counts: vec![0; SIZE_EDGES.len()], volumes: vec![0; SIZE_EDGES.len()] };
+        for size in sizes.iter().filter(|s| **s > 0) {
+            let bucket = SIZE_EDGES.partition_point(|e| e <= size) - 1;
+            out.counts[bucket] += 1;
+            out.volumes[bucket] += *size as i64;
+        }
+        out
+    }
+
+    /// Count shares per bucket.
+    pub fn count_shares(&self) -> Vec<f64> {
+        let total: i64 = self.counts.iter().sum();
+        self.counts.iter().map(|c| if total > 0 { *c as f64 / total as f64 } else { 0.0 }).collect()
+    }
+
+    /// Volume shares per bucket.
+    pub fn volume_shares(&self) -> Vec<f64> {
+        let total: i64 = self.volumes.iter().sum();
+        self.volumes.iter().map(|v| if total > 0 { *v as f64 / total as f64 } else { 0.0 }).collect()
+    }
+}
+
+/// Odd, round and mixed lot shares of trades and of volume.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LotShares {
+    pub odd_count: f64,
+    pub round_count: f64,
+    pub mixed_count: f64,
+    pub odd_volume: f64,
+    pub round_volume: f64,
+    pub mixed_volume: f64,
+}
+
+/// Odd lot execution against the nearest round lot trade within the matching window, in basis
+/// points, positive when the odd lot got the better price for its (tick rule) side.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct OddLotImprovement {
+    pub matched: usize,
+    pub mean_bps: f64,
+    pub median_bps: f64,
+    pub improved_share: f64,
+}
+
+/// Trade size report of one symbol, `flagged_odd_lots` being the feed's own `Tc4::OddLotTrade`
+/// count for comparison with the size based one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradeSizeReport {
+    pub trades: usize,
+    pub lot_size: i32,
+    pub mean_size: f64,
+    pub median_size: f64,
+    pub histogram: SizeHistogram,
+    pub shares: LotShares,
+    pub improvement: OddLotImprovement,
+    pub flagged_odd_lots: i32,
+}
+
+/// Odd lot price improvement, `trades` being `(time, price, volume, side)` in time order.
+pub fn odd_lot_improvement(trades: &[(f64, f64, i32, TradeSide)], lot_size: i32, window: f64) -> OddLotImprovement {
+    let round: Vec<(f64, f64)> = trades.iter()
+        .filter(|t| LotClass::classify(t.2, lot_size) != LotClass::Odd)
+        .map(|t| (t.0, t.1))
+        .collect();
+    let mut bps: Vec<f64> = Vec::new();
+    for (time, price, volume, side) in trades.iter() {
+        if LotClass::classify(*volume, lot_size) != LotClass::Odd || *side == TradeSide::Unknown || round.is_empty() {
+            continue;
+        }
+        let idx = round.partition_point(|r| r.0 < *time);
+        let nearest = [idx.checked_sub(1), Some(idx).filter(|i| *i < round.len())].into_iter().flatten()
+            .min_by(|a, b| (round[*a].0 - time).abs().total_cmp(&(round[*b].0 - time).abs()));
+        if let Some(i) = nearest.filter(|i| (round[*i].0 - time).abs() <= window) {
+            // a buy improves by paying less than the round lot, a sell by receiving more
+            bps.push(-(side.sign() as f64) * (price - round[i].1) / round[i].1 * 1e4);
+        }
+    }
+    if bps.is_empty() {
+        return OddLotImprovement::default();
+    }
+    let n = bps.len() as f64;
+    let improved = bps.iter().filter(|b| **b > 0.0).count() as f64;
+    let mean = bps.iter().sum::<f64>() / n;
+    bps.sort_by(|a, b| a.total_cmp(b));
+    let median = if bps.len() % 2 == 1 { bps[bps.len() / 2] } else { (bps[bps.len() / 2 - 1] + bps[bps.len() / 2]) / 2.0 };
+    OddLotImprovement { matched: bps.len(), mean_bps: mean, median_bps: median, improved_share: improved / n }
+}
+
+/// Trade size report of an `EventList`, odd lots being matched to round lots within `window` seconds.
+///
+/// # Example
+///
+/// ```ignore
+/// let report = event_list_trade_sizes(&el, 100, 1.0);
+/// println!("odd lots {:.1}% of trades, improvement {:.2} bps", report.shares.odd_count * 100.0, report.improvement.mean_bps);
+/// ```
+pub fn event_list_trade_sizes(event_list: &EventList, lot_size: i32, window: f64) -> TradeSizeReport {
+    let el = event_list.with_trade_sides();
+    let trades: Vec<(f64, f64, i32, TradeSide)> = el.events.values()
+        .flat_map(|e| e.tics.iter())
+        .map(|t| (t.seconds.to_f64().unwrap_or(0.0), t.price.to_f64().unwrap_or(0.0), t.volume, t.side))
+        .collect();
+    let sizes: Vec<i32> = trades.iter().map(|t| t.2).collect();
+
+    let mut shares = LotShares::default();
+    let total_volume: f64 = sizes.iter().map(|s| *s as f64).sum();
+    let n = sizes.len().max(1) as f64;
+    for size in sizes.iter() {
+        let v = *size as f64 / total_volume.max(1.0);
+        match LotClass::classify(*size, lot_size) {
+            LotClass::Odd => { shares.odd_count += 1.0 / n; shares.odd_volume += v; }
+            LotClass::Round => { shares.round_count += 1.0 / n; shares.round_volume += v; }
+            LotClass::Mixed => { shares.mixed_count += 1.0 / n; shares.mixed_volume += v; }
+        }
+    }
+    let mut sorted = sizes.clone();
+    sorted.sort();
+    let median_size = match sorted.len() {
+        0 => 0.0,
+        len if len % 2 == 1 => sorted[len / 2] as f64,
+        len => (sorted[len / 2 - 1] + sorted[len / 2]) as f64 / 2.0,
+    };
+    TradeSizeReport {
+        trades: sizes.len(),
+        lot_size,
+        mean_size: total_volume / n,
+        median_size,
+        histogram: SizeHistogram::new(&sizes),
+        shares,
+        improvement: odd_lot_improvement(&trades, lot_size, window),
+        flagged_odd_lots: 0,
+    }
+}
+
+/// Reports for every symbol with at least `min_trades` trades, lot sizes from type 3 messages.
+pub fn event_stats_trade_sizes(event_stats: &EventStats, reference: &ReferenceStats, trade_stats: &TradeStats,
+                               window: f64, min_trades: usize) -> HashMap<String, TradeSizeReport> {
+    let mut out = HashMap::new();
+    for (symbol, el) in event_stats.symbol_events.iter() {
+        if el.events.values().map(|e| e.tics.len()).sum::<usize>() < min_trades {
+            continue;
+        }
+        let mut report = event_list_trade_sizes(el, reference.get_lot_size(symbol), window);
+        report.flagged_odd_lots = trade_stats.get_flagged_odd_lots(symbol).0;
+        out.insert(symbol.clone(), report);
+    }
+    out
+}
+
+/// One CSV row per symbol tagged with `day`, so files of several days can be concatenated
+/// (after the first header) and compared.
+pub fn trade_sizes_csv(day: &str, reports: &HashMap<String, TradeSizeReport>) -> String {
+    let mut out = String::from("day,symbol,trades,lot_size,mean_size,median_size,odd_count,round_count,mixed_count,\
+odd_volume,round_volume,mixed_volume,flagged_odd_lots,improvement_matched,improvement_mean_bps,improvement_median_bps,improved_share");
+    for edge in SIZE_EDGES {
+        write!(out, ",count_ge_{}", edge).unwrap();
+    }
+    out.push('\n');
+    let mut symbols: Vec<&String> = reports.keys().collect();
+    symbols.sort();
+    for symbol in symbols {
+        let r = &reports[symbol];
+        let s = &r.shares;
+        let i = &r.improvement;
+        write!(out, "{},{},{},{},{:.3},{:.1},{:.6},{:.6},{:.6},{:.6},{:.6},{:.6},{},{},{:.4},{:.4},{:.6}", day, symbol, r.trades, r.lot_size,
+               r.mean_size, r.median_size, s.odd_count, s.round_count, s.mixed_count, s.odd_volume, s.round_volume, s.mixed_volume,
+               r.flagged_odd_lots, i.matched, i.mean_bps, i.median_bps, i.improved_share).unwrap();
+        for share in r.histogram.count_shares() {
+            write!(out, ",{:.6}", share).unwrap();
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_structs::test_event_list;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_classify_and_histogram() {
+        assert_eq!(LotClass::classify(37, 100), LotClass::Odd);
+        assert_eq!(LotClass::classify(300, 100), LotClass::Round);
+        assert_eq!(LotClass::classify(150, 100), LotClass::Mixed);
+        assert_eq!(LotClass::classify(15, 10), LotClass::Mixed);
+        let h = SizeHistogram::new(&[1, 3, 100, 100, 150, 20000]);
+        assert_eq!(h.counts[0], 1);
+        assert_eq!(h.counts[1], 1);
+        assert_eq!(h.counts[6], 2);
+        assert_eq!(h.counts[7], 1);
+        assert_eq!(h.counts[13], 1);
+        assert_relative_eq!(h.count_shares()[6], 2.0 / 6.0);
+        assert_eq!(h.volumes.iter().sum::<i64>(), 20354);
+    }
+
+    #[test]
+    fn test_event_list_trade_sizes() {
+        // round lots at 10.00 / 10.02, an odd lot buy on an uptick at 10.03 and a sell on a downtick at 10.01
+        let el = test_event_list(&[(34_200, "10.00", 100), (34_201, "10.02", 200), (34_202, "10.03", 10),
+                                   (34_203, "10.02", 300), (34_204, "10.01", 20), (34_205, "10.00", 150)]);
+        let report = event_list_trade_sizes(&el, 100, 1.0);
+        assert_eq!(report.trades, 6);
+        assert_relative_eq!(report.shares.odd_count, 2.0 / 6.0, epsilon = 1e-12);
+        assert_relative_eq!(report.shares.round_count, 3.0 / 6.0, epsilon = 1e-12);
+        assert_relative_eq!(report.shares.mixed_volume, 150.0 / 780.0, epsilon = 1e-12);
+        assert_relative_eq!(report.median_size, 125.0);
+        // buy at 10.03 vs 10.02 is 1 cent worse, sell at 10.01 vs 10.02 is 1 cent worse
+        assert_eq!(report.improvement.matched, 2);
+        assert!(report.improvement.mean_bps < 0.0);
+        assert_relative_eq!(report.improvement.improved_share, 0.0);
+        assert!(el.events.values().all(|e| e.tics.iter().all(|t| t.side == TradeSide::Unknown)));
+
+        let mut stats = EventStats::new();
+        stats.symbol_events.insert("AAA".to_string(), el);
+        let reports = event_stats_trade_sizes(&stats, &ReferenceStats::new(), &TradeStats::new(), 1.0, 5);
+        let csv = trade_sizes_csv("2023-01-03", &reports);
+        assert_eq!(csv.lines().count(), 2);
+        assert!(csv.lines().nth(1).unwrap().starts_with("2023-01-03,AAA,6,100,"));
+        assert_eq!(csv.lines().next().unwrap().split(',').count(), csv.lines().nth(1).unwrap().split(',').count());
+    }
+}
@@ -0,0 +1,250 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+
+// Volume-synchronized probability of informed trading (Easley, Lopez de Prado & O'Hara, 2012).
+// Volume is classified in bulk per time bar from standardized price changes instead of
+// trade by trade, then accumulated into equal volume buckets.
+
+use std::error::Error;
+use rust_decimal::prelude::ToPrimitive;
+use crate::event_structs::EventList;
+use crate::math_funcs::distributions::norm_cdf;
+
+/// One equal volume bucket.
+///
+/// - `end_time`: Start second of the bar that filled the bucket.
+/// - `price`: Close of that bar.
+/// - `buy_volume`, `sell_volume`: Bulk classified volume, summing to the bucket volume.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VolumeBucket {
+    pub end_time: i32,
+    pub price: f32,
+    pub buy_volume: f64,
+    pub sell_volume: f64,
+}
+
+/// Fills equal volume buckets from the `bar_interval` second bars of an `EventList`.
+///
+/// Each bar's volume is split into buy and sell with the bulk volume classification
+/// `buy = V * Phi(dp / sigma_dp)`, `dp` being the log change in bar close. Bars larger than
+/// the room left in a bucket spill into the next ones; a trailing partial bucket is dropped.
+///
+/// # Arguments
+///
+/// * `event_list` - Trades for one symbol.
+/// * `bucket_volume` - Shares per bucket.
+/// * `bar_interval` - Bar length in seconds used for the classification.
+pub fn volume_buckets(event_list: &EventList, bucket_volume: f64, bar_interval: i32) -> Result<Vec<VolumeBucket>, Box<dyn Error>> {
+    if bucket_volume <= 0.0 {
+        return Err("bucket volume must be positive".into());
+    }
+    let bars = event_list.get_bars(bar_interval);
+    if bars.len() < 2 {
+        return Err(format!("need at least 2 bars of {}s, got {}", bar_interval, bars.len()).into());
+    }
+    let mut prev = bars[0].open.to_f64().unwrap_or(0.0);
+    let mut changes: Vec<f64> = Vec::with_capacity(bars.len());
+    for bar in bars.iter() {
+        let close = bar.close.to_f64().unwrap_or(0.0);
+        if close <= 0.0 || prev <= 0.0 {
+            return Err("VPIN needs positive prices".into());
+        }
+        changes.push((close / prev).ln());
+        prev = close;
+    }
+    let mean = changes.iter().sum::<f64>() / changes.len() as f64;
+    let sigma = (changes.iter().map(|c| (c - mean).powi(2)).sum::<f64>() / changes.len() as f64).sqrt();
+    if sigma <= 0.0 {
+        return Err("price never changed, bulk volume classification is undefined".into());
+    }
+
+    let mut buckets: Vec<VolumeBucket> = Vec::new();
+    let mut buy = 0.0;
+    let mut sell = 0.0;
+    for (bar, change) in bars.iter().zip(changes.iter()) {
+        let buy_fraction = norm_cdf(change / sigma);
+        let mut remaining = bar.volume as f64;
+        while remaining > 0.0 {
+            let take = remaining.min(bucket_volume - buy - sell);
+            buy += take * buy_fraction;
+            sell += take * (1.0 - buy_fraction);
+            remaining -= take;
+            if buy + sell >= bucket_volume * (1.0 - 1e-9) {
+                buckets.push(VolumeBucket {
+                    end_time: bar.start,
+                    price: bar.close.to_f32().unwrap_or(0.0),
+                    buy_volume: buy,
+                    sell_volume: sell,
+                });
+                buy = 0.0;
+                sell = 0.0;
+            }
+        }
+    }
+    Ok(buckets)
+}
+
+/// Rolling VPIN, `sum |buy - sell| / (window * bucket volume)` over the last `window` buckets.
+///
+/// Returns one value per bucket from bucket `window - 1` on.
+pub fn rolling_vpin(buckets: &[VolumeBucket], window: usize) -> Vec<f32> {
+    if window == 0 || buckets.len() < window {
+        return Vec::new();
+    }
+    let imbalance: Vec<f64> = buckets.iter().map(|b| (b.buy_volume - b.sell_volume).abs()).collect();
+    let totals: Vec<f64> = buckets.iter().map(|b| b.buy_volume + b.sell_volume).collect();
+    imbalance.windows(window).zip(totals.windows(window))
+        .map(|(i, t)| (i.iter().sum::<f64>() / t.iter().sum::<f64>()) as f32)
+        .collect()
+}
+
+/// Empirical CDF of each value within the series, i.e. the share of values at or below it.
+pub fn empirical_cdf(inp: &[f32]) -> Vec<f32> {
+    let mut sorted = inp.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let n = sorted.len() as f32;
+    inp.iter().map(|v| sorted.partition_point(|s| s <= v) as f32 / n).collect()
+}
+
+/// VPIN series for one symbol.
+///
+/// - `times`, `prices`: End time and close of the last bucket in each window.
+/// - `vpin`: Rolling VPIN.
+/// - `cdf`: Empirical CDF of `vpin` over the session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VpinSeries {
+    pub bucket_volume: f64,
+    pub window: usize,
+    pub times: Vec<i32>,
+    pub prices: Vec<f32>,
+    pub vpin: Vec<f32>,
+    pub cdf: Vec<f32>,
+}
+
+impl VpinSeries {
+    /// `(time, vpin)` for every point whose CDF is at or above `threshold`, e.g. 0.9.
+    pub fn alerts(&self, threshold: f32) -> Vec<(i32, f32)> {
+        self.times.iter().zip(self.vpin.iter()).zip(self.cdf.iter())
+            .filter(|(_, c)| **c >= threshold)
+            .map(|((t, v), _)| (*t, *v))
+            .collect()
+    }
+}
+
+/// VPIN for an `EventList` with the bucket size set to the session volume over `buckets_per_session`.
+///
+/// # Example
+///
+/// ```ignore
+/// // 50 buckets per day, one day rolling window, one minute bars
+/// let vpin = event_list_vpin(&event_list, 50, 50, 60)?;
+/// for (t, v) in vpin.alerts(0.9) { println!("{} {:.3}", s2hhmmss_32(t), v); }
+/// ```
+pub fn event_list_vpin(event_list: &EventList, buckets_per_session: usize, window: usize,
+                       bar_interval: i32) -> Result<VpinSeries, Box<dyn Error>> {
+    if buckets_per_session == 0 {
+        return Err("need at least one bucket per session".into());
+    }
+    let bucket_volume = event_list.get_volume() as f64 / buckets_per_session as f64;
+    let buckets = volume_buckets(event_list, bucket_volume, bar_interval)?;
+    let vpin = rolling_vpin(&buckets, window);
+    if vpin.is_empty() {
+        return Err(format!("{} buckets is less than the window of {}", buckets.len(), window).into());
+    }
+    let tail = &buckets[window - 1..];
+    Ok(VpinSeries {
+        bucket_volume,
+        window,
+        times: tail.iter().map(|b| b.end_time).collect(),
+        prices: tail.iter().map(|b| b.price).collect(),
+        cdf: empirical_cdf(&vpin),
+        vpin,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event_structs::test_event_list;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_volume_buckets() {
+        let el = test_event_list(&[(34200, "10.00", 100), (34201, "10.10", 250), (34202, "10.00", 50), (34203, "10.10", 100)]);
+        let buckets = volume_buckets(&el, 100.0, 1).unwrap();
+        assert_eq!(buckets.len(), 5);
+        for b in buckets.iter() {
+            assert_relative_eq!(b.buy_volume + b.sell_volume, 100.0, epsilon = 1e-9);
+        }
+        // first bar has no change, split evenly
+        assert_relative_eq!(buckets[0].buy_volume, 50.0, epsilon = 1e-4);
+        // an up bar is mostly buys, half up half down nets out
+        assert!(buckets[1].buy_volume > 50.0);
+        assert_eq!(buckets[2].end_time, 34201);
+        assert_relative_eq!(buckets[3].buy_volume, 50.0, epsilon = 1e-4);
+        assert!(volume_buckets(&test_event_list(&[(34200, "10.00", 100), (34201, "10.00", 100)]), 100.0, 1).is_err());
+    }
+
+    #[test]
+    fn test_rolling_vpin_and_cdf() {
+        let bucket = |buy: f64| VolumeBucket { end_time: 0, price: 1.0, buy_volume: buy, sell_volume: 100.0 - buy };
+        let buckets = vec![bucket(50.0), bucket(100.0), bucket(0.0), bucket(50.0)];
+        let vpin = rolling_vpin(&buckets, 2);
+        assert_eq!(vpin.len(), 3);
+        assert_relative_eq!(vpin[0], 0.5);
+        assert_relative_eq!(vpin[1], 1.0);
+        assert_relative_eq!(vpin[2], 0.5);
+        assert!(rolling_vpin(&buckets, 5).is_empty());
+        assert_eq!(empirical_cdf(&vpin), vec![2.0 / 3.0, 1.0, 2.0 / 3.0]);
+    }
+
+    #[test]
+    fn test_event_list_vpin_alerts() {
+        // quiet two way trading followed by a one sided run
+        let mut ticks: Vec<(i32, String, i32)> = Vec::new();
+        for i in 0..200 {
+            let price = if i % 2 == 0 { "10.00".to_string() } else { "10.01".to_string() };
+            ticks.push((34200 + i, price, 100));
+        }
+        for i in 0..50 {
+            ticks.push((34400 + i, format!("{:.2}", 10.02 + 0.01 * i as f64), 100));
+        }
+        let el = test_event_list(&ticks);
+        let vpin = event_list_vpin(&el, 50, 5, 1).unwrap();
+        assert_relative_eq!(vpin.bucket_volume, 500.0);
+        assert_eq!(vpin.vpin.len(), 46);
+        let alerts = vpin.alerts(0.9);
+        assert!(!alerts.is_empty());
+        assert!(alerts.iter().all(|(t, _)| *t >= 34400));
+        assert!(event_list_vpin(&el, 50, 60, 1).is_err());
+    }
+}
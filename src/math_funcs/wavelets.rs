@@ -0,0 +1,358 @@
+/*
+ *
+ *  *
+ *  *
+ *  *
+ *  *
+ *  * MIT License
+ *  * Copyright (c) 2023. Dwight J. Browne
+ *  * dwight[-dot-]browne[-at-]dwightjbrowne[-dot-]com
+ *  *
+ *  *
+ *  * Permission is hereby granted, free of charge, to any person obtaining a copy
+ *  * of this software and associated documentation files (the "Software"), to deal
+ *  * in the Software without restriction, including without limitation the rights
+ *  * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ *  * copies of the Software, and to permit persons to whom the Software is
+ *  * furnished to do so, subject to the following conditions:
+ *  *
+ *  * The above copyright notice and this permission notice shall be included in all
+ *  * copies or substantial portions of the Software.
+ *  *
+ *  * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ *  * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ *  * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ *  * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ *  * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ *  * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  * SOFTWARE.
+ *
+ */
+
+// Discrete, maximal overlap and continuous wavelet transforms.
+// Filter conventions and the pyramid algorithm follow Percival & Walden,
+// "Wavelet Methods for Time Series Analysis", with periodic boundary handling.
+
+use std::error::Error;
+use std::f64::consts::PI;
+use rustfft::{FftPlanner, num_complex::Complex};
+use crate::event_structs::EventList;
+use crate::math_funcs::pre_processing::{resample_event_list, SeriesKind};
+
+/// Orthonormal wavelet families, Daubechies named by number of vanishing moments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Wavelet {
+    Haar,
+    Db2,
+    Db3,
+    Db4,
+}
+
+impl Wavelet {
+    /// Scaling (low-pass) filter, normalised so the coefficients sum to sqrt(2).
+    pub fn scaling_filter(&self) -> Vec<f64> {
+        match self {
+            Wavelet::Haar => vec![std::f64::consts::FRAC_1_SQRT_2, std::f64::consts::FRAC_1_SQRT_2],
+            Wavelet::Db2 => vec![0.48296291314469025, 0.836516303737469, 0.22414386804185735, -0.12940952255092145],
+            Wavelet::Db3 => vec![0.3326705529509569, 0.8068915093133388, 0.4598775021193313, -0.13501102001039084,
+                                 -0.08544127388224149, 0.035226291882100656],
+            Wavelet::Db4 => vec![0.23037781330885523, 0.7148465705525415, 0.6308807679295904, -0.02798376941698385,
+                                 -0.18703481171888114, 0.030841381835986965, 0.032883011666982945, -0.010597401784997278],
+        }
+    }
+
+    /// Wavelet (high-pass) filter, the quadrature mirror of the scaling filter.
+    pub fn wavelet_filter(&self) -> Vec<f64> {
+        let g = self.scaling_filter();
+        let len = g.len();
+        (0..len).map(|l| if l % 2 == 0 { g[len - 1 - l] } else { -g[len - 1 - l] }).collect()
+    }
+}
+
+/// Output of a multi-level wavelet transform.
+///
+/// - `details`: Wavelet coefficients per level, `details[0]` is the finest scale.
+/// - `smooth`: Scaling coefficients at the coarsest level.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WaveletDecomposition {
+    pub details: Vec<Vec<f32>>,
+    pub smooth: Vec<f32>,
+}
+
+/// Discrete wavelet transform by the pyramid algorithm.
+///
+/// The transform is orthonormal, so the energy of the input equals the summed energy of all
+/// detail levels plus the smooth.
+///
+/// # Arguments
+///
+/// * `inp` - The series, its length must be a multiple of `2^levels`.
+/// * `wavelet` - The wavelet family.
+/// * `levels` - Number of levels, 1 or more.
+///
+/// # Returns
+///
+/// * `Ok(WaveletDecomposition)` with `inp.len() / 2^j` coefficients at level `j`.
+/// * `Err` if `levels` is zero or the length is not a multiple of `2^levels`.
+pub fn dwt(inp: &[f32], wavelet: Wavelet, levels: usize) -> Result<WaveletDecomposition, Box<dyn Error>> {
+    if levels == 0 {
+        return Err("at least one level is needed".into());
+    }
+    let block = 1usize.checked_shl(levels as u32).ok_or("too many levels")?;
+    if inp.is_empty() || inp.len() & (block - 1) != 0 {
+        return Err(format!("series length {} is not a multiple of 2^{}", inp.len(), levels).into());
+    }
+    let g = wavelet.scaling_filter();
+    let h = wavelet.wavelet_filter();
+    let mut v: Vec<f64> = inp.iter().map(|x| *x as f64).collect();
+    let mut details = Vec::with_capacity(levels);
+
+    for _ in 0..levels {
+        let n = v.len();
+        let half = n / 2;
+        let mut w_next = vec![0.0; half];
+        let mut v_next = vec![0.0; half];
+        for t in 0..half {
+            for l in 0..g.len() {
+                let idx = (2 * t + 1 + n * g.len() - l) % n;
+                w_next[t] += h[l] * v[idx];
+                v_next[t] += g[l] * v[idx];
+            }
+        }
+        details.push(w_next.iter().map(|x| *x as f32).collect());
+        v = v_next;
+    }
+
+    Ok(WaveletDecomposition { details, smooth: v.iter().map(|x| *x as f32).collect() })
+}
+
+/// Maximal overlap discrete wavelet transform.
+///
+/// Unlike `dwt` there is no downsampling, every level has as many coefficients as the input,
+/// any length works and the result does not depend on where the series starts. Energy is
+/// preserved the same way as for the DWT.
+///
+/// # Returns
+///
+/// * `Ok(WaveletDecomposition)` with `inp.len()` coefficients at every level.
+/// * `Err` if `levels` is zero or the series is empty.
+pub fn modwt(inp: &[f32], wavelet: Wavelet, levels: usize) -> Result<WaveletDecomposition, Box<dyn Error>> {
+    if levels == 0 || inp.is_empty() {
+        return Err("at least one level and a non-empty series are needed".into());
+    }
+    let g: Vec<f64> = wavelet.scaling_filter().iter().map(|x| x / std::f64::consts::SQRT_2).collect();
+    let h: Vec<f64> = wavelet.wavelet_filter().iter().map(|x| x / std::f64::consts::SQRT_2).collect();
+    let n = inp.len();
+    let mut v: Vec<f64> = inp.iter().map(|x| *x as f64).collect();
+    let mut details = Vec::with_capacity(levels);
+
+    for j in 0..levels {
+        let step = (1usize << j) % n;
+        let mut w_next = vec![0.0; n];
+        let mut v_next = vec![0.0; n];
+        for t in 0..n {
+            let mut idx = t;
+            for l in 0..g.len() {
+                w_next[t] += h[l] * v[idx];
+                v_next[t] += g[l] * v[idx];
+                idx = (idx + n - step) % n;
+            }
+        }
+        details.push(w_next.iter().map(|x| *x as f32).collect());
+        v = v_next;
+    }
+
+    Ok(WaveletDecomposition { details, smooth: v.iter().map(|x| *x as f32).collect() })
+}
+
+/// Unbiased MODWT wavelet variance per level.
+///
+/// Coefficients affected by the periodic boundary are dropped, so the estimate at level `j` uses
+/// `N - L_j + 1` coefficients where `L_j = (2^j - 1)(L - 1) + 1` for a filter of length `L`.
+/// Level `j` describes changes over `2^(j-1)` samples. Levels with no usable coefficients are
+/// returned as NaN.
+///
+/// # Example
+///
+/// ```
+/// # use feed_parser::math_funcs::wavelets::{wavelet_variance, Wavelet};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let returns: Vec<f32> = (0..1024).map(|i| ((i * 7_919 % 101) as f32 - 50.0) * 1e-4).collect();
+/// let variance = wavelet_variance(&returns, Wavelet::Db2, 8)?;
+/// # assert_eq!(variance.len(), 8);
+/// # Ok(())
+/// # }
+/// ```
+pub fn wavelet_variance(inp: &[f32], wavelet: Wavelet, levels: usize) -> Result<Vec<f32>, Box<dyn Error>> {
+    let decomposition = modwt(inp, wavelet, levels)?;
+    let filter_len = wavelet.scaling_filter().len();
+    let n = inp.len();
+    Ok(decomposition.details.iter().enumerate().map(|(j, w)| {
+        let boundary = ((1usize << (j + 1)) - 1) * (filter_len - 1);
+        if boundary >= n {
+            return f32::NAN;
+        }
+        let usable = &w[boundary..];
+        (usable.iter().map(|x| (*x as f64).powi(2)).sum::<f64>() / usable.len() as f64) as f32
+    }).collect())
+}
+
+/// Resamples an `EventList` and returns `(scale in seconds, wavelet variance)` for each MODWT level.
+pub fn event_list_wavelet_variance(event_list: &EventList, interval: i32, kind: SeriesKind, wavelet: Wavelet,
+                                   levels: usize) -> Result<Vec<(f32, f32)>, Box<dyn Error>> {
+    let series = resample_event_list(event_list, interval, kind);
+    let variance = wavelet_variance(&series, wavelet, levels)?;
+    Ok(variance.iter().enumerate().map(|(j, v)| (((1usize << j) as i32 * interval) as f32, *v)).collect())
+}
+
+/// Continuous wavelet transform power.
+///
+/// - `scales`: Wavelet scale of each row in seconds.
+/// - `periods`: Equivalent Fourier period of each row in seconds.
+/// - `power`: `|W(s, t)|^2`, indexed `power[scale][time]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scalogram {
+    pub scales: Vec<f32>,
+    pub periods: Vec<f32>,
+    pub power: Vec<Vec<f32>>,
+}
+
+/// Morlet wavelet centre frequency, 6 makes the wavelet approximately admissible.
+pub const MORLET_OMEGA0: f64 = 6.0;
+
+/// Continuous wavelet transform with a Morlet wavelet, computed in the frequency domain.
+///
+/// Follows Torrence & Compo (1998): scales run from `2 dt` upwards in steps of
+/// `1 / scales_per_octave` octaves, for `octaves` octaves. The series is de-meaned and zero padded
+/// to the next power of two, so the first and last points of the coarse scales are affected by
+/// the edges.
+///
+/// # Arguments
+///
+/// * `inp` - The regularly sampled series.
+/// * `dt` - Sample spacing in seconds.
+/// * `scales_per_octave` - Resolution of the scale axis.
+/// * `octaves` - Number of octaves above the smallest scale.
+///
+/// # Returns
+///
+/// * `Ok(Scalogram)` with `scales_per_octave * octaves + 1` rows of `inp.len()` points.
+/// * `Err` if the series has fewer than two points or the parameters are zero.
+pub fn cwt_morlet(inp: &[f32], dt: f32, scales_per_octave: usize, octaves: usize) -> Result<Scalogram, Box<dyn Error>> {
+    if inp.len() < 2 || scales_per_octave == 0 || octaves == 0 || dt <= 0.0 {
+        return Err("need at least two points and positive dt, scales_per_octave and octaves".into());
+    }
+    let n = inp.len();
+    let padded = n.next_power_of_two() * 2;
+    let dt = dt as f64;
+    let mean = inp.iter().map(|x| *x as f64).sum::<f64>() / n as f64;
+
+    let mut planner = FftPlanner::<f64>::new();
+    let fft = planner.plan_fft_forward(padded);
+    let ifft = planner.plan_fft_inverse(padded);
+    let mut spectrum: Vec<Complex<f64>> = inp.iter().map(|x| Complex::new(*x as f64 - mean, 0.0)).collect();
+    spectrum.resize(padded, Complex::new(0.0, 0.0));
+    fft.process(&mut spectrum);
+
+    // angular frequency of each FFT bin
+    let omega: Vec<f64> = (0..padded).map(|k| {
+        let k = if k <= padded / 2 { k as f64 } else { k as f64 - padded as f64 };
+        2.0 * PI * k / (padded as f64 * dt)
+    }).collect();
+
+    let s0 = 2.0 * dt;
+    let rows = scales_per_octave * octaves + 1;
+    let fourier_factor = 4.0 * PI / (MORLET_OMEGA0 + (2.0 + MORLET_OMEGA0 * MORLET_OMEGA0).sqrt());
+    let mut scales = Vec::with_capacity(rows);
+    let mut periods = Vec::with_capacity(rows);
+    let mut power = Vec::with_capacity(rows);
+
+    for j in 0..rows {
+        let s = s0 * 2f64.powf(j as f64 / scales_per_octave as f64);
+        let norm = (2.0 * PI * s / dt).sqrt() * PI.powf(-0.25);
+        let mut buffer: Vec<Complex<f64>> = spectrum.iter().zip(omega.iter()).map(|(x, w)| {
+            if *w > 0.0 {
+                x * norm * (-(s * w - MORLET_OMEGA0).powi(2) / 2.0).exp()
+            } else {
+                Complex::new(0.0, 0.0)
+            }
+        }).collect();
+        ifft.process(&mut buffer);
+        power.push(buffer[..n].iter().map(|c| (c.norm_sqr() / (padded * padded) as f64) as f32).collect());
+        scales.push(s as f32);
+        periods.push((s * fourier_factor) as f32);
+    }
+
+    Ok(Scalogram { scales, periods, power })
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use approx::assert_relative_eq;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    fn energy(x: &[f32]) -> f64 {
+        x.iter().map(|v| (*v as f64).powi(2)).sum()
+    }
+
+    #[test]
+    fn test_filters() {
+        for wavelet in [Wavelet::Haar, Wavelet::Db2, Wavelet::Db3, Wavelet::Db4] {
+            let g = wavelet.scaling_filter();
+            let h = wavelet.wavelet_filter();
+            assert_relative_eq!(g.iter().sum::<f64>(), std::f64::consts::SQRT_2, epsilon = 1e-10);
+            assert_relative_eq!(h.iter().sum::<f64>(), 0.0, epsilon = 1e-10);
+            assert_relative_eq!(g.iter().map(|x| x * x).sum::<f64>(), 1.0, epsilon = 1e-10);
+        }
+    }
+
+    #[test]
+    fn test_dwt_haar_and_energy() {
+        let inp: Vec<f32> = vec![1.0, 3.0, 2.0, 2.0, 5.0, 1.0, 0.0, 4.0];
+        let out = dwt(&inp, Wavelet::Haar, 1).unwrap();
+        let r = std::f32::consts::FRAC_1_SQRT_2;
+        assert_relative_eq!(out.details[0][0], 2.0 * r, epsilon = 1e-6);
+        assert_relative_eq!(out.smooth[0], 4.0 * r, epsilon = 1e-6);
+
+        let rng = &mut StdRng::seed_from_u64(7);
+        let inp: Vec<f32> = (0..256).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        for wavelet in [Wavelet::Haar, Wavelet::Db2, Wavelet::Db4] {
+            let out = dwt(&inp, wavelet, 4).unwrap();
+            assert_eq!(out.details[3].len(), 16);
+            let total: f64 = out.details.iter().map(|d| energy(d)).sum::<f64>() + energy(&out.smooth);
+            assert_relative_eq!(total, energy(&inp), epsilon = 1e-3);
+        }
+        assert!(dwt(&inp[..100], Wavelet::Haar, 3).is_err());
+    }
+
+    #[test]
+    fn test_modwt_energy_and_variance() {
+        let rng = &mut StdRng::seed_from_u64(11);
+        let inp: Vec<f32> = (0..4000).map(|_| rng.gen_range(-1.0_f32..1.0) * 3f32.sqrt()).collect();
+        let out = modwt(&inp, Wavelet::Db2, 3).unwrap();
+        assert_eq!(out.details[2].len(), inp.len());
+        let total: f64 = out.details.iter().map(|d| energy(d)).sum::<f64>() + energy(&out.smooth);
+        assert_relative_eq!(total, energy(&inp), max_relative = 1e-4);
+
+        // white noise with unit variance has wavelet variance 1 / 2^j
+        let variance = wavelet_variance(&inp, Wavelet::Haar, 3).unwrap();
+        assert_relative_eq!(variance[0], 0.5, epsilon = 0.05);
+        assert_relative_eq!(variance[1], 0.25, epsilon = 0.03);
+        assert!(wavelet_variance(&inp[..4], Wavelet::Db4, 3).unwrap()[2].is_nan());
+    }
+
+    #[test]
+    fn test_cwt_finds_period() {
+        let inp: Vec<f32> = (0..1024).map(|i| (2.0 * std::f32::consts::PI * i as f32 / 32.0).sin()).collect();
+        let scalogram = cwt_morlet(&inp, 1.0, 8, 6).unwrap();
+        assert_eq!(scalogram.power.len(), 49);
+        assert_eq!(scalogram.power[0].len(), 1024);
+        let mean_power: Vec<f32> = scalogram.power.iter().map(|row| row[256..768].iter().sum::<f32>()).collect();
+        let best = (0..mean_power.len()).max_by(|a, b| mean_power[*a].partial_cmp(&mean_power[*b]).unwrap()).unwrap();
+        assert_relative_eq!(scalogram.periods[best], 32.0, max_relative = 0.1);
+        assert!(cwt_morlet(&inp[..1], 1.0, 8, 6).is_err());
+    }
+}
@@ -11,7 +11,7 @@
 // 34 *
 
 
-mod mt34;
-mod mt3;
+pub  mod mt34;
+pub  mod mt3;
 pub  mod mt220;
 pub mod base_funcs;
\ No newline at end of file
@@ -34,9 +34,12 @@
 
 use std::collections::HashMap;
 use std::error::Error;
-use crate::nyse::mt220::T220;
+use crate::nyse::mt220::{T220, Tc4};
+use crate::nyse::mt3::T3;
+use crate::nyse::mt34::{HaltCondition, SecurityStatus, T34};
 use crate::time_funcs::time_to_dec;
 use priority_queue::PriorityQueue;
+use rust_decimal::Decimal;
 use crate::event_structs::EventList;
 
 /// Represents the NYSE messages we are currently processing.
@@ -118,6 +121,8 @@ pub struct TradeStats {
     // second and count per second
     symbol_volume: HashMap<String, i32>,
     total_volume: i64,
+    odd_lots: HashMap<String, (i32, i64)>,
+    // trades flagged odd lot by the feed, count and volume
 
 }
 
@@ -128,6 +133,7 @@ impl TradeStats {
             rate: HashMap::new(),
             symbol_volume: HashMap::new(),
             total_volume: 0,
+            odd_lots: HashMap::new(),
         }
     }
 
@@ -144,6 +150,11 @@ impl TradeStats {
         self.total_volume += trade.volume as i64;
         let rate_count = self.rate.entry(second).or_insert(0);
         *rate_count += 1;
+        if trade.trade_cond4 == Tc4::OddLotTrade {
+            let odd = self.odd_lots.entry(trade.symbol.clone()).or_insert((0, 0));
+            odd.0 += 1;
+            odd.1 += trade.volume as i64;
+        }
 
 
         Ok(())
@@ -164,6 +175,11 @@ impl TradeStats {
         self.total_volume
     }
 
+    /// Count and volume of the symbol's trades flagged `Tc4::OddLotTrade`.
+    pub fn get_flagged_odd_lots(&self, symbol: &str) -> (i32, i64) {
+        self.odd_lots.get(symbol).cloned().unwrap_or((0, 0))
+    }
+
     pub fn get_average_rate(&self) -> f32 {
         let count = self.rate.len() as i32;
         if count == 0 {
@@ -253,12 +269,110 @@ impl SymbolStats {
 }
 
 
+/// A trading halt for one symbol, in seconds after midnight. `end` is `None` if it never resumed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HaltPeriod {
+    pub start: f64,
+    pub end: Option<f64>,
+    pub condition: HaltCondition,
+}
+
+impl HaltPeriod {
+    /// True if `time` falls within the halt widened by `tolerance` seconds on each side.
+    pub fn contains(&self, time: f64, tolerance: f64) -> bool {
+        time >= self.start - tolerance && self.end.is_none_or(|end| time <= end + tolerance)
+    }
+}
+
+/// Halt and resume periods per symbol from type 34 security status messages, `malformed` counts
+/// status lines that could not be parsed.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HaltStats {
+    pub halts: HashMap<String, Vec<HaltPeriod>>,
+    pub malformed: i32,
+}
+
+impl HaltStats {
+    pub fn new() -> HaltStats {
+        HaltStats {
+            halts: HashMap::new(),
+            malformed: 0,
+        }
+    }
+
+    /// Opens a period on a halt status and closes the open one on a resume; other statuses are ignored.
+    pub fn add(&mut self, msg: &T34) -> Result<(), Box<dyn Error>> {
+        let time = time_to_dec(&msg.source_time)?;
+        match SecurityStatus::get(&msg.sec_status) {
+            SecurityStatus::Halt => {
+                let periods = self.halts.entry(msg.symbol.clone()).or_default();
+                if periods.last().is_none_or(|p| p.end.is_some()) {
+                    periods.push(HaltPeriod { start: time, end: None, condition: HaltCondition::get(&msg.halt_condition) });
+                }
+            }
+            SecurityStatus::Resume => {
+                if let Some(period) = self.halts.get_mut(&msg.symbol).and_then(|p| p.last_mut()) {
+                    if period.end.is_none() {
+                        period.end = Some(time);
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn get_halts(&self, symbol: &str) -> &[HaltPeriod] {
+        self.halts.get(symbol).map_or(&[], |p| p.as_slice())
+    }
+}
+
+/// Per symbol reference data from type 3 symbol index mapping messages, `malformed` counts
+/// mapping lines that could not be parsed.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ReferenceStats {
+    pub prev_close: HashMap<String, Decimal>,
+    pub lot_size: HashMap<String, i32>,
+    pub malformed: i32,
+}
+
+impl ReferenceStats {
+    pub fn new() -> ReferenceStats {
+        ReferenceStats {
+            prev_close: HashMap::new(),
+            lot_size: HashMap::new(),
+            malformed: 0,
+        }
+    }
+
+    /// Records the previous close and round lot size, blank or zero values (e.g. new listings) are skipped.
+    pub fn add(&mut self, msg: &T3) {
+        if msg.prev_close_price > Decimal::ZERO {
+            self.prev_close.insert(msg.symbol.clone(), msg.prev_close_price);
+        }
+        if msg.lot_size > 0 {
+            self.lot_size.insert(msg.symbol.clone(), msg.lot_size);
+        }
+    }
+
+    pub fn get_prev_close(&self, symbol: &str) -> Option<Decimal> {
+        self.prev_close.get(symbol).copied()
+    }
+
+    /// Round lot size, 100 shares when the feed did not give one.
+    pub fn get_lot_size(&self, symbol: &str) -> i32 {
+        self.lot_size.get(symbol).copied().unwrap_or(100)
+    }
+}
+
 #[derive(Debug)]
 pub struct Stats {
     pub msg_stats: MsgStats,
     pub trade_stats: TradeStats,
     pub symbol_stats: SymbolStats,
     pub event_stats: EventStats,
+    pub halt_stats: HaltStats,
+    pub reference_stats: ReferenceStats,
 }
 
 impl Stats {
@@ -268,6 +382,8 @@ impl Stats {
             trade_stats: TradeStats::new(),
             symbol_stats: SymbolStats::new(),
             event_stats: EventStats::new(),
+            halt_stats: HaltStats::new(),
+            reference_stats: ReferenceStats::new(),
         }
     }
 }
@@ -380,6 +496,8 @@ mod test {
         assert_eq!(stats.get_count_per_symbol("MSFT"), 2500);
         assert_eq!(stats.get_total_volume(), 4500);
         assert_eq!(stats.get_average_rate().round(), 4.0);
+        assert_eq!(stats.get_flagged_odd_lots("MSFT"), (2, 2500));
+        assert_eq!(stats.get_flagged_odd_lots("XYZ"), (0, 0));
     }
 
     #[test]
@@ -430,4 +548,26 @@ mod test {
         stats.update("IBM", 10);
         assert_eq!(stats.get_most_active(10)[0], ("IBM".to_string(), 7));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_halt_stats() {
+        use super::HaltStats;
+        use crate::nyse::mt34::{HaltCondition, T34};
+        let msg = |time: &str, status: &str| T34::new(format!("34,1,{},XYZ,1,{},M", time, status)
+            .split(',').map(|s| s.to_string()).collect()).unwrap();
+        let mut stats = HaltStats::new();
+        stats.add(&msg("09:45:00.000000000", "O")).unwrap();
+        stats.add(&msg("10:00:00.000000000", "4")).unwrap();
+        stats.add(&msg("10:05:00.500000000", "5")).unwrap();
+        stats.add(&msg("11:00:00.000000000", "4")).unwrap();
+        let halts = stats.get_halts("XYZ");
+        assert_eq!(halts.len(), 2);
+        assert_eq!(halts[0].start, 36_000.0);
+        assert_eq!(halts[0].end, Some(36_300.5));
+        assert_eq!(halts[0].condition, HaltCondition::LULDPause);
+        assert!(halts[0].contains(36_310.0, 10.0));
+        assert!(!halts[0].contains(36_320.0, 10.0));
+        assert!(halts[1].end.is_none() && halts[1].contains(50_000.0, 0.0));
+        assert!(stats.get_halts("ABC").is_empty());
+    }
+}
@@ -33,8 +33,10 @@
 // Based on  https://www.nyse.com/publicdocs/nyse/data/TAQ_Pillar_Products_Client_Spec_v2.3i.pdf
 // Symbol Index Mapping Message (Type 3)
 
+use std::error::Error;
+use rust_decimal::prelude::*;
 
-#[allow(dead_code)] //to be used in the future
+#[derive(Debug, Clone, PartialEq)]
 pub struct T3 {
     //symbol Index Mapping Message
     pub msg_type: u8,
@@ -45,7 +47,7 @@ pub struct T3 {
     pub exchange_code: String,
     pub security_type: String,
     pub lot_size: i32,
-    pub prev_close_price: f32,
+    pub prev_close_price: Decimal,
     pub prev_close_volume: i32,
     pub price_resolution: String,
     pub round_lot: String,
@@ -53,6 +55,34 @@ pub struct T3 {
     pub unit_of_trade: i32,
 }
 
+impl T3 {
+    /// Builds a `T3` from the comma separated tokens of a type 3 line, in struct field order.
+    /// Only the message type, sequence number and symbol are required; reference fields such as
+    /// the previous close default to empty/zero when the feed leaves them blank.
+    pub fn new(inp: Vec<String>) -> Result<Self, Box<dyn Error>> {
+        if inp.len() < 3 {
+            return Err(format!("type 3 message has {} fields, expected at least 3", inp.len()).into());
+        }
+        let text = |i: usize| inp.get(i).cloned().unwrap_or_default();
+        Ok(T3 {
+            msg_type: 3,
+            seq_num: inp[1].parse::<i32>()?,
+            symbol: inp[2].clone(),
+            market_id: text(3),
+            system_id: text(4).parse::<i32>().unwrap_or(0),
+            exchange_code: text(5),
+            security_type: text(6),
+            lot_size: text(7).parse::<i32>().unwrap_or(0),
+            prev_close_price: Decimal::from_str(&text(8)).unwrap_or_default(),
+            prev_close_volume: text(9).parse::<i32>().unwrap_or(0),
+            price_resolution: text(10),
+            round_lot: text(11),
+            mpv: text(12).parse::<f32>().unwrap_or(0.0),
+            unit_of_trade: text(13).parse::<i32>().unwrap_or(0),
+        })
+    }
+}
+
 #[allow(dead_code)]    //will be implemented in the future
 #[derive(PartialEq, Debug)]
 pub enum MarketID {
@@ -156,6 +186,22 @@ impl PriceResolution{
 
 #[cfg(test)]
 mod test {
+    #[test]
+    fn t_t3_new() {
+        use super::*;
+        let line = "3,12,IBM,1,0,N,C,100,141.21,3459010,0,Y,0.01,1";
+        let msg = T3::new(line.split(',').map(|s| s.to_string()).collect()).unwrap();
+        assert_eq!(msg.symbol, "IBM");
+        assert_eq!(msg.seq_num, 12);
+        assert_eq!(msg.lot_size, 100);
+        assert_eq!(msg.prev_close_price, Decimal::new(14121, 2));
+        assert_eq!(msg.prev_close_volume, 3459010);
+        assert_eq!(msg.unit_of_trade, 1);
+        let short = T3::new(vec!["3".to_string(), "1".to_string(), "XYZ".to_string()]).unwrap();
+        assert_eq!(short.prev_close_price, Decimal::ZERO);
+        assert!(T3::new(vec!["3".to_string()]).is_err());
+    }
+
     #[test]
     fn test_market_id() {
         use super::*;
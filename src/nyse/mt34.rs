@@ -34,7 +34,9 @@
 // Based on https://www.nyse.com/publicdocs/nyse/data/TAQ_Pillar_Products_Client_Spec_v2.3i.pdf
 
 
-#[allow(dead_code)]    //will be implemented in the future
+use std::error::Error;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct T34 {
     // Security Status Message
     pub msg_type: u8,
@@ -54,6 +56,33 @@ pub struct T34 {
     pub market_state: String,
 }
 
+impl T34 {
+    /// Builds a `T34` from the comma separated tokens of a type 34 line, in struct field order.
+    /// Only the fields up to the halt condition are required; missing or empty trailing fields
+    /// default to empty/zero since feeds leave them blank when they do not apply.
+    pub fn new(inp: Vec<String>) -> Result<Self, Box<dyn Error>> {
+        if inp.len() < 7 {
+            return Err(format!("type 34 message has {} fields, expected at least 7", inp.len()).into());
+        }
+        let text = |i: usize| inp.get(i).cloned().unwrap_or_default();
+        Ok(T34 {
+            msg_type: 34,
+            seq_num: inp[1].parse::<i32>()?,
+            source_time: inp[2].clone(),
+            symbol: inp[3].clone(),
+            symbol_seq_num: inp[4].parse::<i32>()?,
+            sec_status: inp[5].clone(),
+            halt_condition: inp[6].clone(),
+            price_1: text(7).parse::<f32>().unwrap_or(0.0),
+            price_2: text(8).parse::<f32>().unwrap_or(0.0),
+            ssr_t_exid: text(9),
+            ssr_t_vol: text(10).parse::<i32>().unwrap_or(0),
+            time: text(11),
+            market_state: text(12),
+        })
+    }
+}
+
 
 
 #[allow(dead_code)]    //will be implemented in the future
@@ -167,7 +196,7 @@ impl SecurityStatus {
 
 
 #[allow(dead_code)]   //unused for now but part of the spec
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum HaltCondition {
     // • '~' - Security not delayed/halted
     NotDelayed,
@@ -371,6 +400,17 @@ mod test {
         assert_eq!(MarketState::get("X"), MarketState::Closed);
         assert_eq!(MarketState::get("A"), MarketState::ERROR);
     }
-}
-
 
+    #[test]
+    fn t_t34_new() {
+        let line = "34,1822,09:45:12.123456789,XYZ,7,4,M,0,0,~,0,09:45:12.123456789,O";
+        let msg = T34::new(line.split(',').map(|s| s.to_string()).collect()).unwrap();
+        assert_eq!(msg.symbol, "XYZ");
+        assert_eq!(SecurityStatus::get(&msg.sec_status), SecurityStatus::Halt);
+        assert_eq!(HaltCondition::get(&msg.halt_condition), HaltCondition::LULDPause);
+        assert_eq!(msg.market_state, "O");
+        let short = T34::new("34,1,09:30:00.000000000,XYZ,1,5,~".split(',').map(|s| s.to_string()).collect()).unwrap();
+        assert_eq!(short.time, "");
+        assert!(T34::new(vec!["34".to_string(), "1".to_string()]).is_err());
+    }
+}
@@ -39,6 +39,11 @@ const  BILLION: i64 = 1_000_000_000;
 const  HOUR: f64 = 3_600.0;
 const  MINUTE: f64 = 60.0;
 
+/// Start of the NYSE core session, 09:30:00, in seconds after midnight.
+pub const CORE_OPEN_SECS: i32 = 34_200;
+/// End of the NYSE core session, 16:00:00, in seconds after midnight.
+pub const CORE_CLOSE_SECS: i32 = 57_600;
+
 
 
 /// Trait `Hhmmss` provides methods to represent time durations in `HH:MM:SS` and `HH:MM:SS.xxxxxxxxx` formats.